@@ -0,0 +1,83 @@
+//! Compares the number of heap allocations and the time taken by ``par_zstd_lines()`` and ``par_zstd_lines_ref()``.
+//!
+//! Run with ``cargo bench --bench allocations``.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// Number of lines in the generated file.
+const LINES: usize = 2_000_000;
+
+/// Counts every allocation made through the global allocator.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Run `process` once and print how long it took and how many allocations it made.
+fn measure(name: &str, process: impl FnOnce() -> usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let total = process();
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!(
+        "{:<20} {:>8.1?} {:>10} allocations ({:.2} per line, {} bytes of lines)",
+        name,
+        elapsed,
+        allocations,
+        allocations as f64 / LINES as f64,
+        total
+    );
+}
+
+fn main() {
+    let dir = std::env::temp_dir().join("zstd_lines_bench_allocations");
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("dump.jsonl.zst");
+    let content: String = (0..LINES)
+        .map(|i| {
+            format!(
+                "{{\"id\":{},\"activity\":\"raid\",\"completed\":true}}\n",
+                i
+            )
+        })
+        .collect();
+    std::fs::write(&file, zstd::encode_all(content.as_bytes(), 3).unwrap()).unwrap();
+    let files = [file.as_path()];
+
+    measure("par_zstd_lines", || {
+        let total = AtomicUsize::new(0);
+        zstd_lines::par_zstd_lines_from(files, |line: String, _path: &Path| {
+            total.fetch_add(line.len(), Ordering::Relaxed);
+        });
+        total.into_inner()
+    });
+    measure("par_zstd_lines_ref", || {
+        let total = AtomicUsize::new(0);
+        zstd_lines::par_zstd_lines_ref(files, |line: &str, _path: &Path| {
+            total.fetch_add(line.len(), Ordering::Relaxed);
+        });
+        total.into_inner()
+    });
+}
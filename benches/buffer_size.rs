@@ -0,0 +1,84 @@
+//! Compares processing a plain zst file and a tar.zst archive with the default buffers against larger ones
+//! set with ``ZstdLinesConfig::buffer_size()``.
+//!
+//! Run with ``cargo bench --bench buffer_size``.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+use zstd_lines::ZstdLinesConfig;
+
+/// Number of lines in the generated file and archive.
+const LINES: usize = 2_000_000;
+/// Number of entries the lines of the archive are split into.
+const ENTRIES: usize = 200;
+
+/// Run `config` on `file` once and print how long it took.
+fn measure(name: &str, config: ZstdLinesConfig<'_>, file: &Path) {
+    let lines = AtomicU64::new(0);
+    let start = Instant::now();
+    config.run([file], |_line, _path| {
+        lines.fetch_add(1, Ordering::Relaxed);
+    });
+    println!(
+        "{:<26} {:>8.1?} {:>10} lines",
+        name,
+        start.elapsed(),
+        lines.into_inner()
+    );
+}
+
+/// The content of the generated lines `range`.
+fn lines(range: std::ops::Range<usize>) -> String {
+    range
+        .map(|i| format!("{{\"id\":{},\"activity\":\"raid\",\"players\":6}}\n", i))
+        .collect()
+}
+
+fn main() {
+    let dir = std::env::temp_dir().join("zstd_lines_bench_buffer_size");
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let plain = dir.join("dump.jsonl.zst");
+    std::fs::write(
+        &plain,
+        zstd::encode_all(lines(0..LINES).as_bytes(), 3).unwrap(),
+    )
+    .unwrap();
+
+    let mut tar = tar::Builder::new(Vec::new());
+    let per_entry = LINES / ENTRIES;
+    for entry in 0..ENTRIES {
+        let content = lines(entry * per_entry..(entry + 1) * per_entry);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_cksum();
+        tar.append_data(
+            &mut header,
+            format!("part-{}.jsonl", entry),
+            content.as_bytes(),
+        )
+        .unwrap();
+    }
+    let archive: PathBuf = dir.join("dump.tar.zst");
+    std::fs::write(
+        &archive,
+        zstd::encode_all(&tar.into_inner().unwrap()[..], 3).unwrap(),
+    )
+    .unwrap();
+
+    for (kind, file) in [("zst", &plain), ("tar.zst", &archive)] {
+        measure(
+            &format!("{} default buffers", kind),
+            ZstdLinesConfig::new(),
+            file,
+        );
+        for (size, buffer_size) in [("64 KiB", 64 * 1024), ("1 MiB", 1024 * 1024)] {
+            measure(
+                &format!("{} {} buffers", kind, size),
+                ZstdLinesConfig::new().buffer_size(buffer_size),
+                file,
+            );
+        }
+    }
+}
@@ -0,0 +1,52 @@
+//! Compares collecting a result for every line with ``par_zstd_lines_map()`` against pushing into a ``Mutex<Vec<_>>``.
+//!
+//! Run with ``cargo bench --bench collect``.
+
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Number of files in the generated batch.
+const FILES: usize = 16;
+/// Number of lines in every generated file.
+const LINES: usize = 250_000;
+
+/// Run `process` once and print how long it took.
+fn measure(name: &str, process: impl FnOnce() -> Vec<usize>) {
+    let start = Instant::now();
+    let results = process();
+    println!(
+        "{:<20} {:>8.1?} {:>10} results",
+        name,
+        start.elapsed(),
+        results.len()
+    );
+}
+
+fn main() {
+    let dir = std::env::temp_dir().join("zstd_lines_bench_collect");
+    std::fs::create_dir_all(&dir).unwrap();
+    let files: Vec<PathBuf> = (0..FILES)
+        .into_par_iter()
+        .map(|i| {
+            let content: String = (0..LINES)
+                .map(|j| format!("{{\"file\":{},\"id\":{},\"activity\":\"raid\"}}\n", i, j))
+                .collect();
+            let file = dir.join(format!("part-{}.jsonl.zst", i));
+            std::fs::write(&file, zstd::encode_all(content.as_bytes(), 3).unwrap()).unwrap();
+            file
+        })
+        .collect();
+
+    measure("Mutex<Vec<_>>", || {
+        let results = Mutex::new(Vec::new());
+        zstd_lines::par_zstd_lines_from(&files, |line: String, _path: &Path| {
+            results.lock().unwrap().push(line.len());
+        });
+        results.into_inner().unwrap()
+    });
+    measure("par_zstd_lines_map", || {
+        zstd_lines::par_zstd_lines_map(&files, |line: String, _path: &Path| line.len())
+    });
+}
@@ -0,0 +1,48 @@
+//! Compares counting lines with ``par_zstd_count_lines()`` against counting the lines ``par_zstd_lines()`` delivers.
+//!
+//! Run with ``cargo bench --bench count``.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Number of files in the generated batch.
+const FILES: usize = 8;
+/// Number of lines in every generated file.
+const LINES: usize = 500_000;
+
+/// Run `count` once and print how long it took.
+fn measure(name: &str, count: impl FnOnce() -> u64) {
+    let start = Instant::now();
+    let lines = count();
+    println!("{:<22} {:>8.1?} {:>10} lines", name, start.elapsed(), lines);
+}
+
+fn main() {
+    let dir = std::env::temp_dir().join("zstd_lines_bench_count");
+    std::fs::create_dir_all(&dir).unwrap();
+    let files: Vec<PathBuf> = (0..FILES)
+        .map(|file| {
+            let path = dir.join(format!("dump_{}.jsonl.zst", file));
+            let content: String = (0..LINES)
+                .map(|i| format!("{{\"file\":{},\"id\":{},\"activity\":\"raid\"}}\n", file, i))
+                .collect();
+            std::fs::write(&path, zstd::encode_all(content.as_bytes(), 3).unwrap()).unwrap();
+            path
+        })
+        .collect();
+
+    measure("par_zstd_lines", || {
+        let lines = AtomicU64::new(0);
+        zstd_lines::par_zstd_lines_from(&files, |_line: String, _path: &Path| {
+            lines.fetch_add(1, Ordering::Relaxed);
+        });
+        lines.into_inner()
+    });
+    measure("par_zstd_count_lines", || {
+        zstd_lines::par_zstd_count_lines(&files)
+            .into_iter()
+            .map(|(_path, lines)| lines.unwrap())
+            .sum()
+    });
+}
@@ -0,0 +1,442 @@
+//! Walking the entries of a tar archive using the size recorded in each header.
+
+use crate::error::tar_header_error;
+use crate::{read_up_to, TAR_BLOCK_SIZE};
+use std::io::{self, Read};
+
+/// Offset and length of the name field in a tar header.
+const NAME_FIELD: (usize, usize) = (0, 100);
+/// Offset and length of the name prefix field in a POSIX ustar header.
+const PREFIX_FIELD: (usize, usize) = (345, 155);
+/// Magic and version of a POSIX ustar header, old GNU headers use the prefix bytes for other fields.
+const POSIX_MAGIC: (usize, &[u8]) = (257, b"ustar\0");
+/// Offset and length of the size field in a tar header.
+const SIZE_FIELD: (usize, usize) = (124, 12);
+/// Offset and length of the checksum field in a tar header.
+const CHECKSUM_FIELD: (usize, usize) = (148, 8);
+/// Offset of the entry type in a tar header.
+const TYPEFLAG_OFFSET: usize = 156;
+/// Entry types of regular files: the ustar type, the pre-POSIX NUL, contiguous files and GNU sparse files.
+const REGULAR_TYPES: [u8; 4] = [b'0', 0, b'7', GNU_SPARSE];
+/// Old GNU sparse file, whose sparse map is stored in the header and extension blocks following it.
+const GNU_SPARSE: u8 = b'S';
+/// Offset of the flag in an old GNU sparse header marking that an extension block follows.
+const GNU_SPARSE_EXTENDED_OFFSET: usize = 482;
+/// Offset of the same flag in a sparse extension block.
+const GNU_SPARSE_EXTENSION_EXTENDED_OFFSET: usize = 504;
+/// GNU pseudo-entry whose content is the name of the following entry.
+const GNU_LONG_NAME: u8 = b'L';
+/// GNU pseudo-entry whose content is the link target of the following entry.
+const GNU_LONG_LINK: u8 = b'K';
+/// PAX extended header applying to the following entry.
+const PAX_EXTENDED: u8 = b'x';
+/// PAX global header applying to all following entries.
+const PAX_GLOBAL: u8 = b'g';
+/// Upper limit for metadata held in memory, far above any real path length.
+const MAX_METADATA_SIZE: u64 = 1 << 20;
+
+/// The header of a single tar entry.
+#[derive(Debug, Clone)]
+pub(crate) struct TarEntry {
+    /// Name of the entry, taken from a PAX header or a GNU long name entry if there is one.
+    pub(crate) name: Vec<u8>,
+    /// Size of the entry content in bytes, without the padding to the next block.
+    pub(crate) size: u64,
+    /// The type flag of the header.
+    pub(crate) kind: u8,
+}
+
+impl TarEntry {
+    /// Whether this entry is a regular file. Directories, links, devices and FIFOs have no content to split into lines.
+    pub(crate) fn is_regular(&self) -> bool {
+        REGULAR_TYPES.contains(&self.kind)
+    }
+}
+
+/// A reader over decompressed tar content which yields the entries one after another.
+/// After ``next_entry()``, reading returns the content of that entry and nothing beyond it.
+pub(crate) struct TarReader<R> {
+    inner: R,
+    /// Content bytes of the current entry which have not been read yet.
+    remaining: u64,
+    /// Padding bytes after the content of the current entry.
+    padding: u64,
+    /// Offset of the next byte of `inner` within the archive.
+    offset: u64,
+    finished: bool,
+    /// Values from PAX global headers seen so far.
+    global: PaxOverrides,
+}
+
+/// Header values overridden by PAX records.
+#[derive(Debug, Clone, Default)]
+struct PaxOverrides {
+    path: Option<Vec<u8>>,
+    size: Option<u64>,
+    /// The real name of a sparse file, whose path is a placeholder.
+    sparse_name: Option<Vec<u8>>,
+    /// Version of the PAX sparse format, 1.0 stores the sparse map in front of the content.
+    sparse_version: (Option<u64>, Option<u64>),
+}
+
+impl<R: Read> TarReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        TarReader {
+            inner,
+            remaining: 0,
+            padding: 0,
+            offset: 0,
+            finished: false,
+            global: PaxOverrides::default(),
+        }
+    }
+
+    /// Skip the rest of the current entry and parse the next header.
+    /// Metadata entries are consumed and applied to the entry they describe.
+    /// Returns ``None`` once the end of the archive has been reached.
+    pub(crate) fn next_entry(&mut self) -> io::Result<Option<TarEntry>> {
+        let mut long_name = None;
+        let mut extended = PaxOverrides::default();
+        loop {
+            let Some(header) = self.next_header()? else {
+                return Ok(None);
+            };
+            let kind = header[TYPEFLAG_OFFSET];
+            match kind {
+                GNU_LONG_NAME => long_name = Some(self.read_metadata()?),
+                // Link targets are not needed, only the content of regular files is split into lines
+                GNU_LONG_LINK => {
+                    self.read_metadata()?;
+                }
+                PAX_EXTENDED => extended.apply(&self.read_metadata()?)?,
+                PAX_GLOBAL => {
+                    let records = self.read_metadata()?;
+                    self.global.apply(&records)?;
+                }
+                _ => {
+                    if let Some(size) = extended.size.or(self.global.size) {
+                        // Sizes beyond the range of the header field are only stored in PAX records
+                        self.remaining = size;
+                        self.padding = padding(size);
+                    }
+                    // Sparse files are delivered as the data they contain, holes are left out
+                    if kind == GNU_SPARSE {
+                        self.skip_sparse_extensions(&header)?;
+                    }
+                    match extended.sparse_version {
+                        (None, _) | (Some(0), _) => {}
+                        (Some(1), Some(0)) => self.skip_sparse_map()?,
+                        (Some(major), minor) => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!(
+                                    "unsupported GNU sparse format {}.{}",
+                                    major,
+                                    minor.unwrap_or(0)
+                                ),
+                            ))
+                        }
+                    }
+                    let name = extended
+                        .sparse_name
+                        .or(extended.path)
+                        .or(long_name)
+                        .or_else(|| self.global.path.clone())
+                        .unwrap_or_else(|| header_name(&header));
+                    return Ok(Some(TarEntry {
+                        name,
+                        size: self.remaining,
+                        kind,
+                    }));
+                }
+            }
+        }
+    }
+
+    /// Skip the rest of the current entry and read the next header, leaving the reader at its content.
+    fn next_header(&mut self) -> io::Result<Option<[u8; TAR_BLOCK_SIZE]>> {
+        if self.finished {
+            return Ok(None);
+        }
+        // Content of the current entry which has not been read is never searched for headers
+        self.skip(self.remaining + self.padding)?;
+        self.remaining = 0;
+        self.padding = 0;
+
+        let mut header_offset = self.offset;
+        let Some(mut header) = self.read_block()? else {
+            // Archives written without an end marker are accepted as long as they end at an entry boundary
+            self.finished = true;
+            return Ok(None);
+        };
+        if is_zero_block(&header) {
+            // The end of the archive is marked by two zero blocks, a lone zero block is skipped.
+            // Anything after the marker, like padding to a larger blocking factor, is never read
+            header_offset = self.offset;
+            match self.read_block()? {
+                Some(next) if !is_zero_block(&next) => header = next,
+                _ => {
+                    self.finished = true;
+                    return Ok(None);
+                }
+            }
+        }
+        // Without the ustar magic this may still be a header written by an old tar, so only the checksum decides
+        if !checksum_matches(&header) {
+            return Err(tar_header_error(
+                header_offset,
+                "tar header checksum mismatch",
+            ));
+        }
+
+        let size = parse_number(field(&header, SIZE_FIELD))
+            .ok_or_else(|| tar_header_error(header_offset, "invalid entry size in tar header"))?;
+        self.remaining = size;
+        self.padding = padding(size);
+        Ok(Some(header))
+    }
+
+    /// Skip the extension blocks holding the rest of the sparse map of an old GNU sparse entry.
+    fn skip_sparse_extensions(&mut self, header: &[u8]) -> io::Result<()> {
+        let mut extended = header[GNU_SPARSE_EXTENDED_OFFSET] != 0;
+        while extended {
+            let block = self.read_block()?.ok_or_else(truncated)?;
+            extended = block[GNU_SPARSE_EXTENSION_EXTENDED_OFFSET] != 0;
+        }
+        Ok(())
+    }
+
+    /// Skip the sparse map in front of the content of a PAX 1.0 sparse entry.
+    /// The map is a newline separated list of decimal numbers, the number of regions followed by their offsets and sizes,
+    /// padded to the next block.
+    fn skip_sparse_map(&mut self) -> io::Result<()> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid GNU sparse map");
+        let mut block = [0; TAR_BLOCK_SIZE];
+        let mut numbers = 0;
+        let mut expected = None;
+        let mut value: u64 = 0;
+        loop {
+            if self.remaining < TAR_BLOCK_SIZE as u64 {
+                return Err(invalid());
+            }
+            self.read_exact(&mut block)?;
+            for &b in &block {
+                match b {
+                    b'0'..=b'9' => {
+                        value = value
+                            .checked_mul(10)
+                            .and_then(|value| value.checked_add(u64::from(b - b'0')))
+                            .ok_or_else(invalid)?;
+                    }
+                    b'\n' => {
+                        numbers += 1;
+                        let expected = *expected.get_or_insert(
+                            value
+                                .checked_mul(2)
+                                .and_then(|v| v.checked_add(1))
+                                .ok_or_else(invalid)?,
+                        );
+                        if numbers == expected {
+                            return Ok(());
+                        }
+                        value = 0;
+                    }
+                    _ => return Err(invalid()),
+                }
+            }
+        }
+    }
+
+    /// Read a whole block, returning ``None`` at the end of the stream.
+    fn read_block(&mut self) -> io::Result<Option<[u8; TAR_BLOCK_SIZE]>> {
+        let mut block = [0; TAR_BLOCK_SIZE];
+        let bytes_read = read_up_to(&mut self.inner, &mut block)?;
+        self.offset += bytes_read as u64;
+        match bytes_read {
+            0 => Ok(None),
+            TAR_BLOCK_SIZE => Ok(Some(block)),
+            _ => Err(truncated()),
+        }
+    }
+
+    /// Read the content of a metadata entry, without trailing NUL bytes.
+    fn read_metadata(&mut self) -> io::Result<Vec<u8>> {
+        if self.remaining > MAX_METADATA_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "tar metadata entry of {} bytes is too large",
+                    self.remaining
+                ),
+            ));
+        }
+        let mut metadata = Vec::new();
+        self.read_to_end(&mut metadata)?;
+        while metadata.last() == Some(&0) {
+            metadata.pop();
+        }
+        Ok(metadata)
+    }
+
+    /// Discard `len` bytes of the underlying stream.
+    fn skip(&mut self, len: u64) -> io::Result<()> {
+        let skipped = io::copy(&mut (&mut self.inner).take(len), &mut io::sink())?;
+        self.offset += skipped;
+        if skipped < len {
+            return Err(truncated());
+        }
+        Ok(())
+    }
+}
+
+impl PaxOverrides {
+    /// Apply the records of a PAX header, each formatted as ``"<length> <key>=<value>\n"``.
+    /// Records with an empty value remove an earlier value, unknown keys are ignored.
+    fn apply(&mut self, mut records: &[u8]) -> io::Result<()> {
+        while !records.is_empty() {
+            let (key, value, rest) = split_pax_record(records).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "invalid PAX header record")
+            })?;
+            records = rest;
+            let value = (!value.is_empty()).then_some(value);
+            match key {
+                b"path" => self.path = value.map(<[u8]>::to_vec),
+                b"GNU.sparse.name" => self.sparse_name = value.map(<[u8]>::to_vec),
+                b"GNU.sparse.major" => self.sparse_version.0 = value.and_then(parse_decimal),
+                b"GNU.sparse.minor" => self.sparse_version.1 = value.and_then(parse_decimal),
+                b"size" => {
+                    self.size = match value {
+                        Some(value) => Some(parse_decimal(value).ok_or_else(|| {
+                            io::Error::new(io::ErrorKind::InvalidData, "invalid PAX size record")
+                        })?),
+                        None => None,
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split the first PAX record into key, value and the remaining records.
+fn split_pax_record(records: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    let space = records.iter().position(|&b| b == b' ')?;
+    let len = usize::try_from(parse_decimal(&records[..space])?).ok()?;
+    if len <= space + 1 || len > records.len() || records[len - 1] != b'\n' {
+        return None;
+    }
+    let record = &records[space + 1..len - 1];
+    let equals = record.iter().position(|&b| b == b'=')?;
+    Some((&record[..equals], &record[equals + 1..], &records[len..]))
+}
+
+/// Parse an unsigned decimal number.
+fn parse_decimal(digits: &[u8]) -> Option<u64> {
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+impl<R: Read> Read for TarReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf
+            .len()
+            .min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        let bytes_read = self.inner.read(&mut buf[..len])?;
+        if bytes_read == 0 {
+            return Err(truncated());
+        }
+        self.remaining -= bytes_read as u64;
+        self.offset += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+/// Verify the checksum of a tar header, the sum of all header bytes with the checksum field counted as spaces.
+/// Old tar implementations summed signed bytes, so both the unsigned and the signed sum are accepted.
+pub(crate) fn checksum_matches(header: &[u8]) -> bool {
+    let (start, len) = CHECKSUM_FIELD;
+    let Some(expected) = parse_number(field(header, CHECKSUM_FIELD)) else {
+        return false;
+    };
+
+    let field = start..start + len;
+    let (mut unsigned, mut signed) = (0u64, 0i64);
+    for (i, &b) in header.iter().enumerate() {
+        let b = if field.contains(&i) { b' ' } else { b };
+        unsigned += u64::from(b);
+        signed += i64::from(b as i8);
+    }
+    expected == unsigned || i64::try_from(expected) == Ok(signed)
+}
+
+/// The bytes of a header field.
+fn field(header: &[u8], (start, len): (usize, usize)) -> &[u8] {
+    &header[start..start + len]
+}
+
+/// The bytes of a text header field up to the first NUL.
+fn field_text(header: &[u8], location: (usize, usize)) -> &[u8] {
+    let field = field(header, location);
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    &field[..len]
+}
+
+/// The name stored in the header itself, joined with the ustar prefix for long names.
+fn header_name(header: &[u8]) -> Vec<u8> {
+    let name = field_text(header, NAME_FIELD);
+    let (magic_offset, magic) = POSIX_MAGIC;
+    let prefix = if header[magic_offset..].starts_with(magic) {
+        field_text(header, PREFIX_FIELD)
+    } else {
+        &[]
+    };
+    if prefix.is_empty() {
+        return name.to_vec();
+    }
+    [prefix, b"/", name].concat()
+}
+
+/// Check for a block of zeros, which marks the end of the archive.
+pub(crate) fn is_zero_block(block: &[u8]) -> bool {
+    block.iter().all(|&b| b == 0)
+}
+
+/// The error for an archive whose stream ends within an entry or a header.
+fn truncated() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "tar archive is truncated, the stream ended within an entry",
+    )
+}
+
+/// Number of padding bytes after `size` bytes of content, up to the next block boundary.
+fn padding(size: u64) -> u64 {
+    let block_size = TAR_BLOCK_SIZE as u64;
+    (block_size - size % block_size) % block_size
+}
+
+/// Parse a numeric header field, either octal terminated by NUL or space, or GNU base-256 for large values.
+fn parse_number(field: &[u8]) -> Option<u64> {
+    if field.first().is_some_and(|&b| b & 0x80 != 0) {
+        // Base-256, the remaining bits of the first byte and all following bytes are big endian
+        let mut value: u64 = u64::from(field[0] & 0x7F);
+        for &b in &field[1..] {
+            value = value.checked_mul(256)?.checked_add(u64::from(b))?;
+        }
+        return Some(value);
+    }
+
+    let digits: Vec<u8> = field
+        .iter()
+        .copied()
+        .skip_while(|&b| b == b' ')
+        .take_while(|&b| b != 0 && b != b' ')
+        .collect();
+    if digits.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(std::str::from_utf8(&digits).ok()?, 8).ok()
+}
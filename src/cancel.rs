@@ -0,0 +1,142 @@
+//! Cancelling a run from outside, e.g. when a service shuts down, see ``ZstdLinesConfig::cancel_with()``.
+
+use crate::config::ZstdLinesConfig;
+use rayon::prelude::*;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A flag to cancel the runs of every configuration it is passed to, shared by all its clones.
+///
+/// # Example
+/// ```
+/// let token = zstd_lines::CancellationToken::new();
+/// let handle = token.clone();
+/// assert!(!token.is_cancelled());
+/// handle.cancel();
+/// assert!(token.is_cancelled());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a token which is not cancelled.
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// Cancel the runs, files stop after the line they are at and files which were not started yet are skipped.
+    /// Can be called from any thread, e.g. a signal handler thread. A token stays cancelled.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Check whether ``cancel()`` was called on this token or one of its clones.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A run which was cancelled before all files were finished, returned by ``ZstdLinesConfig::run_cancellable()``.
+/// Every list is in the order of the paths.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Cancelled {
+    /// The files which were processed to their end, or failed before the run was cancelled.
+    pub completed: Vec<PathBuf>,
+    /// The files which were stopped after the line they were at, only part of their lines were delivered.
+    pub interrupted: Vec<PathBuf>,
+    /// The files which were skipped, none of their lines were delivered.
+    pub not_started: Vec<PathBuf>,
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "processing was cancelled: {} files completed, {} interrupted, {} not started",
+            self.completed.len(),
+            self.interrupted.len(),
+            self.not_started.len()
+        )
+    }
+}
+
+impl Error for Cancelled {}
+
+/// How far a file got in a run which may be cancelled.
+pub(crate) enum Outcome {
+    Completed,
+    Interrupted,
+    NotStarted,
+}
+
+impl Cancelled {
+    /// Collect the outcomes of the files of a run, ``None`` if all of them were completed.
+    pub(crate) fn from_outcomes(outcomes: Vec<(PathBuf, Outcome)>) -> Option<Self> {
+        let mut cancelled = Cancelled::default();
+        for (path, outcome) in outcomes {
+            match outcome {
+                Outcome::Completed => cancelled.completed.push(path),
+                Outcome::Interrupted => cancelled.interrupted.push(path),
+                Outcome::NotStarted => cancelled.not_started.push(path),
+            }
+        }
+        (!cancelled.interrupted.is_empty() || !cancelled.not_started.is_empty())
+            .then_some(cancelled)
+    }
+}
+
+/// Process each line in zstd compressed files in parallel like ``par_zstd_lines()`` until `token` is cancelled.
+/// Cancelling takes effect within a line for the files being processed, and files which were not started yet
+/// are skipped. See ``ZstdLinesConfig::run_cancellable()`` for the same with other settings.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `token` - The token which cancels the run.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use rayon::ThreadPoolBuilder;
+/// use zstd_lines::CancellationToken;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_cancellable");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let files: Vec<_> = (0..3).map(|i| dir.join(format!("part-{}.zst", i))).collect();
+/// for file in &files {
+///     std::fs::write(file, zstd::encode_all(&b"1\n2\n3\n"[..], 0).unwrap()).unwrap();
+/// }
+///
+/// let token = CancellationToken::new();
+/// let pool = ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+/// let result = pool.install(|| {
+///     zstd_lines::par_zstd_lines_cancellable(&files, &token, |line, path| {
+///         // A shutdown signal arrives in the middle of the second file
+///         if path == files[1] && line == "2" {
+///             token.cancel();
+///         }
+///     })
+/// });
+/// let cancelled = result.unwrap_err();
+/// assert_eq!(cancelled.completed, [files[0].clone()]);
+/// assert_eq!(cancelled.interrupted, [files[1].clone()]);
+/// assert_eq!(cancelled.not_started, [files[2].clone()]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_cancellable<I, F>(
+    paths: I,
+    token: &CancellationToken,
+    line_handler: F,
+) -> Result<(), Cancelled>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    ZstdLinesConfig::new()
+        .cancel_with(token)
+        .run_cancellable(paths, line_handler)
+}
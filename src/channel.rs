@@ -0,0 +1,77 @@
+//! Funnelling lines decoded in parallel to a single consumer through a bounded channel.
+
+use crate::config::DEFAULT_CONFIG;
+use crate::process_file;
+use crate::text::decoded_sync;
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::{self, JoinHandle};
+
+/// Decompress zstd compressed files in parallel on a background thread and send their lines to the returned receiver,
+/// for consumers which are not thread-safe. The channel holds at most `capacity` lines, so workers wait for a slow consumer
+/// instead of buffering everything in memory. A `capacity` of 0 is treated as 1.
+///
+/// Lines of the same file arrive in order, lines of different files are interleaved. The channel is closed once all files
+/// are processed. Dropping the receiver early stops the workers after the line they are at and skips all files which were
+/// not started yet. Join the handle only after the receiver is drained or dropped, otherwise the workers wait forever.
+///
+/// # Arguments
+///
+/// * `paths` - The files to process.
+/// * `capacity` - The maximum number of lines waiting in the channel.
+///
+/// # Example
+/// ```
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_channel");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let content: String = (0..10_000).map(|i| format!("{}\n", i)).collect();
+/// std::fs::write(dir.join("a.zst"), zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+/// std::fs::write(dir.join("b.zst"), zstd::encode_all(&b"x\ny\n"[..], 0).unwrap()).unwrap();
+///
+/// let (handle, lines) = zstd_lines::par_zstd_lines_channel([dir.join("a.zst"), dir.join("b.zst")], 64);
+/// let mut total = 0;
+/// for (path, line) in lines {
+///     if path.ends_with("b.zst") {
+///         assert!(line == "x" || line == "y");
+///     }
+///     total += 1;
+/// }
+/// handle.join().unwrap();
+/// assert_eq!(total, 10_002);
+///
+/// // Stopping early closes the channel and lets the workers finish
+/// let (handle, lines) = zstd_lines::par_zstd_lines_channel([dir.join("a.zst")], 1);
+/// let first: Vec<_> = lines.iter().take(3).map(|(_path, line)| line).collect();
+/// assert_eq!(first, vec!["0", "1", "2"]);
+/// drop(lines);
+/// handle.join().unwrap();
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_channel<I>(
+    paths: I,
+    capacity: usize,
+) -> (JoinHandle<()>, Receiver<(PathBuf, String)>)
+where
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+{
+    let paths: Vec<PathBuf> = paths
+        .into_iter()
+        .map(|path| path.as_ref().to_path_buf())
+        .collect();
+    let (sender, receiver) = mpsc::sync_channel(capacity.max(1));
+    let handle = thread::spawn(move || {
+        let config = DEFAULT_CONFIG.start_run();
+        let line_handler = decoded_sync(&config, |line, context| {
+            // The receiver was dropped, nobody is interested in the remaining lines
+            if sender.send((context.path.to_path_buf(), line)).is_err() {
+                config.stop();
+            }
+        });
+        paths.par_iter().for_each(|path| {
+            process_file(path, &config, &line_handler);
+        });
+    });
+    (handle, receiver)
+}
@@ -0,0 +1,256 @@
+//! Reporting zstd content checksum mismatches with the file and position they occur at.
+
+use crate::error::{decode_error, truncated_error};
+use crate::read_up_to;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use zstd::zstd_safe::{get_error_name, zstd_sys::ZSTD_ErrorCode};
+
+/// Magic number of a zstd frame.
+const ZSTD_MAGIC: u32 = 0xFD2FB528;
+/// Mask for the magic numbers of skippable frames, which differ in the lowest 4 bits.
+const SKIPPABLE_MAGIC_MASK: u32 = 0xFFFFFFF0;
+/// Magic number of skippable frames with the lowest 4 bits cleared.
+const SKIPPABLE_MAGIC: u32 = 0x184D2A50;
+/// Bit of the frame header descriptor which marks a content checksum at the end of the frame.
+const CHECKSUM_FLAG: u8 = 0b0000_0100;
+/// The error code of the zstd library for a frame which does not match its checksum.
+const CHECKSUM_WRONG: ZSTD_ErrorCode = ZSTD_ErrorCode::ZSTD_error_checksum_wrong;
+
+/// The error returned when decompressed content does not match the checksum stored in its zstd frame,
+/// which means the file is corrupted. It is wrapped in an ``io::Error`` of kind ``InvalidData``.
+///
+/// # Example
+/// ```
+/// use std::io::Write;
+/// use zstd_lines::{ChecksumMismatch, ZstdLines};
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_checksum_mismatch");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let mut encoder = zstd::stream::write::Encoder::new(Vec::new(), 3).unwrap();
+/// encoder.include_checksum(true).unwrap();
+/// encoder.write_all(b"first\nsecond\n").unwrap();
+/// let mut data = encoder.finish().unwrap();
+/// // Flip a bit in the checksum
+/// *data.last_mut().unwrap() ^= 1;
+/// let file = dir.join("corrupted.jsonl.zst");
+/// std::fs::write(&file, data).unwrap();
+///
+/// let error = file.zstd_lines(|_line, _path| {}).unwrap_err();
+/// let mismatch = error.get_ref().unwrap().downcast_ref::<ChecksumMismatch>().unwrap();
+/// // The corrupted frame starts at the beginning of the decompressed content
+/// assert_eq!(mismatch.offset(), 0);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct ChecksumMismatch {
+    pub(crate) path: PathBuf,
+    pub(crate) offset: u64,
+}
+
+impl ChecksumMismatch {
+    /// The path of the corrupted file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Offset in the decompressed content up to which everything was delivered before the mismatch was detected.
+    /// The corruption lies in the frame ending around this offset.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "zstd content checksum mismatch in {} near decompressed offset {}",
+            self.path.display(),
+            self.offset
+        )
+    }
+}
+
+impl Error for ChecksumMismatch {}
+
+/// A reader over decompressed zstd content which counts the bytes read and turns checksum errors of the decoder
+/// into a ``ChecksumMismatch``.
+pub(crate) struct ChecksumReader<'a, R> {
+    inner: R,
+    label: &'a Path,
+    /// Offset of the next byte in the decompressed content.
+    offset: u64,
+}
+
+impl<'a, R: Read> ChecksumReader<'a, R> {
+    /// Wrap a zstd decoder whose output starts at `offset` within the decompressed content of `label`.
+    pub(crate) fn new(inner: R, label: &'a Path, offset: u64) -> Self {
+        ChecksumReader {
+            inner,
+            label,
+            offset,
+        }
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner.read(buf) {
+            Ok(bytes_read) => {
+                self.offset += bytes_read as u64;
+                Ok(bytes_read)
+            }
+            Err(e) if is_checksum_wrong(&e) => Err(checksum_error(self.label, self.offset)),
+            // The decoder runs out of input in the middle of a frame
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Err(truncated_error(self.offset)),
+            Err(e) => Err(decode_error(self.offset, e)),
+        }
+    }
+}
+
+/// The error for content of `path` which does not match its checksum after `offset` bytes, reported as
+/// ``ZstdLinesError::ChecksumMismatch``.
+pub(crate) fn checksum_error(path: &Path, offset: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        ChecksumMismatch {
+            path: path.to_owned(),
+            offset,
+        },
+    )
+}
+
+/// Check whether the zstd decoder failed because a frame does not match its checksum.
+/// The decoder keeps nothing of the error code but its name, so the name is compared to the one the library gives the code.
+fn is_checksum_wrong(error: &io::Error) -> bool {
+    error.to_string() == get_error_name(0usize.wrapping_sub(CHECKSUM_WRONG as usize))
+}
+
+/// Check whether the first zstd frame of a file stores a content checksum, i.e. was written with ``zstd --check``.
+/// Skippable frames in front of it are skipped. Files which are not zstd compressed have no checksum.
+/// Checksums are always verified while decoding when they are present.
+///
+/// # Arguments
+///
+/// * `path` - The file to check.
+///
+/// # Example
+/// ```
+/// use std::io::Write;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_has_checksum");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let mut encoder = zstd::stream::write::Encoder::new(Vec::new(), 3).unwrap();
+/// encoder.include_checksum(true).unwrap();
+/// encoder.write_all(b"line\n").unwrap();
+/// std::fs::write(dir.join("checked.zst"), encoder.finish().unwrap()).unwrap();
+///
+/// let mut encoder = zstd::stream::write::Encoder::new(Vec::new(), 3).unwrap();
+/// encoder.include_checksum(false).unwrap();
+/// encoder.write_all(b"line\n").unwrap();
+/// std::fs::write(dir.join("unchecked.zst"), encoder.finish().unwrap()).unwrap();
+///
+/// assert!(zstd_lines::has_content_checksum(dir.join("checked.zst"))?);
+/// assert!(!zstd_lines::has_content_checksum(dir.join("unchecked.zst"))?);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn has_content_checksum(path: impl AsRef<Path>) -> io::Result<bool> {
+    let mut file = File::open(path)?;
+    loop {
+        let mut magic = [0; 4];
+        if read_up_to(&mut file, &mut magic)? < magic.len() {
+            return Ok(false);
+        }
+        let magic = u32::from_le_bytes(magic);
+        if magic & SKIPPABLE_MAGIC_MASK == SKIPPABLE_MAGIC {
+            let mut size = [0; 4];
+            if read_up_to(&mut file, &mut size)? < size.len() {
+                return Ok(false);
+            }
+            let size = u64::from(u32::from_le_bytes(size));
+            io::copy(&mut (&mut file).take(size), &mut io::sink())?;
+        } else if magic == ZSTD_MAGIC {
+            let mut descriptor = [0; 1];
+            let bytes_read = read_up_to(&mut file, &mut descriptor)?;
+            return Ok(bytes_read == 1 && descriptor[0] & CHECKSUM_FLAG != 0);
+        } else {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_dir;
+    use crate::{ZstdLines, ZstdLinesConfig, ZstdLinesError};
+    use std::io::Write;
+
+    #[test]
+    fn checksum_mismatches_are_reported_with_their_offset() {
+        let dir = test_dir("checksum_mismatch");
+        let frame = |content: &[u8]| {
+            let mut encoder = zstd::stream::write::Encoder::new(Vec::new(), 3).unwrap();
+            encoder.include_checksum(true).unwrap();
+            encoder.write_all(content).unwrap();
+            encoder.finish().unwrap()
+        };
+        let mut data = frame(b"first\nsecond\n");
+        data.extend(frame(b"last\n"));
+        *data.last_mut().unwrap() ^= 1;
+        let file = dir.join("corrupted.zst");
+        std::fs::write(&file, data).unwrap();
+
+        let errors = ZstdLinesConfig::new()
+            .report_failures(false)
+            .try_run([&file], |_line, _path| {})
+            .unwrap_err();
+        assert!(matches!(
+            &errors.failures[0],
+            ZstdLinesError::ChecksumMismatch { path, offset: 13 } if *path == file
+        ));
+        assert_eq!(
+            errors.failures[0].to_string(),
+            format!(
+                "Failed to process zst file {}: zstd content checksum mismatch in {} near decompressed offset 13",
+                file.display(),
+                file.display()
+            )
+        );
+    }
+
+    #[test]
+    fn lines_in_front_of_a_corrupted_frame_are_delivered() {
+        let dir = test_dir("checksum_lines");
+        let frame = |content: &[u8]| {
+            let mut encoder = zstd::stream::write::Encoder::new(Vec::new(), 3).unwrap();
+            encoder.include_checksum(true).unwrap();
+            encoder.write_all(content).unwrap();
+            encoder.finish().unwrap()
+        };
+        let content: String = (0..100).map(|i| format!("line {}\n", i)).collect();
+        let mut data = frame(content.as_bytes());
+        data.extend(frame(b"last\n"));
+        *data.last_mut().unwrap() ^= 1;
+        let file = dir.join("corrupted.zst");
+        std::fs::write(&file, data).unwrap();
+        assert!(crate::has_content_checksum(&file).unwrap());
+
+        let mut lines = Vec::new();
+        let error = file.zstd_lines(|line, _path| lines.push(line)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let mismatch = error
+            .get_ref()
+            .unwrap()
+            .downcast_ref::<ChecksumMismatch>()
+            .unwrap();
+        assert_eq!(mismatch.path(), file);
+        assert_eq!(mismatch.offset(), content.len() as u64);
+        assert_eq!(lines.len(), 100);
+    }
+}
@@ -0,0 +1,2309 @@
+//! Configuration for runs which need more than the defaults.
+
+use crate::cancel::{CancellationToken, Cancelled, Outcome};
+use crate::context::LineContext;
+use crate::diagnostics;
+use crate::error::{self, ZstdLinesError};
+use crate::failure::ZstdLinesErrors;
+use crate::iter::ZstdLinesIter;
+use crate::limit::FileSlots;
+use crate::line_errors::{self, LineErrors};
+use crate::ordered;
+use crate::pipe::{self, PipeOptions, PipeReader};
+use crate::progress::{self, Counted, Progress, ProgressCounters, ProgressEvery};
+#[cfg(feature = "indicatif")]
+use crate::progress_bars::{Bars, ProgressBars};
+use crate::report::{self, FileReport};
+use crate::retry::RetryPolicy;
+use crate::sample::Sampling;
+use crate::schedule;
+use crate::text::{decoded_sync, EmptyLines, LineEnding, LongLines, Utf8Policy};
+use crate::timeout::InputFile;
+use crate::{process_file, try_process_file};
+#[cfg(feature = "indicatif")]
+use indicatif::MultiProgress;
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+#[cfg(feature = "indicatif")]
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use zstd::dict::DecoderDictionary;
+
+/// Callback invoked for every zstd skippable frame with its magic number, payload and the source path.
+pub(crate) type SkippableFrameHandler<'a> = dyn Fn(u32, &[u8], &Path) + Sync + Send + 'a;
+
+/// Selects the text encoding of a file from its path.
+#[cfg(feature = "encoding")]
+pub(crate) type EncodingSelector<'a> =
+    dyn Fn(&Path) -> &'static encoding_rs::Encoding + Sync + Send + 'a;
+
+/// Callback invoked for every line which cannot be decoded with its bytes, the source path and the line number.
+pub(crate) type InvalidUtf8Handler<'a> = dyn Fn(&[u8], &Path, u64) + Sync + Send + 'a;
+
+/// Callback invoked when a file is started, on the thread processing it.
+pub(crate) type FileStartHandler<'a> = dyn Fn(&Path) + Sync + Send + 'a;
+
+/// Callback invoked when a file is finished with its statistics, or the error it failed with.
+pub(crate) type FileEndHandler<'a> =
+    dyn Fn(&Path, Result<FileStats, &io::Error>) + Sync + Send + 'a;
+
+/// Callback invoked for every problem which would be reported on stderr otherwise, possibly from several threads at once.
+pub(crate) type ErrorHandler<'a> = dyn Fn(&Path, &ZstdLinesError) + Sync + Send + 'a;
+
+/// Predicate deciding from the entry name and size whether a tar entry is split into lines.
+pub(crate) type EntryFilter<'a> = dyn Fn(&str, u64) -> bool + Sync + Send + 'a;
+
+/// Key deciding the order in which files are started, largest first, see ``ZstdLinesConfig::schedule_by()``.
+pub(crate) type ScheduleKey<'a> = dyn Fn(&Path) -> Option<u64> + Sync + Send + 'a;
+
+/// Callback invoked with the progress of a run, possibly from several threads at once.
+pub(crate) type ProgressHandler<'a> = dyn Fn(&Progress) + Sync + Send + 'a;
+
+/// Configuration for processing zstd compressed files, built with chained methods.
+///
+/// # Example
+/// ```
+/// use zstd_lines::ZstdLinesConfig;
+/// use std::path::PathBuf;
+///
+/// let files = vec![PathBuf::from("file.jsonl.zst")];
+/// ZstdLinesConfig::new()
+///     .on_skippable_frame(|magic, payload, path| {
+///         println!("Skippable frame {:#X} with {} bytes in {:?}", magic, payload.len(), path);
+///     })
+///     .run(&files, |line, path| {
+///         println!("Decompressed line: {} in {:?}", line, path);
+///     });
+/// ```
+pub struct ZstdLinesConfig<'a> {
+    pub(crate) on_skippable_frame: Option<Box<SkippableFrameHandler<'a>>>,
+    pub(crate) window_log_max: Option<u32>,
+    pub(crate) dictionary: Option<DecoderDictionary<'static>>,
+    pub(crate) entry_filter: Option<Box<EntryFilter<'a>>>,
+    pub(crate) strip_bom: bool,
+    pub(crate) line_ending: LineEnding,
+    /// The byte records are separated by, ``\n`` for lines.
+    pub(crate) delimiter: u8,
+    pub(crate) empty_lines: EmptyLines,
+    pub(crate) sampling: Sampling,
+    /// Number of lines at the start of every file or archive entry which are not delivered.
+    pub(crate) skip_lines: u64,
+    /// Number of lines delivered from every file or archive entry after the skipped lines.
+    pub(crate) take_lines: u64,
+    /// Lines longer than this many bytes, without their delimiter, are never held in memory.
+    pub(crate) max_line_len: u64,
+    pub(crate) long_lines: LongLines,
+    pub(crate) line_errors: LineErrors,
+    /// Lines have to be numbered, so files are never split across threads.
+    pub(crate) line_numbers: bool,
+    pub(crate) buffer_size: Option<usize>,
+    #[cfg(feature = "encoding")]
+    pub(crate) encoding: Option<Box<EncodingSelector<'a>>>,
+    pub(crate) replace_malformed: bool,
+    /// Number of lines delivered with replaced malformed sequences.
+    pub(crate) replaced_lines: AtomicU64,
+    pub(crate) on_invalid_utf8: Option<Box<InvalidUtf8Handler<'a>>>,
+    /// Number of lines skipped because they could not be decoded.
+    pub(crate) invalid_lines: AtomicU64,
+    pub(crate) on_file_start: Option<Box<FileStartHandler<'a>>>,
+    pub(crate) on_file_end: Option<Box<FileEndHandler<'a>>>,
+    /// Files which fail are reported on stderr.
+    pub(crate) report_failures: bool,
+    pub(crate) on_error: Option<Box<ErrorHandler<'a>>>,
+    /// The first problem stops all files.
+    pub(crate) strict: bool,
+    pub(crate) retry: Option<RetryPolicy>,
+    pub(crate) thread_pool: Option<&'a ThreadPool>,
+    pub(crate) max_concurrent_files: usize,
+    /// The files being processed with this configuration, at most ``max_concurrent_files``.
+    pub(crate) file_slots: FileSlots,
+    pub(crate) schedule: Option<Box<ScheduleKey<'a>>>,
+    pub(crate) on_progress: Option<(ProgressEvery, Box<ProgressHandler<'a>>)>,
+    pub(crate) cancellation: Option<CancellationToken>,
+    pub(crate) file_timeout: Option<Duration>,
+    pub(crate) pipe_options: Option<PipeOptions>,
+    /// Counted for ``on_progress()`` during the current run.
+    pub(crate) progress: ProgressCounters,
+}
+
+/// What was read from a file which was processed successfully, passed to ``ZstdLinesConfig::on_file_end()``.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FileStats {
+    /// Number of lines read from the file, including lines which were skipped because they could not be decoded,
+    /// but not the lines skipped by ``ZstdLinesConfig::skip_empty_lines()`` or left out of a sample.
+    pub lines: u64,
+    /// Number of bytes in these lines, without their line breaks.
+    pub bytes: u64,
+}
+
+/// Counts the lines of a single file for ``ZstdLinesConfig::on_file_end()``, shared by all threads decoding the file.
+#[derive(Debug, Default)]
+pub(crate) struct FileCounter {
+    lines: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl FileCounter {
+    /// Count a line if anybody is interested in the statistics of its file.
+    pub(crate) fn count(&self, config: &ZstdLinesConfig<'_>, line: &[u8]) {
+        if config.on_file_end.is_some() || cfg!(feature = "tracing") {
+            self.lines.fetch_add(1, Ordering::Relaxed);
+            self.bytes.fetch_add(line.len() as u64, Ordering::Relaxed);
+        }
+        config.progress_line();
+    }
+}
+
+/// A single run of a configuration, with what only holds until the run ends. Runs of the same configuration
+/// at the same time, e.g. from several tasks of a thread pool, never stop each other.
+pub(crate) struct Run<'a> {
+    config: &'a ZstdLinesConfig<'a>,
+    /// Set once all files of the run should stop after their current line.
+    stopped: AtomicBool,
+    /// The problem which stopped all files in strict mode.
+    first_problem: Mutex<Option<ZstdLinesError>>,
+}
+
+impl<'a> Deref for Run<'a> {
+    type Target = ZstdLinesConfig<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.config
+    }
+}
+
+impl Run<'_> {
+    /// Pass a problem to ``on_error()``, or report it on stderr, and stop in strict mode.
+    pub(crate) fn report(&self, error: &ZstdLinesError) {
+        self.notify(error);
+        let line = matches!(
+            error,
+            ZstdLinesError::InvalidLine { .. } | ZstdLinesError::LongLine { .. }
+        );
+        if line {
+            line_errors::line_problem();
+        }
+        if self.strict || (line && self.line_errors == LineErrors::Abort) {
+            if let Some(problem) = error.copy_problem() {
+                self.fail_fast(problem);
+            }
+        }
+    }
+
+    /// Keep the first problem and stop all files.
+    fn fail_fast(&self, problem: ZstdLinesError) {
+        let mut first_problem = self.first_problem.lock().unwrap();
+        if first_problem.is_none() {
+            *first_problem = Some(problem);
+        }
+        self.stop();
+    }
+
+    /// Report a file which failed, unless it was stopped or ``report_failures()`` is turned off.
+    /// In strict mode the failure is kept as the first problem, and the file ends like the files stopped because of it.
+    pub(crate) fn report_failure(&self, path: &Path, result: io::Result<()>) -> io::Result<()> {
+        match result {
+            Err(e) if (self.report_failures || self.strict) && !is_stopped(&e) => {
+                let error = ZstdLinesError::from_io(path, e);
+                if self.report_failures {
+                    self.notify(&error);
+                }
+                if self.strict {
+                    self.fail_fast(error);
+                    return self.check_stopped();
+                }
+                Err(error.into_io())
+            }
+            result => result,
+        }
+    }
+
+    /// Stop all files of this run after the line they are at, files which were not started yet are skipped.
+    pub(crate) fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    /// Check whether processing was stopped, or cancelled with the token of ``cancel_with()``.
+    fn stop_requested(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+            || self
+                .cancellation
+                .as_ref()
+                .is_some_and(CancellationToken::is_cancelled)
+    }
+
+    /// Fail with an error which is never reported once processing was stopped, so the current file is abandoned.
+    pub(crate) fn check_stopped(&self) -> io::Result<()> {
+        if self.stop_requested() {
+            return Err(io::Error::other(Stopped { started: true }));
+        }
+        Ok(())
+    }
+
+    /// Like ``check_stopped()``, for a file which was not started yet, which is recognized by ``is_skipped()``.
+    pub(crate) fn check_start(&self) -> io::Result<()> {
+        if self.stop_requested() {
+            return Err(io::Error::other(Stopped { started: false }));
+        }
+        Ok(())
+    }
+
+    /// Take the problem which stopped this run in strict mode.
+    pub(crate) fn take_first_problem(&self) -> Option<ZstdLinesError> {
+        self.first_problem.lock().unwrap().take()
+    }
+}
+
+/// The error a file which was stopped early is abandoned with, it is never reported.
+#[derive(Debug)]
+struct Stopped {
+    /// The file was started before processing was stopped, so part of its lines may have been delivered.
+    started: bool,
+}
+
+impl fmt::Display for Stopped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("processing was stopped")
+    }
+}
+
+impl Error for Stopped {}
+
+/// Check whether an error only means that processing was stopped early.
+pub(crate) fn is_stopped(error: &io::Error) -> bool {
+    error.get_ref().is_some_and(|e| e.is::<Stopped>())
+}
+
+/// Check whether an error means that a file was skipped because processing was stopped before it was started.
+pub(crate) fn is_skipped(error: &io::Error) -> bool {
+    error
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<Stopped>())
+        .is_some_and(|stopped| !stopped.started)
+}
+
+/// Lines longer than 256 MiB are not held in memory unless ``ZstdLinesConfig::max_line_len()`` allows it.
+const DEFAULT_MAX_LINE_LEN: u64 = 256 * 1024 * 1024;
+/// Size of the buffer decompressed content is split into lines from, unless ``buffer_size()`` is set.
+const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+/// How often ``ZstdLinesConfig::progress_bars()`` updates the bars.
+#[cfg(feature = "indicatif")]
+const PROGRESS_BAR_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The configuration used by all functions which do not take a ``ZstdLinesConfig``.
+pub(crate) static DEFAULT_CONFIG: ZstdLinesConfig<'static> = ZstdLinesConfig::new();
+
+/// The default configuration for functions which pass line numbers to the line handler.
+pub(crate) static NUMBERED_CONFIG: ZstdLinesConfig<'static> =
+    ZstdLinesConfig::new().with_line_numbers();
+
+impl<'a> ZstdLinesConfig<'a> {
+    /// Create a configuration with default settings.
+    pub const fn new() -> Self {
+        ZstdLinesConfig {
+            on_skippable_frame: None,
+            window_log_max: None,
+            dictionary: None,
+            entry_filter: None,
+            strip_bom: true,
+            line_ending: LineEnding::StripCr,
+            delimiter: b'\n',
+            empty_lines: EmptyLines::Keep,
+            sampling: Sampling::All,
+            skip_lines: 0,
+            take_lines: u64::MAX,
+            max_line_len: DEFAULT_MAX_LINE_LEN,
+            long_lines: LongLines::Skip,
+            line_errors: LineErrors::ContinueFile {
+                max_consecutive: u64::MAX,
+            },
+            line_numbers: false,
+            buffer_size: None,
+            #[cfg(feature = "encoding")]
+            encoding: None,
+            replace_malformed: false,
+            replaced_lines: AtomicU64::new(0),
+            on_invalid_utf8: None,
+            invalid_lines: AtomicU64::new(0),
+            on_file_start: None,
+            on_file_end: None,
+            report_failures: true,
+            on_error: None,
+            strict: false,
+            retry: None,
+            thread_pool: None,
+            max_concurrent_files: usize::MAX,
+            file_slots: FileSlots::new(),
+            schedule: None,
+            on_progress: None,
+            cancellation: None,
+            file_timeout: None,
+            pipe_options: None,
+            progress: ProgressCounters::new(),
+        }
+    }
+
+    /// Number the lines of every file, which rules out decoding a single file on several threads.
+    /// Lines of files in the zstd seekable format have the number 0 otherwise, see ``run_with_ctx()``.
+    pub const fn with_line_numbers(mut self) -> Self {
+        self.line_numbers = true;
+        self
+    }
+
+    /// Invoke a callback for every zstd skippable frame, e.g. to read metadata embedded between data frames.
+    /// The callback receives the frame's magic number (0x184D2A50 to 0x184D2A5F), its payload and the path of the file.
+    /// Lines from the data frames are delivered as usual.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_skippable");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// let mut data = zstd::encode_all(&b"first\n"[..], 0).unwrap();
+    /// data.extend([0x5E, 0x2A, 0x4D, 0x18, 9, 0, 0, 0]);
+    /// data.extend(br#"{"id":1}"#);
+    /// data.push(b'\n');
+    /// data.extend(zstd::encode_all(&b"second\n"[..], 0).unwrap());
+    /// std::fs::write(dir.join("dump.jsonl.zst"), data).unwrap();
+    ///
+    /// let frames = Mutex::new(Vec::new());
+    /// let lines = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new()
+    ///     .on_skippable_frame(|magic, payload, _path| {
+    ///         frames.lock().unwrap().push((magic, payload.to_vec()));
+    ///     })
+    ///     .run(vec![dir.join("dump.jsonl.zst")], |line, _path| {
+    ///         lines.lock().unwrap().push(line);
+    ///     });
+    /// assert_eq!(frames.into_inner().unwrap(), vec![(0x184D2A5E, b"{\"id\":1}\n".to_vec())]);
+    /// assert_eq!(lines.into_inner().unwrap(), vec!["first", "second"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn on_skippable_frame<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(u32, &[u8], &Path) + Sync + Send + 'a,
+    {
+        self.on_skippable_frame = Some(Box::new(handler));
+        self
+    }
+
+    /// Limit the zstd window size to ``2^window_log_max`` bytes, e.g. 31 for archives compressed with ``zstd --long=31``.
+    /// Without a limit, every frame of a file may declare a window of up to ``2^31`` bytes,
+    /// so setting a lower limit caps the memory a single file can make the decoder allocate.
+    /// Files needing a larger window fail with "Frame requires too much memory for decoding".
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::io::Write;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_window_log");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// // The equivalent of zstd --long=28
+    /// let mut encoder = zstd::stream::write::Encoder::new(Vec::new(), 3).unwrap();
+    /// encoder.long_distance_matching(true).unwrap();
+    /// encoder.window_log(28).unwrap();
+    /// encoder.write_all(b"first line\nsecond line\n").unwrap();
+    /// std::fs::write(dir.join("long.jsonl.zst"), encoder.finish().unwrap()).unwrap();
+    ///
+    /// // A lower limit rejects the file
+    /// let lines = Mutex::new(Vec::<String>::new());
+    /// ZstdLinesConfig::new()
+    ///     .window_log_max(27)
+    ///     .on_error(|_path, _error| {})
+    ///     .run([dir.join("long.jsonl.zst")], |line, _path| lines.lock().unwrap().push(line));
+    /// assert!(lines.into_inner().unwrap().is_empty());
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn window_log_max(mut self, window_log_max: u32) -> Self {
+        self.window_log_max = Some(window_log_max);
+        self
+    }
+
+    /// Decompress zstd frames with a dictionary, e.g. one trained with ``zstd --train``.
+    /// The dictionary is prepared once and shared by all files. Files compressed without a dictionary still decode,
+    /// while files compressed with a different dictionary fail and are reported with their path.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_dictionary");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// let samples: Vec<Vec<u8>> = (0..1000)
+    ///     .map(|i| format!("{{\"user\":{},\"name\":\"user{}\",\"score\":{}}}\n", i, i * 31 % 977, i % 101).into_bytes())
+    ///     .collect();
+    /// let dictionary = zstd::dict::from_samples(&samples, 4096).unwrap();
+    /// let mut compressor = zstd::bulk::Compressor::with_dictionary(3, &dictionary).unwrap();
+    /// let compressed = compressor.compress(b"{\"user\":1,\"name\":\"user1\",\"score\":1}\n").unwrap();
+    /// std::fs::write(dir.join("users.jsonl.zst"), compressed).unwrap();
+    ///
+    /// let lines = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new()
+    ///     .dictionary(&dictionary)
+    ///     .run([dir.join("users.jsonl.zst")], |line, _path| lines.lock().unwrap().push(line));
+    /// assert_eq!(lines.into_inner().unwrap(), vec![r#"{"user":1,"name":"user1","score":1}"#]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn dictionary(mut self, dictionary: &[u8]) -> Self {
+        self.dictionary = Some(DecoderDictionary::copy(dictionary));
+        self
+    }
+
+    /// Only split the tar entries and zip members into lines for which `filter` returns true, given the entry name and its size in bytes.
+    /// Other entries are skipped without looking at their content.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_entry_filter");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// let mut tar = tar::Builder::new(Vec::new());
+    /// let entries = [
+    ///     ("a.jsonl", &b"{\"a\":1}\n"[..]),
+    ///     ("logo.png", &b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"[..]),
+    ///     ("README", &b"Exported data\n"[..]),
+    ///     ("b.jsonl", &b"{\"b\":2}\n"[..]),
+    /// ];
+    /// for (name, content) in entries {
+    ///     let mut header = tar::Header::new_ustar();
+    ///     header.set_size(content.len() as u64);
+    ///     tar.append_data(&mut header, name, content).unwrap();
+    /// }
+    /// std::fs::write(dir.join("dump.tar.zst"), zstd::encode_all(&tar.into_inner().unwrap()[..], 0).unwrap()).unwrap();
+    ///
+    /// let lines = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new()
+    ///     .entry_filter(|name, _size| name.ends_with(".jsonl"))
+    ///     .run([dir.join("dump.tar.zst")], |line, _path| lines.lock().unwrap().push(line));
+    /// assert_eq!(lines.into_inner().unwrap(), vec![r#"{"a":1}"#, r#"{"b":2}"#]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn entry_filter<F>(mut self, filter: F) -> Self
+    where
+        F: Fn(&str, u64) -> bool + Sync + Send + 'a,
+    {
+        self.entry_filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Remove a UTF-8 byte order mark from the first line of every file and of every tar entry, enabled by default.
+    /// Only a mark at the very start is removed, the same bytes anywhere else are part of the line.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_bom");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// let content = "\u{feff}{\"a\":1}\n\u{feff}{\"a\":2}\n";
+    /// std::fs::write(dir.join("bom.jsonl.zst"), zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+    ///
+    /// let lines = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new().run([dir.join("bom.jsonl.zst")], |line, _path| lines.lock().unwrap().push(line));
+    /// assert_eq!(lines.into_inner().unwrap(), vec!["{\"a\":1}", "\u{feff}{\"a\":2}"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn strip_bom(mut self, strip_bom: bool) -> Self {
+        self.strip_bom = strip_bom;
+        self
+    }
+
+    /// Choose what is removed from the end of every line besides the ``\n``, by default a single ``\r``.
+    /// The same policy applies to plain files, tar entries and zip members, so the same content always yields the same lines.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::{LineEnding, ZstdLinesConfig};
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_line_ending");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("mixed.zst"), zstd::encode_all(&b"a\r\nb \nc\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let collect = |line_ending| {
+    ///     let lines = Mutex::new(Vec::new());
+    ///     ZstdLinesConfig::new()
+    ///         .line_ending(line_ending)
+    ///         .run([dir.join("mixed.zst")], |line, _path| lines.lock().unwrap().push(line));
+    ///     lines.into_inner().unwrap()
+    /// };
+    /// assert_eq!(collect(LineEnding::KeepCr), vec!["a\r", "b ", "c"]);
+    /// assert_eq!(collect(LineEnding::StripCr), vec!["a", "b ", "c"]);
+    /// assert_eq!(collect(LineEnding::TrimWhitespace), vec!["a", "b", "c"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn line_ending(mut self, line_ending: LineEnding) -> Self {
+        self.line_ending = line_ending;
+        self
+    }
+
+    /// Split the decompressed content into records separated by `delimiter` instead of lines,
+    /// e.g. ``0`` for the output of ``find -print0`` or ``0x1E`` for JSON text sequences.
+    /// The delimiter is removed from every record, and a last record without a delimiter is delivered as well.
+    /// ``line_ending()`` only applies to the default ``\n`` delimiter, other delimiters are removed without touching the rest of the record.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_delimiter");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// let content = b"./a.txt\0./with\nnewline\r\0./last";
+    /// std::fs::write(dir.join("files.zst"), zstd::encode_all(&content[..], 0).unwrap()).unwrap();
+    ///
+    /// let records = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new()
+    ///     .delimiter(0)
+    ///     .run([dir.join("files.zst")], |record, _path| records.lock().unwrap().push(record));
+    /// assert_eq!(records.into_inner().unwrap(), vec!["./a.txt", "./with\nnewline\r", "./last"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Skip empty lines, or lines of nothing but whitespace, instead of passing them to the line handler.
+    /// Lines are checked after ``line_ending()`` is applied, so lines of a single ``\r`` are empty by default.
+    /// Skipped lines still count for the line numbers passed along with every line, so numbers keep matching the
+    /// decompressed content, but they are not counted in the ``FileStats`` passed to ``on_file_end()``.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::{EmptyLines, ZstdLinesConfig};
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_empty_lines");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("groups.zst"), zstd::encode_all(&b"a\n\n\r\n  \nb\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let collect = |empty_lines| {
+    ///     let lines = Mutex::new(Vec::new());
+    ///     ZstdLinesConfig::new()
+    ///         .skip_empty_lines(empty_lines)
+    ///         .run([dir.join("groups.zst")], |line, _path| lines.lock().unwrap().push(line));
+    ///     lines.into_inner().unwrap()
+    /// };
+    /// assert_eq!(collect(EmptyLines::Keep), vec!["a", "", "", "  ", "b"]);
+    /// assert_eq!(collect(EmptyLines::SkipEmpty), vec!["a", "  ", "b"]);
+    /// assert_eq!(collect(EmptyLines::SkipBlank), vec!["a", "b"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn skip_empty_lines(mut self, empty_lines: EmptyLines) -> Self {
+        self.empty_lines = empty_lines;
+        self
+    }
+
+    /// Only deliver every `n`th line of every file or archive entry, i.e. the lines numbered `n`, `2n` and so on.
+    /// The other lines are never decoded or passed to the line handler, and line numbers stay those of the original file.
+    /// A `n` of 0 is treated as 1. Since lines have to be numbered, files in the zstd seekable format are decoded on a single thread.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_every_nth");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// let content: String = (1..=10).map(|i| format!("{}\n", i)).collect();
+    /// std::fs::write(dir.join("numbers.zst"), zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+    ///
+    /// let lines = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new()
+    ///     .every_nth(3)
+    ///     .run([dir.join("numbers.zst")], |line, _path| lines.lock().unwrap().push(line));
+    /// assert_eq!(lines.into_inner().unwrap(), vec!["3", "6", "9"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn every_nth(mut self, n: u64) -> Self {
+        self.sampling = Sampling::EveryNth(n.max(1));
+        self.with_line_numbers()
+    }
+
+    /// Only deliver a random sample of about `fraction` of the lines, between 0 and 1.
+    /// Whether a line is part of the sample only depends on `seed`, the path, the archive entry and the position of the line,
+    /// so runs with the same seed deliver the same lines. The other lines are never decoded or passed to the line handler.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_fraction");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// let content: String = (0..10_000).map(|i| format!("{{\"id\":{}}}\n", i)).collect();
+    /// std::fs::write(dir.join("dump.jsonl.zst"), zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+    ///
+    /// let sample = |seed| {
+    ///     let lines = Mutex::new(Vec::new());
+    ///     ZstdLinesConfig::new()
+    ///         .fraction(0.01, seed)
+    ///         .run([dir.join("dump.jsonl.zst")], |line, _path| lines.lock().unwrap().push(line));
+    ///     lines.into_inner().unwrap()
+    /// };
+    /// let first = sample(7);
+    /// assert!((50..150).contains(&first.len()), "{}", first.len());
+    /// assert_eq!(first, sample(7));
+    /// assert_ne!(first, sample(8));
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn fraction(mut self, fraction: f64, seed: u64) -> Self {
+        self.sampling = Sampling::fraction(fraction, seed);
+        self
+    }
+
+    /// Skip the first `n` lines of every file, and of every entry of a tar or zip archive, since line numbers start over for every entry.
+    /// Skipped lines are only counted, they are never decoded or passed to the line handler.
+    /// Since lines have to be numbered, files in the zstd seekable format are decoded on a single thread.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_skip_lines");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// let content: String = (1..=1000).map(|i| format!("{}\n", i)).collect();
+    /// std::fs::write(dir.join("numbers.zst"), zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+    ///
+    /// let lines = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new()
+    ///     .skip_lines(500)
+    ///     .take_lines(3)
+    ///     .run([dir.join("numbers.zst")], |line, _path| lines.lock().unwrap().push(line));
+    /// assert_eq!(lines.into_inner().unwrap(), vec!["501", "502", "503"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn skip_lines(mut self, n: u64) -> Self {
+        self.skip_lines = n;
+        self.with_line_numbers()
+    }
+
+    /// Deliver at most `n` lines of every file, and of every entry of a tar or zip archive, after the lines skipped with ``skip_lines()``.
+    /// Once a plain file has delivered its lines, decompression stops and the file is closed right away.
+    /// The rest of a tar entry is skipped without splitting it into lines, so the following entries can be read.
+    /// Since lines have to be numbered, files in the zstd seekable format are decoded on a single thread.
+    pub fn take_lines(mut self, n: u64) -> Self {
+        self.take_lines = n;
+        self.with_line_numbers()
+    }
+
+    /// Limit how long a line may get, 256 MiB by default, so a file without line breaks cannot run out of memory.
+    /// Reading a line stops accumulating it once it is longer than `max_line_len` bytes, without its line break.
+    /// By default, the line is then reported on stderr with its path and byte offset and skipped up to the next line break,
+    /// ``long_lines()`` fails the file instead. Skipped lines still count for the line numbers.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_max_line_len");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// let content = format!("short\n{}\nnext\n", "x".repeat(100_000));
+    /// std::fs::write(dir.join("dump.zst"), zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+    ///
+    /// let lines = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new()
+    ///     .max_line_len(1000)
+    ///     .on_error(|_path, _error| {})
+    ///     .run([dir.join("dump.zst")], |line, _path| lines.lock().unwrap().push(line));
+    /// assert_eq!(lines.into_inner().unwrap(), vec!["short", "next"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn max_line_len(mut self, max_line_len: usize) -> Self {
+        self.max_line_len = max_line_len as u64;
+        self
+    }
+
+    /// Set the size of the buffers compressed input and decompressed content are read through, larger buffers mean
+    /// fewer reads, e.g. on network file systems or fast disks. By default zstd input is read in blocks of the size zstd
+    /// recommends, and decompressed content, including tar archives the headers are read from, in blocks of 8 KiB.
+    /// Lines may be longer than the buffer. ``cargo bench --bench buffer_size`` compares a few sizes.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_buffer_size");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// let content: String = (0..1000).map(|i| format!("line number {}\n", i)).collect();
+    /// std::fs::write(dir.join("lines.zst"), zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+    ///
+    /// for buffer_size in [7, 1 << 20] {
+    ///     let lines = Mutex::new(Vec::new());
+    ///     ZstdLinesConfig::new()
+    ///         .buffer_size(buffer_size)
+    ///         .run([dir.join("lines.zst")], |line, _path| lines.lock().unwrap().push(line));
+    ///     let lines = lines.into_inner().unwrap();
+    ///     assert_eq!(lines.len(), 1000);
+    ///     assert_eq!(lines[999], "line number 999");
+    /// }
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.buffer_size = Some(buffer_size.max(1));
+        self
+    }
+
+    /// Process the files on the threads of `pool` instead of rayon's global pool, e.g. to keep decompression
+    /// from taking threads of other work. Running a configuration from inside a task of another pool blocks that task
+    /// until all files are done, so the other pool needs threads to spare for its remaining work, and a pool must not
+    /// wait on a task of another pool which waits on it in turn. Running it from a task of `pool` itself is fine.
+    ///
+    /// # Example
+    /// ```
+    /// use rayon::ThreadPoolBuilder;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_thread_pool");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"1\n2\n"[..], 0).unwrap()).unwrap();
+    /// std::fs::write(dir.join("b.zst"), zstd::encode_all(&b"3\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let pool = ThreadPoolBuilder::new()
+    ///     .num_threads(2)
+    ///     .thread_name(|i| format!("decompress-{}", i))
+    ///     .build()
+    ///     .unwrap();
+    /// let threads = Mutex::new(Vec::new());
+    /// zstd_lines::ZstdLinesConfig::new()
+    ///     .thread_pool(&pool)
+    ///     .run([dir.join("a.zst"), dir.join("b.zst")], |_line, _path| {
+    ///         threads.lock().unwrap().push(std::thread::current().name().unwrap().to_owned());
+    ///     });
+    /// assert!(threads.into_inner().unwrap().iter().all(|name| name.starts_with("decompress-")));
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn thread_pool(mut self, pool: &'a ThreadPool) -> Self {
+        self.thread_pool = Some(pool);
+        self
+    }
+
+    /// Process at most `n` files at once, however many threads the pool has, to bound the memory held by the decoders
+    /// of files in flight, e.g. windows of up to 2 GiB for archives compressed with ``--long=31``. Threads which reach
+    /// another file wait for one of the files to finish. Unlimited by default.
+    ///
+    /// While a thread waits for the work of a file split across threads, it may pick up another file, which is then
+    /// processed without waiting, since the thread could never free its slot otherwise.
+    ///
+    /// # Example
+    /// ```
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_max_concurrent_files");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// let files: Vec<_> = (0..8).map(|i| dir.join(format!("part-{}.zst", i))).collect();
+    /// for file in &files {
+    ///     std::fs::write(file, zstd::encode_all(&b"1\n2\n"[..], 0).unwrap()).unwrap();
+    /// }
+    ///
+    /// // Archives compressed with --long=31 take up to 2 GiB each while they are decoded
+    /// let lines = AtomicUsize::new(0);
+    /// zstd_lines::ZstdLinesConfig::new()
+    ///     .max_concurrent_files(2)
+    ///     .run(&files, |_line, _path| {
+    ///         lines.fetch_add(1, Ordering::Relaxed);
+    ///     });
+    /// assert_eq!(lines.into_inner(), 16);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn max_concurrent_files(mut self, n: usize) -> Self {
+        self.max_concurrent_files = n.max(1);
+        self
+    }
+
+    /// Start the largest files first, so a few big files do not end up on a single thread while the others are idle,
+    /// which is common when file sizes vary wildly. The sizes are read before any file is started, files whose size
+    /// cannot be read keep their place among the others. Off by default, files are started in the order of the paths.
+    /// Results like the failures of ``try_run()`` are in the order of the paths either way.
+    ///
+    /// # Example
+    /// ```
+    /// use rayon::ThreadPoolBuilder;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_largest_first");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// for (name, lines) in [("small.txt", 1), ("big.txt", 1000), ("medium.txt", 100)] {
+    ///     std::fs::write(dir.join(name), "line\n".repeat(lines)).unwrap();
+    /// }
+    ///
+    /// let started = Mutex::new(Vec::new());
+    /// let pool = ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+    /// zstd_lines::ZstdLinesConfig::new()
+    ///     .thread_pool(&pool)
+    ///     .largest_first(true)
+    ///     .report_failures(false)
+    ///     .on_file_start(|path| started.lock().unwrap().push(path.file_name().unwrap().to_owned()))
+    ///     .run(["small.txt", "missing.txt", "big.txt", "medium.txt"].map(|name| dir.join(name)), |_line, _path| {});
+    /// assert_eq!(started.into_inner().unwrap(), ["big.txt", "missing.txt", "medium.txt", "small.txt"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn largest_first(mut self, enabled: bool) -> Self {
+        self.schedule = if enabled {
+            Some(Box::new(schedule::file_size))
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Start the files with the largest keys first, like ``largest_first()`` with another measure of their size,
+    /// e.g. line counts from a manifest or the uncompressed sizes of the frames. Files with the same key are started
+    /// in the order of the paths, files without a key keep their place among the others.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - A function or closure that returns the key of a file from its path, called once per file.
+    ///
+    /// # Example
+    /// ```
+    /// use std::collections::HashMap;
+    /// use std::path::Path;
+    ///
+    /// let expected_lines = HashMap::from([(Path::new("a.zst"), 10), (Path::new("b.zst"), 5_000_000)]);
+    /// let config = zstd_lines::ZstdLinesConfig::new()
+    ///     .schedule_by(|path| expected_lines.get(path).copied());
+    /// ```
+    pub fn schedule_by<K>(mut self, key: K) -> Self
+    where
+        K: Fn(&Path) -> Option<u64> + Sync + Send + 'a,
+    {
+        self.schedule = Some(Box::new(key));
+        self
+    }
+
+    /// Call `handler` with the progress of every run while it goes on, e.g. to print "37/412 files, 1.2B lines" every
+    /// few seconds, and once more when the run is finished. It may be called from several threads at once.
+    /// The threads processing the files only increment atomic counters, reading a line costs a single increment.
+    ///
+    /// # Arguments
+    ///
+    /// * `every` - Whether the handler is called at an interval, from a thread of its own, or every so many lines.
+    /// * `handler` - A function or closure that will handle the progress.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::{Progress, ProgressEvery};
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_on_progress");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"1\n2\n3\n"[..], 0).unwrap()).unwrap();
+    /// std::fs::write(dir.join("b.zst"), zstd::encode_all(&b"4\n5\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let updates = Mutex::new(Vec::new());
+    /// zstd_lines::ZstdLinesConfig::new()
+    ///     .report_failures(false)
+    ///     .on_progress(ProgressEvery::Lines(2), |progress: &Progress| {
+    ///         updates.lock().unwrap().push(*progress);
+    ///     })
+    ///     .run([dir.join("a.zst"), dir.join("b.zst"), dir.join("missing.zst")], |_line, _path| {});
+    /// let updates = updates.into_inner().unwrap();
+    /// // Every two lines and once at the end
+    /// assert_eq!(updates.len(), 3);
+    /// let last = updates.last().unwrap();
+    /// assert_eq!((last.files_total, last.files_done, last.files_failed, last.lines_done), (Some(3), 3, 1, 5));
+    /// let compressed = std::fs::metadata(dir.join("a.zst")).unwrap().len() + std::fs::metadata(dir.join("b.zst")).unwrap().len();
+    /// assert_eq!(last.compressed_bytes, compressed);
+    /// println!("{}/{} files, {} lines", last.files_done, last.files_total.unwrap(), last.lines_done);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn on_progress<H>(mut self, every: ProgressEvery, handler: H) -> Self
+    where
+        H: Fn(&Progress) + Sync + Send + 'a,
+    {
+        let every = match every {
+            ProgressEvery::Lines(n) => ProgressEvery::Lines(n.max(1)),
+            every => every,
+        };
+        self.on_progress = Some((every, Box::new(handler)));
+        self
+    }
+
+    /// Show the progress of every run with indicatif bars added to `multi`: an overall bar with the files done out of
+    /// all files, the lines read and the compressed bytes read, and with ``ProgressBars::PerFile`` a bar for every file
+    /// being processed with the compressed bytes read out of its size. The bar of a file is removed once it is finished,
+    /// also when it fails. The bars are updated ten times a second by ``on_progress()``, which is taken by them.
+    ///
+    /// # Arguments
+    ///
+    /// * `multi` - The ``MultiProgress`` the bars are added to, e.g. to draw them along with bars of your own.
+    /// * `bars` - Whether to show a bar per file besides the overall bar.
+    ///
+    /// # Example
+    /// ```
+    /// use indicatif::{MultiProgress, ProgressDrawTarget};
+    /// use zstd_lines::ProgressBars;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_progress_bars");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"1\n2\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// // Drawn on stderr with MultiProgress::new()
+    /// let multi = MultiProgress::with_draw_target(ProgressDrawTarget::hidden());
+    /// let lines = AtomicUsize::new(0);
+    /// zstd_lines::ZstdLinesConfig::new()
+    ///     .report_failures(false)
+    ///     .progress_bars(&multi, ProgressBars::PerFile)
+    ///     .run([dir.join("a.zst"), dir.join("missing.zst")], |_line, _path| {
+    ///         lines.fetch_add(1, Ordering::Relaxed);
+    ///     });
+    /// assert_eq!(lines.into_inner(), 2);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    #[cfg(feature = "indicatif")]
+    pub fn progress_bars(mut self, multi: &MultiProgress, bars: ProgressBars) -> Self {
+        let in_flight =
+            (bars == ProgressBars::PerFile).then(|| Arc::new(progress::FilesInFlight::default()));
+        self.progress.in_flight = in_flight.clone();
+        let bars = Bars::new(multi, in_flight);
+        self.on_progress(
+            ProgressEvery::Interval(PROGRESS_BAR_INTERVAL),
+            move |progress| bars.update(progress),
+        )
+    }
+
+    /// Stop every run once `token` is cancelled: files being processed stop after the line they are at, and files which
+    /// were not started yet are skipped, like lines after a problem in ``strict()`` mode. ``run_cancellable()`` tells
+    /// which files were completed, interrupted and never started.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The token which cancels the runs, its clones can be cancelled from any thread.
+    pub fn cancel_with(mut self, token: &CancellationToken) -> Self {
+        self.cancellation = Some(token.clone());
+        self
+    }
+
+    /// Abandon a file once no data could be read from it for `timeout`, e.g. when it sits on a dying disk, reporting it
+    /// as ``ZstdLinesError::Timeout`` with the compressed bytes read before it stalled. The lines in front of the stall
+    /// are delivered and the thread moves on to the next file.
+    ///
+    /// A blocking read cannot be interrupted portably, so every file is read by a thread of its own which the thread
+    /// processing the file waits for, at most for `timeout` per read. When a read takes longer, the reader thread is
+    /// left behind with the file: it ends and closes the file only once the read returns, which may be never. This
+    /// costs a thread per open file and a copy of every block read, so it is only worth it for storage which is known
+    /// to hang. The reader threads are spawned outside of the thread pool and do not count against ``thread_pool()``
+    /// or ``max_concurrent_files()``: a file in the zstd seekable format has one for every range decoded in parallel,
+    /// and threads left behind by stalled reads add up over the run. Opening a file is not watched, nor are standard
+    /// input and the first bytes read to tell zip archives apart. A stall is an ``io::ErrorKind::TimedOut`` error,
+    /// which ``retry()`` retries with the default ``RetryPolicy``, and every retry which stalls again leaves another
+    /// reader thread behind.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - How long a single read may take, reading slowly but steadily never times out.
+    ///
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    /// use zstd_lines::ZstdLinesConfig;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_file_timeout");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("dump.jsonl.zst"), zstd::encode_all(&b"1\n2\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// // Files on a share which may hang are abandoned after a minute without data
+    /// let result = ZstdLinesConfig::new()
+    ///     .file_timeout(Duration::from_secs(60))
+    ///     .try_run([dir.join("dump.jsonl.zst")], |_line, _path| {});
+    /// assert!(result.is_ok());
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn file_timeout(mut self, timeout: Duration) -> Self {
+        self.file_timeout = Some(timeout);
+        self
+    }
+
+    /// Keep reading a named pipe (FIFO) after all its writers closed it, for ``PipeOptions::eof_retry_window``,
+    /// so a producer which restarts and reopens the pipe in time does not truncate the stream. Every run waits for
+    /// the window once a pipe really ends. FIFOs and character devices are read from start to end in any case, they are
+    /// never seeked nor asked for their size, so they are not split across threads and have no seek table.
+    /// A ``file_timeout()`` shorter than the window gives up on the pipe while it is waiting for a new writer.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Controls how end-of-file on pipes is handled.
+    ///
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    /// use zstd_lines::{PipeOptions, ZstdLinesConfig};
+    ///
+    /// // A producer which restarts within 5 seconds keeps writing the same stream
+    /// let config = ZstdLinesConfig::new().pipe_options(PipeOptions {
+    ///     eof_retry_window: Duration::from_secs(5),
+    ///     ..PipeOptions::default()
+    /// });
+    /// # let _ = config;
+    /// ```
+    pub fn pipe_options(mut self, options: PipeOptions) -> Self {
+        self.pipe_options = Some(options);
+        self
+    }
+
+    /// Run `op` on the thread pool set with ``thread_pool()``, or on the current one, as a run reported to ``on_progress()``.
+    pub(crate) fn install<R, OP>(&self, op: OP) -> R
+    where
+        R: Send,
+        OP: FnOnce(&Run<'_>) -> R + Send,
+    {
+        let run = self.start_run();
+        progress::track(self, || match self.thread_pool {
+            Some(pool) => pool.install(|| op(&run)),
+            None => op(&run),
+        })
+    }
+
+    /// Start a run which is not stopped yet, for functions which process files with this configuration
+    /// without ``install()``.
+    pub(crate) fn start_run(&self) -> Run<'_> {
+        Run {
+            config: self,
+            stopped: AtomicBool::new(false),
+            first_problem: Mutex::new(None),
+        }
+    }
+
+    /// Wrap decompressed content in a buffer of the configured size to split it into lines.
+    pub(crate) fn buffered<R: Read>(&self, reader: R) -> BufReader<R> {
+        BufReader::with_capacity(self.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE), reader)
+    }
+
+    /// Decide what happens to lines which are longer than ``max_line_len()``, they are skipped by default.
+    pub fn long_lines(mut self, long_lines: LongLines) -> Self {
+        self.long_lines = long_lines;
+        self
+    }
+
+    /// Decide what happens to a file with lines which cannot be decoded or are longer than ``max_line_len()``.
+    /// By default such lines are reported and skipped, however many there are. A corrupted file can be given up on after
+    /// a number of bad lines in a row, at the first bad line, or all files can be stopped, see ``LineErrors``.
+    /// Abandoned files fail with ``ZstdLinesError::Abandoned``, which tells the policy.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::{LineErrors, ZstdLinesConfig, ZstdLinesError};
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_line_errors");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("garbled.zst"), zstd::encode_all(&b"ok\n\xFF\nok\n\xFE\n\xFD\n\xFC\nok\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let lines = AtomicUsize::new(0);
+    /// let errors = ZstdLinesConfig::new()
+    ///     .line_errors(LineErrors::ContinueFile { max_consecutive: 3 })
+    ///     .on_error(|_path, _error| {})
+    ///     .try_run([dir.join("garbled.zst")], |_line, _path| {
+    ///         lines.fetch_add(1, Ordering::Relaxed);
+    ///     })
+    ///     .unwrap_err();
+    /// // A single bad line is skipped, the file is given up on at the third bad line in a row
+    /// assert_eq!(lines.into_inner(), 2);
+    /// assert!(matches!(
+    ///     errors.failures[..],
+    ///     [ZstdLinesError::Abandoned { policy: LineErrors::ContinueFile { .. }, consecutive: 3, .. }]
+    /// ));
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn line_errors(mut self, line_errors: LineErrors) -> Self {
+        self.line_errors = line_errors;
+        self
+    }
+
+    /// Check whether the line with the given number is skipped by ``skip_lines()``.
+    pub(crate) fn skips_line(&self, line_number: u64) -> bool {
+        line_number <= self.skip_lines
+    }
+
+    /// Check whether ``take_lines()`` delivers no more lines after the line with the given number.
+    pub(crate) fn takes_no_more(&self, line_number: u64) -> bool {
+        line_number >= self.skip_lines.saturating_add(self.take_lines)
+    }
+
+    /// Remove the delimiter from a record read with ``read_until()``, along with whatever ``line_ending()`` removes from lines.
+    pub(crate) fn trim(&self, buffer: &mut Vec<u8>) {
+        if self.delimiter == b'\n' {
+            self.line_ending.trim(buffer);
+        } else if buffer.last() == Some(&self.delimiter) {
+            buffer.pop();
+        }
+    }
+
+    /// Decode the lines of every file with `encoding` instead of UTF-8, available with the `encoding` feature.
+    /// Lines which are malformed in that encoding are reported and skipped, unless ``replace_malformed()`` is set.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_encoding");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// // "Café" and "naïve" in Windows-1252
+    /// let content = b"Caf\xE9\nna\xEFve\n";
+    /// std::fs::write(dir.join("legacy.log.zst"), zstd::encode_all(&content[..], 0).unwrap()).unwrap();
+    ///
+    /// let lines = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new()
+    ///     .encoding(encoding_rs::WINDOWS_1252)
+    ///     .run([dir.join("legacy.log.zst")], |line, _path| lines.lock().unwrap().push(line));
+    /// assert_eq!(lines.into_inner().unwrap(), vec!["Café", "naïve"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    #[cfg(feature = "encoding")]
+    pub fn encoding(self, encoding: &'static encoding_rs::Encoding) -> Self {
+        self.encoding_for(move |_path| encoding)
+    }
+
+    /// Decode the lines of every file with the encoding `select` returns for its path, available with the `encoding` feature.
+    /// `select` is called once for every file and every tar entry or zip member, before its first line.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_encoding_for");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// // "日本" in Shift-JIS, and the same text in UTF-8
+    /// std::fs::write(dir.join("sjis.log.zst"), zstd::encode_all(&b"\x93\xFA\x96\x7B\n"[..], 0).unwrap()).unwrap();
+    /// std::fs::write(dir.join("utf8.log.zst"), zstd::encode_all("日本\n".as_bytes(), 0).unwrap()).unwrap();
+    ///
+    /// let lines = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new()
+    ///     .encoding_for(|path| {
+    ///         if path.ends_with("sjis.log.zst") {
+    ///             encoding_rs::SHIFT_JIS
+    ///         } else {
+    ///             encoding_rs::UTF_8
+    ///         }
+    ///     })
+    ///     .run([dir.join("sjis.log.zst"), dir.join("utf8.log.zst")], |line, _path| {
+    ///         lines.lock().unwrap().push(line)
+    ///     });
+    /// assert_eq!(lines.into_inner().unwrap(), vec!["日本", "日本"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    #[cfg(feature = "encoding")]
+    pub fn encoding_for<F>(mut self, select: F) -> Self
+    where
+        F: Fn(&Path) -> &'static encoding_rs::Encoding + Sync + Send + 'a,
+    {
+        self.encoding = Some(Box::new(select));
+        self
+    }
+
+    /// Replace malformed sequences with U+FFFD instead of reporting and skipping the line, available with the `encoding` feature.
+    /// This also applies to files decoded as UTF-8, it is the same setting as ``utf8_lossy()``.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_replace_malformed");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// let content = b"valid\nbroken \xFF\n";
+    /// std::fs::write(dir.join("broken.log.zst"), zstd::encode_all(&content[..], 0).unwrap()).unwrap();
+    ///
+    /// let collect = |config: ZstdLinesConfig| {
+    ///     let lines = Mutex::new(Vec::new());
+    ///     config.run([dir.join("broken.log.zst")], |line, _path| lines.lock().unwrap().push(line));
+    ///     lines.into_inner().unwrap()
+    /// };
+    /// assert_eq!(collect(ZstdLinesConfig::new()), vec!["valid"]);
+    /// assert_eq!(collect(ZstdLinesConfig::new().replace_malformed(true)), vec!["valid", "broken \u{FFFD}"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    #[cfg(feature = "encoding")]
+    pub fn replace_malformed(self, replace_malformed: bool) -> Self {
+        self.utf8_lossy(replace_malformed)
+    }
+
+    /// Deliver lines which are not valid UTF-8 with malformed sequences replaced by U+FFFD, like ``String::from_utf8_lossy()``,
+    /// instead of reporting and skipping them. This applies to plain files and tar entries alike.
+    /// ``replaced_lines()`` tells how many lines needed a replacement.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_utf8_lossy");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// let content = b"{\"a\":1}\n{\"body\":\"caf\xE9\"}\n";
+    /// std::fs::write(dir.join("comments.zst"), zstd::encode_all(&content[..], 0).unwrap()).unwrap();
+    ///
+    /// let config = ZstdLinesConfig::new().utf8_lossy(true);
+    /// let lines = Mutex::new(Vec::new());
+    /// config.run([dir.join("comments.zst")], |line, _path| lines.lock().unwrap().push(line));
+    /// assert_eq!(lines.into_inner().unwrap(), vec![r#"{"a":1}"#, "{\"body\":\"caf\u{FFFD}\"}"]);
+    /// assert_eq!(config.replaced_lines(), 1);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn utf8_lossy(mut self, utf8_lossy: bool) -> Self {
+        self.replace_malformed = utf8_lossy;
+        self
+    }
+
+    /// Decide what happens to lines which are not valid UTF-8, they are reported and skipped by default.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::{Utf8Policy, ZstdLinesConfig};
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_utf8_policy");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("latin1.zst"), zstd::encode_all(&b"caf\xE9\nok\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let lines = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new()
+    ///     .utf8(Utf8Policy::Lossy)
+    ///     .run([dir.join("latin1.zst")], |line, _path| lines.lock().unwrap().push(line));
+    /// assert_eq!(lines.into_inner().unwrap(), vec!["caf\u{FFFD}", "ok"]);
+    ///
+    /// let lines = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new()
+    ///     .utf8(Utf8Policy::Skip)
+    ///     .on_invalid_utf8(|_bytes, _path, _line_number| {})
+    ///     .run([dir.join("latin1.zst")], |line, _path| lines.lock().unwrap().push(line));
+    /// assert_eq!(lines.into_inner().unwrap(), vec!["ok"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn utf8(self, policy: Utf8Policy) -> Self {
+        self.utf8_lossy(policy == Utf8Policy::Lossy)
+    }
+
+    /// Number of lines which were delivered with malformed sequences replaced, see ``utf8_lossy()``.
+    /// Lines of every run with this configuration are counted.
+    pub fn replaced_lines(&self) -> u64 {
+        self.replaced_lines.load(Ordering::Relaxed)
+    }
+
+    /// Invoke a callback for every line which is skipped because it is not valid UTF-8, or not valid in the encoding
+    /// chosen with ``encoding()``, instead of reporting it on stderr. The callback receives the bytes of the line
+    /// without its line break, the path of the file and the number of the line within its file or archive entry, starting at 1.
+    /// Since lines have to be numbered, files in the zstd seekable format are decoded on a single thread.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_invalid_utf8");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// let content = b"{\"a\":1}\n{\"blob\":\"\xFF\"}\n{\"a\":2}\n";
+    /// let mut tar = tar::Builder::new(Vec::new());
+    /// let mut header = tar::Header::new_ustar();
+    /// header.set_size(content.len() as u64);
+    /// tar.append_data(&mut header, "dump.jsonl", &content[..]).unwrap();
+    /// std::fs::write(dir.join("dump.tar.zst"), zstd::encode_all(&tar.into_inner().unwrap()[..], 0).unwrap()).unwrap();
+    ///
+    /// let invalid = Mutex::new(Vec::new());
+    /// let lines = Mutex::new(Vec::new());
+    /// let config = ZstdLinesConfig::new().on_invalid_utf8(|bytes, _path, line_number| {
+    ///     invalid.lock().unwrap().push((line_number, bytes.to_vec()));
+    /// });
+    /// config.run([dir.join("dump.tar.zst")], |line, _path| lines.lock().unwrap().push(line));
+    /// assert_eq!(lines.into_inner().unwrap(), vec![r#"{"a":1}"#, r#"{"a":2}"#]);
+    /// assert_eq!(*invalid.lock().unwrap(), vec![(2, b"{\"blob\":\"\xFF\"}".to_vec())]);
+    /// assert_eq!(config.invalid_lines(), 1);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn on_invalid_utf8<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&[u8], &Path, u64) + Sync + Send + 'a,
+    {
+        self.on_invalid_utf8 = Some(Box::new(handler));
+        self.with_line_numbers()
+    }
+
+    /// Invoke a callback when a file is started, on the thread which processes it, e.g. to open one output per input file.
+    /// Files which are skipped because processing was stopped are never started.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// let started = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new()
+    ///     .on_file_start(|path| started.lock().unwrap().push(path.to_path_buf()))
+    ///     .run(["missing.zst"], |_line, _path| {});
+    /// assert_eq!(started.into_inner().unwrap().len(), 1);
+    /// ```
+    pub fn on_file_start<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&Path) + Sync + Send + 'a,
+    {
+        self.on_file_start = Some(Box::new(handler));
+        self
+    }
+
+    /// Invoke a callback exactly once for every file started with ``on_file_start()``, once it is finished.
+    /// The callback receives the lines and bytes read from the file, or the error it failed with, also when it failed halfway through.
+    /// Files stopped early by ``par_zstd_try_lines()`` and the like end with an error as well.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::{FileStats, ZstdLinesConfig};
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_file_end");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("dump.jsonl.zst"), zstd::encode_all(&b"first\nsecond\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let finished = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new()
+    ///     .on_file_end(|path, result| {
+    ///         finished.lock().unwrap().push((path.file_name().unwrap().to_owned(), result.map_err(|e| e.kind())));
+    ///     })
+    ///     .run([dir.join("dump.jsonl.zst"), dir.join("missing.jsonl.zst")], |_line, _path| {});
+    /// let mut finished = finished.into_inner().unwrap();
+    /// finished.sort_by(|a, b| a.0.cmp(&b.0));
+    /// assert_eq!(finished[0].1, Ok(FileStats { lines: 2, bytes: 11 }));
+    /// assert_eq!(finished[1].1, Err(std::io::ErrorKind::NotFound));
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn on_file_end<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&Path, Result<FileStats, &io::Error>) + Sync + Send + 'a,
+    {
+        self.on_file_end = Some(Box::new(handler));
+        self
+    }
+
+    /// Report files which fail on stderr, which is the default. Turn this off when the failures are taken from
+    /// the result of ``try_run()`` or from ``on_file_end()`` instead.
+    pub fn report_failures(mut self, report_failures: bool) -> Self {
+        self.report_failures = report_failures;
+        self
+    }
+
+    /// Invoke a callback instead of printing to stderr for every problem found while processing files: files which fail,
+    /// lines which cannot be decoded, long lines and zip members which are skipped. The callback is called from the worker
+    /// threads, possibly from several at once, with the path of the file and what went wrong. Lines passed to
+    /// ``on_invalid_utf8()`` are not reported again, neither are failures if ``report_failures()`` is turned off.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::{ZstdLinesConfig, ZstdLinesError};
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_on_error");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"ok\n\xFF\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let events = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new()
+    ///     .on_error(|path, error| {
+    ///         let event = match error {
+    ///             ZstdLinesError::Open { source, .. } => format!("file failed: {}", source.kind()),
+    ///             ZstdLinesError::InvalidLine { line_number, .. } => format!("invalid line {}", line_number),
+    ///             other => other.to_string(),
+    ///         };
+    ///         events.lock().unwrap().push((path.file_name().unwrap().to_owned(), event));
+    ///     })
+    ///     .run([dir.join("a.zst"), dir.join("missing.zst")], |_line, _path| {});
+    /// let mut events = events.into_inner().unwrap();
+    /// events.sort();
+    /// assert_eq!(events[0].1, "invalid line 2");
+    /// assert_eq!(events[1].1, "file failed: entity not found");
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn on_error<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&Path, &ZstdLinesError) + Sync + Send + 'a,
+    {
+        self.on_error = Some(Box::new(handler));
+        self
+    }
+
+    /// Stop all files at the first problem anywhere: a file which cannot be opened or decoded, a line which cannot be decoded,
+    /// a long line or a zip member which is skipped. Files being processed stop after their current line, files which
+    /// were not started yet are skipped, and ``try_run()`` returns the first problem alone. Problems are still reported
+    /// as usual, lines passed to ``on_invalid_utf8()`` are not a problem. Every run starts over, a problem only stops
+    /// the run it was found in.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::{ZstdLinesConfig, ZstdLinesError};
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_strict");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"ok\n\xFF\nnever\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let errors = ZstdLinesConfig::new()
+    ///     .strict(true)
+    ///     .on_error(|_path, _error| {})
+    ///     .try_run([dir.join("a.zst")], |line, _path| assert_eq!(line, "ok"))
+    ///     .unwrap_err();
+    /// assert!(matches!(errors.failures[..], [ZstdLinesError::InvalidLine { line_number: 2, .. }]));
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Process files which failed with a transient error, like a timeout of a network file system, again from the beginning.
+    /// Only files which failed before delivering a line are retried, unless ``RetryPolicy::after_lines`` is set,
+    /// the error of the last attempt is reported. Waiting for the backoff blocks the worker thread of the file.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::{RetryPolicy, ZstdLinesConfig};
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::time::Duration;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_retry");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// # let _ = std::fs::remove_file(dir.join("late.zst"));
+    /// let policy = RetryPolicy {
+    ///     backoff: Duration::from_millis(10),
+    ///     // Pretend a missing file is a share which comes back in a moment
+    ///     retriable: |error| {
+    ///         let file = std::env::temp_dir().join("zstd_lines_doc_retry").join("late.zst");
+    ///         std::fs::write(file, zstd::encode_all(&b"1\n2\n"[..], 0).unwrap()).unwrap();
+    ///         error.kind() == std::io::ErrorKind::NotFound
+    ///     },
+    ///     ..RetryPolicy::default()
+    /// };
+    ///
+    /// let lines = AtomicUsize::new(0);
+    /// ZstdLinesConfig::new()
+    ///     .retry(policy)
+    ///     .try_run([dir.join("late.zst")], |_line, _path| {
+    ///         lines.fetch_add(1, Ordering::Relaxed);
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(lines.into_inner(), 2);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Pass a problem to ``on_error()``, or report it on stderr.
+    fn notify(&self, error: &ZstdLinesError) {
+        match &self.on_error {
+            Some(handler) => handler(error.path(), error),
+            None => diagnostics::report(error),
+        }
+    }
+
+    /// Tell ``on_file_start()`` that a file is started.
+    pub(crate) fn file_started(&self, path: &Path) {
+        diagnostics::file_started(path);
+        #[cfg(feature = "indicatif")]
+        if let Some(in_flight) = &self.progress.in_flight {
+            in_flight.start(path);
+        }
+        if let Some(handler) = &self.on_file_start {
+            handler(path);
+        }
+    }
+
+    /// Tell ``on_file_end()`` that a file is finished.
+    pub(crate) fn file_finished(&self, path: &Path, counter: FileCounter, result: &io::Result<()>) {
+        let stats = FileStats {
+            lines: counter.lines.into_inner(),
+            bytes: counter.bytes.into_inner(),
+        };
+        diagnostics::file_finished(path, stats, result);
+        #[cfg(feature = "indicatif")]
+        if let Some(in_flight) = &self.progress.in_flight {
+            in_flight.finish(path);
+        }
+        if self.on_progress.is_some() {
+            self.progress
+                .file(result.as_ref().is_err_and(|e| !is_stopped(e)));
+        }
+        if let Some(handler) = &self.on_file_end {
+            handler(path, result.as_ref().map(|()| stats));
+        }
+    }
+
+    /// Tell ``on_progress()`` how many files the run has, if it is known.
+    pub(crate) fn progress_files(&self, total: Option<usize>) {
+        if let (Some(_), Some(total)) = (&self.on_progress, total) {
+            self.progress.files(total);
+        }
+    }
+
+    /// Count a line read for ``on_progress()``, calling it every `n` lines if it asks for it.
+    pub(crate) fn progress_line(&self) {
+        let Some((every, handler)) = &self.on_progress else {
+            return;
+        };
+        let lines = self.progress.line();
+        if let ProgressEvery::Lines(n) = every {
+            if lines.is_multiple_of(*n) {
+                handler(&self.progress.snapshot(false));
+            }
+        }
+    }
+
+    /// Open the file at `path` to process it, watched for ``file_timeout()`` and counted for ``on_progress()``.
+    pub(crate) fn open_input(&self, path: &Path) -> io::Result<Counted<'_, InputFile>> {
+        let file = error::open_file(path)?;
+        Ok(self.counted(path, self.watched(file)?))
+    }
+
+    /// Read `file` across end-of-file if it is a FIFO and ``pipe_options()`` are set, and through a reader thread
+    /// if a ``file_timeout()`` is set.
+    pub(crate) fn watched(&self, file: File) -> io::Result<InputFile> {
+        let input = match &self.pipe_options {
+            Some(options) if pipe::is_pipe(&file) => {
+                InputFile::Pipe(PipeReader::new(file, options))
+            }
+            _ => InputFile::Direct(file),
+        };
+        input.watched(self.file_timeout)
+    }
+
+    /// Count the compressed bytes read from the file at `path` for ``on_progress()``.
+    pub(crate) fn counted<R>(&self, path: &Path, reader: R) -> Counted<'_, R> {
+        match self.on_progress {
+            Some(_) => self.progress.counted(path, reader),
+            None => Counted::uncounted(reader),
+        }
+    }
+
+    /// Number of lines which were skipped because they could not be decoded, whether or not ``on_invalid_utf8()`` is set.
+    /// Lines of every run with this configuration are counted.
+    pub fn invalid_lines(&self) -> u64 {
+        self.invalid_lines.load(Ordering::Relaxed)
+    }
+
+    /// Process each line in zstd compressed files in parallel using this configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+    /// * `line_handler` - A function or closure that will handle each decompressed line.
+    pub fn run<I, F>(&self, paths: I, line_handler: F)
+    where
+        I: IntoParallelIterator,
+        I::Item: AsRef<Path>,
+        F: Fn(String, &Path) + Sync + Send,
+    {
+        let paths = paths.into_par_iter();
+        self.install(|run| {
+            let line_handler = decoded_sync(run, |line, context| line_handler(line, context.path));
+            schedule::process_files(run, paths, |path| {
+                process_file(path, run, &line_handler);
+            })
+        });
+    }
+
+    /// Like ``run()``, but passing a ``LineContext`` with the path, line number, byte offset and tar entry of every line,
+    /// like ``par_zstd_lines_ctx()``. Files in the zstd seekable format are decoded on several threads unless
+    /// ``with_line_numbers()`` is set, their lines have the number 0 then.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+    /// * `line_handler` - A function or closure that will handle each decompressed line and its context.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLinesConfig;
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_run_with_ctx");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("records.zst"), zstd::encode_all(&b"a\0b\0"[..], 0).unwrap()).unwrap();
+    ///
+    /// let lines = Mutex::new(Vec::new());
+    /// ZstdLinesConfig::new()
+    ///     .delimiter(b'\0')
+    ///     .with_line_numbers()
+    ///     .run_with_ctx([dir.join("records.zst")], |line, ctx| {
+    ///         lines.lock().unwrap().push((ctx.line_number, ctx.byte_offset, line));
+    ///     });
+    /// assert_eq!(lines.into_inner().unwrap(), vec![(1, 0, "a".to_string()), (2, 2, "b".to_string())]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn run_with_ctx<I, F>(&self, paths: I, line_handler: F)
+    where
+        I: IntoParallelIterator,
+        I::Item: AsRef<Path>,
+        F: Fn(String, &LineContext<'_>) + Sync + Send,
+    {
+        let paths = paths.into_par_iter();
+        self.install(|run| {
+            let line_handler = decoded_sync(run, line_handler);
+            schedule::process_files(run, paths, |path| {
+                process_file(path, run, &line_handler);
+            })
+        });
+    }
+
+    /// Like ``run()``, but returning every file which failed along with its error, in the order of the paths.
+    /// A failing file never affects the other files, which are processed completely.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+    /// * `line_handler` - A function or closure that will handle each decompressed line.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::{ZstdLinesConfig, ZstdLinesError};
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_try_run");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"1\n2\n"[..], 0).unwrap()).unwrap();
+    /// // A zstd magic number followed by garbage
+    /// std::fs::write(dir.join("corrupt.zst"), b"\x28\xB5\x2F\xFDgarbage").unwrap();
+    ///
+    /// let lines = AtomicUsize::new(0);
+    /// let result = ZstdLinesConfig::new()
+    ///     .report_failures(false)
+    ///     .try_run([dir.join("a.zst"), dir.join("missing.zst"), dir.join("corrupt.zst")], |_line, _path| {
+    ///         lines.fetch_add(1, Ordering::Relaxed);
+    ///     });
+    /// assert_eq!(lines.into_inner(), 2);
+    /// let errors = result.unwrap_err();
+    /// assert_eq!(errors.failures.len(), 2);
+    /// assert_eq!(errors.failures[0].path(), dir.join("missing.zst"));
+    /// assert!(matches!(errors.failures[0], ZstdLinesError::Open { .. }));
+    /// assert_eq!(errors.failures[1].path(), dir.join("corrupt.zst"));
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn try_run<I, F>(&self, paths: I, line_handler: F) -> Result<(), ZstdLinesErrors>
+    where
+        I: IntoParallelIterator,
+        I::Item: AsRef<Path>,
+        F: Fn(String, &Path) + Sync + Send,
+    {
+        let paths = paths.into_par_iter();
+        let (failures, first_problem) = self.install(|run| {
+            let line_handler = decoded_sync(run, |line, context| line_handler(line, context.path));
+            let failures: Vec<ZstdLinesError> = schedule::process_files(run, paths, |path| {
+                match try_process_file(path, run, &line_handler) {
+                    // Files skipped because processing was stopped did not fail
+                    Err(e) if !is_stopped(&e) => Some(ZstdLinesError::from_io(path, e)),
+                    _ => None,
+                }
+            })
+            .into_iter()
+            .flatten()
+            .collect();
+            (failures, run.take_first_problem())
+        });
+        if let Some(problem) = first_problem {
+            return Err(ZstdLinesErrors {
+                failures: vec![problem],
+            });
+        }
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(ZstdLinesErrors { failures })
+        }
+    }
+
+    /// Like ``run()``, but returning ``Cancelled`` with the files which were completed, interrupted and never started
+    /// if the run was cancelled with the token of ``cancel_with()`` before all files were finished, like
+    /// ``par_zstd_lines_cancellable()``. Files which fail are reported and count as completed. A run which was stopped
+    /// by a problem in ``strict()`` mode or by ``line_errors()`` is not cancelled, the problem is reported like in ``run()``,
+    /// see ``try_run()`` to get it back instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+    /// * `line_handler` - A function or closure that will handle each decompressed line.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::{CancellationToken, ZstdLinesConfig};
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_run_cancellable");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"1\n2\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let token = CancellationToken::new();
+    /// let config = ZstdLinesConfig::new().cancel_with(&token);
+    /// assert!(config.run_cancellable([dir.join("a.zst")], |_line, _path| {}).is_ok());
+    ///
+    /// // Stopping at a problem is no cancellation
+    /// std::fs::write(dir.join("corrupt.zst"), b"\x28\xB5\x2F\xFDgarbage").unwrap();
+    /// let strict = ZstdLinesConfig::new().cancel_with(&token).strict(true).report_failures(false);
+    /// assert!(strict.run_cancellable([dir.join("corrupt.zst"), dir.join("a.zst")], |_line, _path| {}).is_ok());
+    ///
+    /// token.cancel();
+    /// let cancelled = config.run_cancellable([dir.join("a.zst")], |_line, _path| {}).unwrap_err();
+    /// assert_eq!(cancelled.not_started, [dir.join("a.zst")]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn run_cancellable<I, F>(&self, paths: I, line_handler: F) -> Result<(), Cancelled>
+    where
+        I: IntoParallelIterator,
+        I::Item: AsRef<Path>,
+        F: Fn(String, &Path) + Sync + Send,
+    {
+        let paths = paths.into_par_iter();
+        let outcomes = self.install(|run| {
+            let line_handler = decoded_sync(run, |line, context| line_handler(line, context.path));
+            schedule::process_files(run, paths, |path| {
+                let outcome = match try_process_file(path, run, &line_handler) {
+                    Err(e) if is_skipped(&e) => Outcome::NotStarted,
+                    Err(e) if is_stopped(&e) => Outcome::Interrupted,
+                    _ => Outcome::Completed,
+                };
+                (path.to_path_buf(), outcome)
+            })
+        });
+        // Files stopped by a problem in strict mode look the same as cancelled ones
+        let cancelled = self
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled);
+        match Cancelled::from_outcomes(outcomes) {
+            Some(cancelled_files) if cancelled => Err(cancelled_files),
+            _ => Ok(()),
+        }
+    }
+
+    /// Like ``run()``, but returning a ``FileReport`` for every file in the order of the paths, telling whether it
+    /// succeeded along with its line, byte and tar entry counts and how long it took.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+    /// * `line_handler` - A function or closure that will handle each decompressed line.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::{EmptyLines, ZstdLinesConfig};
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_run_with_reports");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"1\n\n2\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let reports = ZstdLinesConfig::new()
+    ///     .skip_empty_lines(EmptyLines::SkipEmpty)
+    ///     .run_with_reports([dir.join("a.zst")], |_line, _path| {});
+    /// assert_eq!(reports[0].lines_delivered, 2);
+    /// // Skipped lines are decompressed all the same
+    /// assert_eq!(reports[0].decompressed_bytes, 5);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn run_with_reports<I, F>(&self, paths: I, line_handler: F) -> Vec<FileReport>
+    where
+        I: IntoParallelIterator,
+        I::Item: AsRef<Path>,
+        F: Fn(String, &Path) + Sync + Send,
+    {
+        let paths = paths.into_par_iter();
+        self.install(|run| report::report_files(paths, run, line_handler))
+    }
+
+    /// Like ``run()``, but passing the lines to `line_handler` on the calling thread grouped by file in the order
+    /// of the paths, like ``par_zstd_lines_ordered()``. Decoded files wait in memory until they are delivered,
+    /// decoding is throttled once they hold `max_buffered_bytes`.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+    /// * `max_buffered_bytes` - The number of bytes of decoded lines waiting for delivery at which decoding is throttled.
+    /// * `line_handler` - A function or closure that will handle each decompressed line.
+    pub fn run_ordered<I, F>(&self, paths: I, max_buffered_bytes: usize, line_handler: F)
+    where
+        I: IntoParallelIterator,
+        I::Item: AsRef<Path>,
+        F: FnMut(String, &Path),
+    {
+        ordered::run_ordered(
+            self,
+            paths.into_par_iter(),
+            max_buffered_bytes,
+            line_handler,
+        );
+    }
+
+    /// Like ``run()``, but returning the lines as a rayon ``ParallelIterator`` like ``par_zstd_lines_iter()``.
+    /// The lines of every file are decoded on a single thread, files are processed with all the settings
+    /// of this configuration once the iterator is driven.
+    ///
+    /// # Arguments
+    ///
+    /// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+    ///
+    /// # Example
+    /// ```
+    /// use rayon::prelude::*;
+    /// use zstd_lines::ZstdLinesConfig;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_lines_iter_config");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("a.csv.zst"), zstd::encode_all(&b"count\n1\n2\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let total: u32 = ZstdLinesConfig::new()
+    ///     .skip_lines(1)
+    ///     .lines_iter([dir.join("a.csv.zst")])
+    ///     .map(|(_path, line)| line.parse::<u32>().unwrap())
+    ///     .sum();
+    /// assert_eq!(total, 3);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    pub fn lines_iter<I>(&self, paths: I) -> ZstdLinesIter<'_>
+    where
+        I: IntoParallelIterator,
+        I::Item: AsRef<Path>,
+    {
+        ZstdLinesIter::new(self, paths)
+    }
+}
+
+impl Default for ZstdLinesConfig<'_> {
+    fn default() -> Self {
+        ZstdLinesConfig::new()
+    }
+}
+
+impl fmt::Debug for ZstdLinesConfig<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("ZstdLinesConfig");
+        debug
+            .field("on_skippable_frame", &self.on_skippable_frame.is_some())
+            .field("window_log_max", &self.window_log_max)
+            .field("dictionary", &self.dictionary.is_some())
+            .field("entry_filter", &self.entry_filter.is_some())
+            .field("strip_bom", &self.strip_bom)
+            .field("line_ending", &self.line_ending)
+            .field("delimiter", &self.delimiter)
+            .field("empty_lines", &self.empty_lines)
+            .field("sampling", &self.sampling)
+            .field("skip_lines", &self.skip_lines)
+            .field("take_lines", &self.take_lines)
+            .field("max_line_len", &self.max_line_len)
+            .field("long_lines", &self.long_lines)
+            .field("line_errors", &self.line_errors)
+            .field("line_numbers", &self.line_numbers)
+            .field("buffer_size", &self.buffer_size);
+        #[cfg(feature = "encoding")]
+        debug.field("encoding", &self.encoding.is_some());
+        debug
+            .field("replace_malformed", &self.replace_malformed)
+            .field("replaced_lines", &self.replaced_lines())
+            .field("on_invalid_utf8", &self.on_invalid_utf8.is_some())
+            .field("invalid_lines", &self.invalid_lines())
+            .field("on_file_start", &self.on_file_start.is_some())
+            .field("on_file_end", &self.on_file_end.is_some())
+            .field("report_failures", &self.report_failures)
+            .field("on_error", &self.on_error.is_some())
+            .field("strict", &self.strict)
+            .field("retry", &self.retry)
+            .field("thread_pool", &self.thread_pool)
+            .field("max_concurrent_files", &self.max_concurrent_files)
+            .field("schedule", &self.schedule.is_some())
+            .field("file_timeout", &self.file_timeout)
+            .field("pipe_options", &self.pipe_options)
+            .field(
+                "on_progress",
+                &self.on_progress.as_ref().map(|(every, _)| every),
+            );
+        debug.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn files_compressed_with_another_dictionary_fail_alone() {
+        let dir = test_dir("dictionary");
+        let dictionary = |line: fn(u32) -> String| {
+            let samples: Vec<Vec<u8>> = (0..1000).map(|i| line(i).into_bytes()).collect();
+            zstd::dict::from_samples(&samples, 4096).unwrap()
+        };
+        let dictionary_used = dictionary(|i| {
+            format!(
+                "{{\"user\":{},\"name\":\"user{}\",\"score\":{}}}\n",
+                i,
+                i * 31 % 977,
+                i % 101
+            )
+        });
+        let other_dictionary = dictionary(|i| format!("{}|{}|{}\n", i * 7, i % 13, i * 31 % 977));
+        let content = b"{\"user\":1,\"name\":\"user1\",\"score\":1}\n";
+        for (name, dictionary) in [
+            ("with.zst", &dictionary_used),
+            ("other.zst", &other_dictionary),
+        ] {
+            let mut compressor = zstd::bulk::Compressor::with_dictionary(3, dictionary).unwrap();
+            fs::write(dir.join(name), compressor.compress(content).unwrap()).unwrap();
+        }
+        write_zst(&dir.join("without.zst"), content);
+
+        let lines = Mutex::new(Vec::new());
+        let errors = ZstdLinesConfig::new()
+            .dictionary(&dictionary_used)
+            .report_failures(false)
+            .try_run(
+                ["with.zst", "without.zst", "other.zst"].map(|name| dir.join(name)),
+                |_line, path| lines.lock().unwrap().push(path.to_path_buf()),
+            )
+            .unwrap_err();
+        let mut lines = lines.into_inner().unwrap();
+        lines.sort();
+        assert_eq!(lines, vec![dir.join("with.zst"), dir.join("without.zst")]);
+        assert_eq!(errors.failures.len(), 1);
+        assert_eq!(errors.failures[0].path(), dir.join("other.zst"));
+    }
+
+    #[test]
+    fn byte_order_marks_are_stripped_at_the_start_of_every_file_and_entry() {
+        let dir = test_dir("bom");
+        write_zst(&dir.join("bom.zst"), "\u{feff}a\n\u{feff}b\n".as_bytes());
+        // A first line starting with a different character encoded with similar bytes is left alone
+        write_zst(&dir.join("text.zst"), "\u{fefe}text\n".as_bytes());
+        tar_zst(
+            &dir.join("bom.tar.zst"),
+            &[
+                ("a.txt", "\u{feff}a\n".as_bytes()),
+                ("b.txt", "\u{feff}b\n".as_bytes()),
+            ],
+        );
+
+        let collect = |config: ZstdLinesConfig, name| {
+            let lines = Mutex::new(Vec::new());
+            config.run([dir.join(name)], |line, _path| {
+                lines.lock().unwrap().push(line)
+            });
+            lines.into_inner().unwrap()
+        };
+        assert_eq!(
+            collect(ZstdLinesConfig::new(), "text.zst"),
+            vec!["\u{fefe}text"]
+        );
+        assert_eq!(
+            collect(ZstdLinesConfig::new(), "bom.tar.zst"),
+            vec!["a", "b"]
+        );
+        assert_eq!(
+            collect(ZstdLinesConfig::new().strip_bom(false), "bom.zst"),
+            vec!["\u{feff}a", "\u{feff}b"]
+        );
+    }
+
+    #[test]
+    fn line_endings_are_trimmed_the_same_in_files_and_tar_entries() {
+        let dir = test_dir("line_ending");
+        let collect = |line_ending, path: &Path| {
+            let lines = Mutex::new(Vec::new());
+            ZstdLinesConfig::new()
+                .line_ending(line_ending)
+                .run([path], |line, _path| lines.lock().unwrap().push(line));
+            lines.into_inner().unwrap()
+        };
+
+        // Every combination of LF, CRLF, trailing whitespace and a missing final line break
+        let endings = ["\n", "\r\n", " \t\r\n", "\r\r\n", "\r", ""];
+        for first in endings {
+            for last in endings {
+                let content = format!("a{}b{}", first, last);
+                write_zst(&dir.join("plain.zst"), content.as_bytes());
+                tar_zst(&dir.join("entry.tar.zst"), &[("a.txt", content.as_bytes())]);
+                for line_ending in [
+                    LineEnding::KeepCr,
+                    LineEnding::StripCr,
+                    LineEnding::TrimWhitespace,
+                ] {
+                    assert_eq!(
+                        collect(line_ending, &dir.join("plain.zst")),
+                        collect(line_ending, &dir.join("entry.tar.zst")),
+                        "{:?} {:?}",
+                        content,
+                        line_ending
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn delimiters_split_tar_entries_like_files() {
+        let dir = test_dir("delimiter");
+        tar_zst(
+            &dir.join("files.tar.zst"),
+            &[("files.txt", b"./a.txt\0./with\nnewline\r\0./last")],
+        );
+
+        let records = Mutex::new(Vec::new());
+        ZstdLinesConfig::new()
+            .delimiter(0)
+            .run([dir.join("files.tar.zst")], |record, _path| {
+                records.lock().unwrap().push(record)
+            });
+        assert_eq!(
+            records.into_inner().unwrap(),
+            vec!["./a.txt", "./with\nnewline\r", "./last"]
+        );
+    }
+
+    #[test]
+    fn line_ranges_apply_to_every_tar_entry_on_its_own() {
+        let dir = test_dir("line_ranges");
+        tar_zst(
+            &dir.join("entries.tar.zst"),
+            &[("a.txt", b"a1\na2\na3\na4\n"), ("b.txt", b"b1\nb2\nb3\n")],
+        );
+        let collect = |config: ZstdLinesConfig| {
+            let lines = Mutex::new(Vec::new());
+            config.run([dir.join("entries.tar.zst")], |line, _path| {
+                lines.lock().unwrap().push(line)
+            });
+            lines.into_inner().unwrap()
+        };
+
+        assert_eq!(
+            collect(ZstdLinesConfig::new().skip_lines(1).take_lines(2)),
+            vec!["a2", "a3", "b2", "b3"]
+        );
+        assert!(collect(ZstdLinesConfig::new().take_lines(0)).is_empty());
+    }
+
+    #[test]
+    fn long_lines_can_abort_the_file() {
+        let dir = test_dir("long_lines");
+        write_zst(
+            &dir.join("dump.zst"),
+            format!("short\n{}\nnext\n", "x".repeat(100_000)).as_bytes(),
+        );
+
+        let lines = Mutex::new(Vec::new());
+        let errors = ZstdLinesConfig::new()
+            .max_line_len(1000)
+            .long_lines(LongLines::Abort)
+            .report_failures(false)
+            .try_run([dir.join("dump.zst")], |line, _path| {
+                lines.lock().unwrap().push(line)
+            })
+            .unwrap_err();
+        assert_eq!(lines.into_inner().unwrap(), vec!["short"]);
+        assert!(matches!(
+            &errors.failures[..],
+            [ZstdLinesError::Decode { source, .. }] if source.kind() == io::ErrorKind::InvalidData
+        ));
+    }
+
+    #[test]
+    fn runs_from_tasks_of_any_pool_finish() {
+        use rayon::ThreadPoolBuilder;
+
+        let dir = test_dir("thread_pool");
+        write_zst(&dir.join("a.zst"), b"1\n2\n");
+        write_zst(&dir.join("b.zst"), b"3\n");
+        let pool = ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+        let config = ZstdLinesConfig::new().thread_pool(&pool);
+        let count = |paths: &[PathBuf]| {
+            let lines = AtomicUsize::new(0);
+            config.run(paths, |_line, _path| {
+                lines.fetch_add(1, Ordering::Relaxed);
+            });
+            lines.into_inner()
+        };
+
+        // Tasks of another pool, even one with a single thread, wait for the files without deadlocking
+        let other = ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        let lines = other.install(|| {
+            (0..4)
+                .into_par_iter()
+                .map(|_| count(&[dir.join("a.zst")]))
+                .sum::<usize>()
+        });
+        assert_eq!(lines, 8);
+        // As do tasks of its own pool
+        assert_eq!(
+            pool.install(|| count(&[dir.join("a.zst"), dir.join("b.zst")])),
+            3
+        );
+    }
+
+    #[test]
+    fn max_concurrent_files_bounds_the_files_in_flight() {
+        use rayon::ThreadPoolBuilder;
+
+        let dir = test_dir("max_concurrent_files");
+        let content: String = (0..10_000).map(|i| format!("{}\n", i)).collect();
+        let files: Vec<_> = (0..8)
+            .map(|i| dir.join(format!("part-{}.zst", i)))
+            .collect();
+        for file in &files {
+            write_zst(file, content.as_bytes());
+        }
+
+        let in_flight = AtomicUsize::new(0);
+        let most_in_flight = AtomicUsize::new(0);
+        let pool = ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        ZstdLinesConfig::new()
+            .thread_pool(&pool)
+            .max_concurrent_files(2)
+            .on_file_start(|_path| {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                most_in_flight.fetch_max(now, Ordering::SeqCst);
+            })
+            .on_file_end(|_path, _result| {
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+            })
+            .run(&files, |_line, _path| {});
+        assert!(most_in_flight.into_inner() <= 2);
+    }
+
+    /// Create a named pipe at `path` fed with `chunks` by a writer thread, which opens the pipe anew for every chunk
+    /// and sleeps for an hour after the last one if `hang` is set.
+    #[cfg(unix)]
+    fn feed_fifo(path: &Path, chunks: Vec<Vec<u8>>, hang: bool) {
+        use std::io::Write;
+        use std::time::Duration;
+
+        std::process::Command::new("mkfifo")
+            .arg(path)
+            .status()
+            .unwrap();
+        let path = path.to_path_buf();
+        std::thread::spawn(move || {
+            let mut pipe = None;
+            for chunk in chunks {
+                pipe = Some(fs::OpenOptions::new().write(true).open(&path).unwrap());
+                pipe.as_mut().unwrap().write_all(&chunk).unwrap();
+                std::thread::sleep(Duration::from_millis(100));
+                if !hang {
+                    pipe = None;
+                }
+            }
+            if hang {
+                std::thread::sleep(Duration::from_secs(3600));
+            }
+            drop(pipe);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stalled_reads_time_out() {
+        use std::time::Duration;
+
+        let dir = test_dir("file_timeout");
+        let content: String = (0..100_000).map(|i| format!("{}\n", i)).collect();
+        let compressed =
+            zstd::encode_all(&tar_archive(&[("numbers.txt", content.as_bytes())])[..], 0).unwrap();
+        // A named pipe stands in for a disk which hangs halfway through the archive
+        let path = dir.join("stalled.tar.zst");
+        let half = compressed.len() / 2;
+        feed_fifo(&path, vec![compressed[..half].to_vec()], true);
+
+        let errors = ZstdLinesConfig::new()
+            .report_failures(false)
+            .file_timeout(Duration::from_millis(200))
+            .try_run([&path], |_line, _path| {})
+            .unwrap_err();
+        assert!(matches!(
+            &errors.failures[0],
+            ZstdLinesError::Timeout { bytes_read, .. } if *bytes_read == half as u64
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pipe_writers_which_restart_in_time_do_not_truncate_the_stream() {
+        use std::time::Duration;
+
+        let dir = test_dir("pipe_options");
+        let content: String = (0..10_000).map(|i| format!("{}\n", i)).collect();
+        let compressed = zstd::encode_all(content.as_bytes(), 0).unwrap();
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+        let path = dir.join("out.jsonl.zst");
+        feed_fifo(&path, vec![first.to_vec(), second.to_vec()], false);
+
+        let lines = AtomicUsize::new(0);
+        ZstdLinesConfig::new()
+            .pipe_options(PipeOptions {
+                eof_retry_window: Duration::from_secs(1),
+                ..PipeOptions::default()
+            })
+            .try_run([&path], |_line, _path| {
+                lines.fetch_add(1, Ordering::Relaxed);
+            })
+            .unwrap();
+        assert_eq!(lines.into_inner(), 10_000);
+    }
+
+    #[test]
+    fn malformed_lines_of_tar_entries_are_replaced_and_counted() {
+        let dir = test_dir("utf8_lossy");
+        tar_zst(
+            &dir.join("comments.tar.zst"),
+            &[("comments.jsonl", b"{\"a\":1}\n{\"body\":\"caf\xE9\"}\n")],
+        );
+
+        let config = ZstdLinesConfig::new().utf8_lossy(true);
+        for _ in 0..2 {
+            let lines = Mutex::new(Vec::new());
+            config.run([dir.join("comments.tar.zst")], |line, _path| {
+                lines.lock().unwrap().push(line)
+            });
+            assert_eq!(
+                lines.into_inner().unwrap(),
+                vec![r#"{"a":1}"#, "{\"body\":\"caf\u{FFFD}\"}"]
+            );
+        }
+        // Lines of every run are counted
+        assert_eq!(config.replaced_lines(), 2);
+    }
+
+    #[test]
+    fn a_problem_only_stops_its_own_run() {
+        let dir = test_dir("strict_runs");
+        write_zst(&dir.join("a.zst"), b"ok\n\xFF\nnever\n");
+        write_zst(&dir.join("b.zst"), b"1\n2\n");
+
+        let config = ZstdLinesConfig::new()
+            .strict(true)
+            .on_error(|_path, _error| {});
+        assert!(config
+            .try_run([dir.join("a.zst")], |_line, _path| {})
+            .is_err());
+        let lines = AtomicUsize::new(0);
+        config
+            .try_run([dir.join("b.zst")], |_line, _path| {
+                lines.fetch_add(1, Ordering::Relaxed);
+            })
+            .unwrap();
+        assert_eq!(lines.into_inner(), 2);
+    }
+}
@@ -0,0 +1,49 @@
+//! Where a line comes from, passed along with every line.
+
+use crate::text::LineDecoding;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Where a line comes from, passed to handlers of ``par_zstd_lines_ctx()`` along with the line itself.
+/// It is borrowed for the duration of the call, copy out whatever is needed afterwards.
+/// ``shared_path`` can be kept beyond the call by cloning it, which only bumps a reference count.
+///
+/// # Example
+/// ```
+/// use zstd_lines::ParZstdLines;
+/// use std::path::Path;
+/// use std::sync::{Arc, Mutex};
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_shared_path");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("dump.jsonl.zst"), zstd::encode_all(&b"first\nsecond\n"[..], 0).unwrap()).unwrap();
+///
+/// let lines: Mutex<Vec<(Arc<Path>, String)>> = Mutex::new(Vec::new());
+/// [dir.join("dump.jsonl.zst")].par_zstd_lines_ctx(|line, ctx| {
+///     lines.lock().unwrap().push((Arc::clone(ctx.shared_path), line));
+/// });
+/// let lines = lines.into_inner().unwrap();
+/// assert_eq!(&*lines[0].0, dir.join("dump.jsonl.zst"));
+/// // Every line of the file shares the same path
+/// assert!(Arc::ptr_eq(&lines[0].0, &lines[1].0));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct LineContext<'a> {
+    /// The file the line was read from, or the label of a stream.
+    pub path: &'a Path,
+    /// The same path, created once per file, for handlers which need to keep it. Cloning it does not copy the path.
+    pub shared_path: &'a Arc<Path>,
+    /// Number of the line within its file or archive entry, starting at 1. Lines skipped as empty are counted as well.
+    pub line_number: u64,
+    /// Offset of the first byte of the line in the decompressed content of its file or archive entry.
+    pub byte_offset: u64,
+    /// Name of the tar entry or zip member the line belongs to, ``None`` for plain files.
+    pub tar_entry: Option<&'a str>,
+    /// The line is the last one of a truncated stream and was cut off where the stream ends, so it may be incomplete.
+    pub partial: bool,
+    /// Number of the attempt at the file which delivers the line, starting at 1, see ``ZstdLinesConfig::retry()``.
+    pub attempt: u32,
+    /// How the lines of the file or archive entry are decoded, resolved once before its first line.
+    pub(crate) decoding: LineDecoding,
+}
@@ -0,0 +1,86 @@
+//! Counting the lines of zstd compressed files without splitting them into lines.
+
+use crate::config::DEFAULT_CONFIG;
+use crate::process_file_streams;
+use rayon::prelude::*;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+/// Count the lines in zstd compressed files in parallel, e.g. for progress bars or capacity planning.
+/// The decompressed content is scanned for line breaks in place, no line is ever copied or decoded.
+/// Every line of the decompressed content is counted, including a last line without a line break and the lines
+/// ``par_zstd_lines()`` skips, e.g. lines which are not valid UTF-8, so a count can exceed the lines delivered.
+/// Tar entries and zip members are counted by their content only.
+/// Counts are returned in the order of the paths, along with the error a file failed with instead of reporting it.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+///
+/// # Example
+/// ```
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_count_lines");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"1\n2\n3"[..], 0).unwrap()).unwrap();
+/// # let mut builder = tar::Builder::new(Vec::new());
+/// # for (name, content) in [("x.jsonl", &b"x\n"[..]), ("y.jsonl", &b"y\ny\n"[..])] {
+/// #     let mut header = tar::Header::new_gnu();
+/// #     header.set_size(content.len() as u64);
+/// #     header.set_cksum();
+/// #     builder.append_data(&mut header, name, content).unwrap();
+/// # }
+/// // An archive with two entries of 1 and 2 lines
+/// std::fs::write(dir.join("b.tar.zst"), zstd::encode_all(&builder.into_inner().unwrap()[..], 0).unwrap()).unwrap();
+///
+/// let counts = zstd_lines::par_zstd_count_lines([dir.join("a.zst"), dir.join("b.tar.zst"), dir.join("missing.zst")]);
+/// assert_eq!(counts[0].1.as_ref().unwrap(), &3);
+/// assert_eq!(counts[1].1.as_ref().unwrap(), &3);
+/// assert!(counts[2].1.is_err());
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_count_lines<I>(paths: I) -> Vec<(PathBuf, io::Result<u64>)>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+{
+    paths
+        .into_par_iter()
+        .map(|path| {
+            let path = path.as_ref();
+            (path.to_path_buf(), count_file(path))
+        })
+        .collect()
+}
+
+/// Count the lines of a single file, summing up the lines of all tar entries and zip members.
+fn count_file(path: &Path) -> io::Result<u64> {
+    let config = DEFAULT_CONFIG.start_run();
+    let mut lines = 0;
+    process_file_streams(path, &config, |reader, _entry| {
+        lines += count_stream(reader)?;
+        Ok(())
+    })?;
+    Ok(lines)
+}
+
+/// Count the line breaks in a stream, plus a last line without one.
+fn count_stream(reader: &mut dyn BufRead) -> io::Result<u64> {
+    let mut lines = 0;
+    let mut last = b'\n';
+    loop {
+        let buffer = match reader.fill_buf() {
+            Ok([]) => break,
+            Ok(buffer) => buffer,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        lines += buffer.iter().filter(|&&byte| byte == b'\n').count() as u64;
+        last = buffer[buffer.len() - 1];
+        let length = buffer.len();
+        reader.consume(length);
+    }
+    if last != b'\n' {
+        lines += 1;
+    }
+    Ok(lines)
+}
@@ -0,0 +1,210 @@
+//! Reading CSV records with the csv crate, available with the `csv` feature.
+
+use crate::config::DEFAULT_CONFIG;
+use crate::diagnostics;
+use crate::{process_file_streams, report_failure};
+use rayon::prelude::*;
+use serde::de::DeserializeOwned;
+use std::io;
+use std::path::Path;
+
+/// How the decompressed content of CSV files is parsed by ``par_zstd_csv_records()`` and ``par_zstd_csv_deserialize()``.
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    /// The field delimiter, a comma by default.
+    pub delimiter: u8,
+    /// Whether the first record of each file or archive entry is a header row, true by default.
+    /// Header rows are not passed to the handler, but are used to match fields by name when deserializing.
+    pub has_headers: bool,
+    /// Whether records may have a different number of fields than the first record, false by default.
+    pub flexible: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: b',',
+            has_headers: true,
+            flexible: false,
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Create a reader on the decompressed content of a file or archive entry with these options.
+    fn reader<R: io::Read>(&self, reader: R) -> csv::Reader<R> {
+        csv::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .has_headers(self.has_headers)
+            .flexible(self.flexible)
+            .from_reader(reader)
+    }
+}
+
+/// Process each record in zstd compressed CSV files in parallel.
+/// Every file, and every entry of a tar or zip archive, is read by its own CSV reader, so quoted fields may span lines
+/// and each entry may start with its own header row.
+/// Records which cannot be parsed are reported on stderr and skipped, read errors end the file like in ``par_zstd_lines()``.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `options` - How the records should be parsed.
+/// * `record_handler` - A function or closure that will handle each record.
+///
+/// # Example
+/// ```
+/// use zstd_lines::CsvOptions;
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_csv_records");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let content = b"id;comment\n1;\"first\nsecond\"\n2;plain\n";
+/// std::fs::write(dir.join("comments.csv.zst"), zstd::encode_all(&content[..], 0).unwrap()).unwrap();
+///
+/// let records = Mutex::new(Vec::new());
+/// let options = CsvOptions { delimiter: b';', ..CsvOptions::default() };
+/// zstd_lines::par_zstd_csv_records([dir.join("comments.csv.zst")], &options, |record, _path| {
+///     records.lock().unwrap().push(record.iter().map(str::to_string).collect::<Vec<_>>());
+/// });
+/// assert_eq!(records.into_inner().unwrap(), vec![
+///     vec!["1".to_string(), "first\nsecond".to_string()],
+///     vec!["2".to_string(), "plain".to_string()],
+/// ]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_csv_records<I, F>(paths: I, options: &CsvOptions, record_handler: F)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(csv::StringRecord, &Path) + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    paths.into_par_iter().for_each(|path| {
+        let path = path.as_ref();
+        let result = process_file_streams(path, &config, |reader, entry| {
+            let mut reader = options.reader(reader);
+            for record in reader.records() {
+                if let Some(record) = parsed(record, path, entry)? {
+                    record_handler(record, path);
+                }
+            }
+            Ok(())
+        });
+        report_failure(path, &result);
+    });
+}
+
+/// Like ``par_zstd_csv_records()``, but deserializing each record into a `T` on the worker thread.
+/// With headers, fields are matched to struct fields by name, otherwise by position.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `options` - How the records should be parsed.
+/// * `record_handler` - A function or closure that will handle each deserialized record.
+///
+/// # Example
+/// ```
+/// use zstd_lines::CsvOptions;
+/// use std::collections::HashMap;
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_csv_deserialize");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("scores.csv.zst"), zstd::encode_all(&b"name,score\nalice,3\n"[..], 0).unwrap()).unwrap();
+///
+/// let scores = Mutex::new(Vec::new());
+/// zstd_lines::par_zstd_csv_deserialize(
+///     [dir.join("scores.csv.zst")],
+///     &CsvOptions::default(),
+///     |row: HashMap<String, String>, _path| scores.lock().unwrap().push((row["name"].clone(), row["score"].clone())),
+/// );
+/// assert_eq!(scores.into_inner().unwrap(), vec![("alice".to_string(), "3".to_string())]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_csv_deserialize<I, T, F>(paths: I, options: &CsvOptions, record_handler: F)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    T: DeserializeOwned,
+    F: Fn(T, &Path) + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    paths.into_par_iter().for_each(|path| {
+        let path = path.as_ref();
+        let result = process_file_streams(path, &config, |reader, entry| {
+            let mut reader = options.reader(reader);
+            for record in reader.deserialize() {
+                if let Some(record) = parsed(record, path, entry)? {
+                    record_handler(record, path);
+                }
+            }
+            Ok(())
+        });
+        report_failure(path, &result);
+    });
+}
+
+/// Pass on a parsed record, reporting records which cannot be parsed on stderr and ending the stream on read errors.
+fn parsed<T>(record: csv::Result<T>, path: &Path, entry: Option<&str>) -> io::Result<Option<T>> {
+    match record {
+        Ok(record) => Ok(Some(record)),
+        Err(e) if e.is_io_error() => Err(io::Error::from(e)),
+        Err(e) => {
+            let message = match entry {
+                Some(entry) => format!(
+                    "Error parsing CSV record from {} in {}: {}",
+                    entry,
+                    path.display(),
+                    e
+                ),
+                None => format!("Error parsing CSV record from {}: {}", path.display(), e),
+            };
+            diagnostics::report_message(path, &message);
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[test]
+    fn every_tar_entry_has_its_own_header_row() {
+        let dir = test_dir("csv_entries");
+        // The columns are in a different order, and a quoted field spans two lines
+        tar_zst(
+            &dir.join("scores.tar.zst"),
+            &[
+                ("a.csv", b"name,score\nalice,3\n"),
+                ("b.csv", b"score,name\n5,\"bob\nby\"\n"),
+            ],
+        );
+
+        let scores = Mutex::new(Vec::new());
+        par_zstd_csv_deserialize(
+            [dir.join("scores.tar.zst")],
+            &CsvOptions::default(),
+            |row: HashMap<String, String>, _path| {
+                scores
+                    .lock()
+                    .unwrap()
+                    .push((row["name"].clone(), row["score"].clone()))
+            },
+        );
+        let mut scores = scores.into_inner().unwrap();
+        scores.sort();
+        assert_eq!(
+            scores,
+            vec![
+                ("alice".to_string(), "3".to_string()),
+                ("bob\nby".to_string(), "5".to_string())
+            ]
+        );
+    }
+}
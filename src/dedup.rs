@@ -0,0 +1,181 @@
+//! Delivering every distinct line only once across a whole batch of files.
+
+use crate::config::DEFAULT_CONFIG;
+use crate::context::LineContext;
+use crate::process_file;
+use crate::text::decode_line;
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::hash::{DefaultHasher, Hasher};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Number of independently locked parts of the set of seen lines, so threads rarely wait for each other.
+const SHARDS: usize = 64;
+
+/// Options for ``par_zstd_lines_dedup()``.
+#[derive(Debug, Clone)]
+pub struct DedupOptions {
+    /// The maximum number of distinct lines remembered, unlimited by default.
+    /// Every remembered line takes about 32 bytes of memory, whatever its length. Once the limit is reached,
+    /// lines which were not seen before are still delivered but not remembered, so later copies of them are delivered as well.
+    pub max_tracked: usize,
+}
+
+impl Default for DedupOptions {
+    fn default() -> Self {
+        DedupOptions {
+            max_tracked: usize::MAX,
+        }
+    }
+}
+
+/// How many lines ``par_zstd_lines_dedup()`` delivered and left out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DedupSummary {
+    /// Number of lines seen for the first time, including lines which were left out because they are not valid UTF-8.
+    pub unique_lines: u64,
+    /// Number of lines which were left out because the same line was seen before.
+    pub duplicate_lines: u64,
+    /// Number of lines delivered without being remembered, after ``DedupOptions::max_tracked`` was reached.
+    pub untracked_lines: u64,
+}
+
+/// The hashes of all lines seen so far, split into shards.
+struct SeenLines {
+    shards: Vec<Mutex<HashSet<u128>>>,
+    tracked: AtomicU64,
+    max_tracked: u64,
+    duplicates: AtomicU64,
+    untracked: AtomicU64,
+}
+
+impl SeenLines {
+    fn new(options: &DedupOptions) -> Self {
+        SeenLines {
+            shards: (0..SHARDS).map(|_| Mutex::new(HashSet::new())).collect(),
+            tracked: AtomicU64::new(0),
+            max_tracked: options.max_tracked as u64,
+            duplicates: AtomicU64::new(0),
+            untracked: AtomicU64::new(0),
+        }
+    }
+
+    /// Check whether a line is delivered, i.e. it was not seen before, and remember it.
+    fn first_sight(&self, line: &[u8]) -> bool {
+        let hash = hash_line(line);
+        let mut shard = self.shards[hash as usize % SHARDS].lock().unwrap();
+        if shard.contains(&hash) {
+            self.duplicates.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        // Reserving a place and checking the limit is a single step, so threads of other shards never go past it together
+        let reserved = self
+            .tracked
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |tracked| {
+                (tracked < self.max_tracked).then_some(tracked + 1)
+            });
+        if reserved.is_ok() {
+            shard.insert(hash);
+        } else {
+            self.untracked.fetch_add(1, Ordering::Relaxed);
+        }
+        true
+    }
+
+    fn summary(self) -> DedupSummary {
+        DedupSummary {
+            unique_lines: self.tracked.into_inner(),
+            duplicate_lines: self.duplicates.into_inner(),
+            untracked_lines: self.untracked.into_inner(),
+        }
+    }
+}
+
+/// Hash the bytes of a line to 128 bits from two runs of the standard hasher, one of them with a prefix byte.
+/// The algorithm of the standard hasher may change between Rust releases, hashes only need to match within one call.
+fn hash_line(line: &[u8]) -> u128 {
+    let mut low = DefaultHasher::new();
+    low.write(line);
+    let mut high = DefaultHasher::new();
+    high.write_u8(0xFF);
+    high.write(line);
+    u128::from(high.finish()) << 64 | u128::from(low.finish())
+}
+
+/// Process each distinct line in zstd compressed files in parallel only once, however often it is repeated in the same or other files.
+/// Which copy of a line is delivered, and thus the path it comes with, depends on which file gets to it first.
+///
+/// Lines are compared by a 128-bit hash of their bytes, before they are decoded, so copies of lines which are not valid UTF-8
+/// are only reported once. Two different lines are only mistaken for copies of each other if their hashes collide, which is
+/// about as likely as 1 in 10^20 for a billion distinct lines. Only the hashes are kept in memory, see ``DedupOptions::max_tracked``
+/// to limit how many.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `options` - How many distinct lines are remembered.
+/// * `line_handler` - A function or closure that will handle each distinct decompressed line.
+///
+/// # Example
+/// ```
+/// use zstd_lines::DedupOptions;
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_dedup");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("2024-01.zst"), zstd::encode_all(&b"a\nb\na\n\xFF\n"[..], 0).unwrap()).unwrap();
+/// std::fs::write(dir.join("2024-02.zst"), zstd::encode_all(&b"b\nc\n\xFF\n"[..], 0).unwrap()).unwrap();
+///
+/// let lines = Mutex::new(Vec::new());
+/// let summary = zstd_lines::par_zstd_lines_dedup(
+///     [dir.join("2024-01.zst"), dir.join("2024-02.zst")],
+///     &DedupOptions::default(),
+///     |line, _path| lines.lock().unwrap().push(line),
+/// );
+/// let mut lines = lines.into_inner().unwrap();
+/// lines.sort();
+/// assert_eq!(lines, vec!["a", "b", "c"]);
+/// // The invalid line is reported once, its copy is left out before it is decoded
+/// assert_eq!(summary.unique_lines, 4);
+/// assert_eq!(summary.duplicate_lines, 3);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_dedup<I, F>(paths: I, options: &DedupOptions, line_handler: F) -> DedupSummary
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    let seen = SeenLines::new(options);
+    let line_handler = |buffer: &mut Vec<u8>, context: &LineContext<'_>| {
+        if seen.first_sight(buffer) {
+            if let Some(line) = decode_line(buffer, context, &config) {
+                line_handler(line, context.path);
+            }
+        }
+    };
+    paths.into_par_iter().for_each(|path| {
+        process_file(path.as_ref(), &config, &line_handler);
+    });
+    seen.summary()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn concurrent_lines_never_go_past_max_tracked() {
+        let seen = SeenLines::new(&DedupOptions { max_tracked: 100 });
+        (0..1_000u32).into_par_iter().for_each(|line| {
+            assert!(seen.first_sight(&line.to_le_bytes()));
+        });
+        let summary = seen.summary();
+        assert_eq!(summary.unique_lines, 100);
+        assert_eq!(summary.untracked_lines, 900);
+        assert_eq!(summary.duplicate_lines, 0);
+    }
+}
@@ -0,0 +1,69 @@
+//! Telling about files being processed, as ``tracing`` events and spans with the `tracing` feature or on stderr without it.
+
+use crate::config::FileStats;
+use crate::error::ZstdLinesError;
+use std::io;
+use std::path::Path;
+
+/// Entered while a file is processed on the thread which started it.
+#[cfg(feature = "tracing")]
+pub(crate) type FileSpan = tracing::span::EnteredSpan;
+
+/// Files have no span without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct FileSpan;
+
+/// Enter the span of a file, ranges of files in the zstd seekable format decoded on other threads are outside of it.
+#[cfg(feature = "tracing")]
+pub(crate) fn file_span(path: &Path) -> FileSpan {
+    tracing::info_span!("zstd_lines_file", path = %path.display()).entered()
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn file_span(_path: &Path) -> FileSpan {
+    FileSpan
+}
+
+/// Tell that a file is started.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn file_started(path: &Path) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(path = %path.display(), "file started");
+}
+
+/// Tell that a file is finished, successfully or not.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn file_finished(path: &Path, stats: FileStats, result: &io::Result<()>) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        path = %path.display(),
+        lines = stats.lines,
+        bytes = stats.bytes,
+        failed = result.is_err(),
+        "file finished"
+    );
+}
+
+/// Tell that a file failed and is processed again, only as an event with the `tracing` feature.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn file_retried(path: &Path, attempt: u32, error: &io::Error) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(path = %path.display(), attempt, "retrying after {}", error);
+}
+
+/// Report a problem as a warning event, or on stderr without the `tracing` feature.
+pub(crate) fn report(error: &ZstdLinesError) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(path = %error.path().display(), kind = error.kind(), "{}", error);
+    #[cfg(not(feature = "tracing"))]
+    eprintln!("{}", error);
+}
+
+/// Report a message with the path it belongs to, for problems which are not a ``ZstdLinesError``.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) fn report_message(path: &Path, message: &str) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(path = %path.display(), "{}", message);
+    #[cfg(not(feature = "tracing"))]
+    eprintln!("{}", message);
+}
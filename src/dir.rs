@@ -0,0 +1,177 @@
+//! Recursive discovery of .zst files below a directory.
+
+use crate::diagnostics;
+use crate::par_zstd_lines_from;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Options controlling how ``par_zstd_lines_in_dir()`` walks a directory tree.
+#[derive(Debug, Clone, Default)]
+pub struct DirOptions {
+    /// Maximum depth to descend to. Files directly inside the root are at depth 1, ``None`` means unlimited.
+    pub max_depth: Option<usize>,
+    /// Whether to follow symbolic links to files and directories.
+    pub follow_symlinks: bool,
+    /// Whether to include files and directories whose name starts with a dot.
+    pub include_hidden: bool,
+}
+
+/// Recursively discover all files ending in .zst below `root` and process them line-by-line and in parallel.
+/// Directories that cannot be read are reported the same way as files that fail to process.
+///
+/// # Arguments
+///
+/// * `root` - The directory to start discovery from.
+/// * `options` - Controls the maximum depth, symlink handling and hidden files.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use zstd_lines::DirOptions;
+/// use std::sync::Mutex;
+///
+/// # let root = std::env::temp_dir().join("zstd_lines_doc_dir");
+/// # let _ = std::fs::remove_dir_all(&root);
+/// # std::fs::create_dir_all(root.join("2023")).unwrap();
+/// # std::fs::write(root.join("a.jsonl.zst"), zstd::encode_all(&b"a\n"[..], 0).unwrap()).unwrap();
+/// # std::fs::write(root.join("2023/b.jsonl.zst"), zstd::encode_all(&b"b\n"[..], 0).unwrap()).unwrap();
+/// let lines = Mutex::new(Vec::new());
+/// zstd_lines::par_zstd_lines_in_dir(&root, &DirOptions::default(), |line, _path| {
+///     lines.lock().unwrap().push(line);
+/// });
+/// let mut lines = lines.into_inner().unwrap();
+/// lines.sort();
+/// assert_eq!(lines, vec!["a", "b"]);
+/// # std::fs::remove_dir_all(&root).unwrap();
+/// ```
+pub fn par_zstd_lines_in_dir<P, F>(root: P, options: &DirOptions, line_handler: F)
+where
+    P: AsRef<Path>,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    let files = discover_zstd_files(root.as_ref(), options);
+    par_zstd_lines_from(files, line_handler);
+}
+
+/// Walk the directory tree below `root` and collect all .zst files according to `options`.
+fn discover_zstd_files(root: &Path, options: &DirOptions) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    let mut pending = vec![(root.to_path_buf(), 0)];
+
+    while let Some((dir, depth)) = pending.pop() {
+        if options.follow_symlinks {
+            // Guard against symlink loops by remembering every directory we entered
+            if let Ok(canonical) = fs::canonicalize(&dir) {
+                if !visited.insert(canonical) {
+                    continue;
+                }
+            }
+        }
+        if let Err(e) = visit_dir(&dir, depth, options, &mut files, &mut pending) {
+            let message = format!("Failed to read directory {}: {}", dir.display(), e);
+            diagnostics::report_message(&dir, &message);
+        }
+    }
+
+    files
+}
+
+/// Read a single directory, collecting .zst files and queueing subdirectories.
+fn visit_dir(
+    dir: &Path,
+    depth: usize,
+    options: &DirOptions,
+    files: &mut Vec<PathBuf>,
+    pending: &mut Vec<(PathBuf, usize)>,
+) -> io::Result<()> {
+    let depth = depth + 1;
+    if options.max_depth.is_some_and(|max_depth| depth > max_depth) {
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                let message = format!("Failed to read directory entry in {}: {}", dir.display(), e);
+                diagnostics::report_message(dir, &message);
+                continue;
+            }
+        };
+        let path = entry.path();
+        if !options.include_hidden && is_hidden(&path) {
+            continue;
+        }
+
+        let file_type = match entry.file_type() {
+            Ok(file_type) if file_type.is_symlink() && options.follow_symlinks => {
+                match fs::metadata(&path) {
+                    Ok(metadata) => metadata.file_type(),
+                    Err(e) => {
+                        let message = format!("Failed to follow symlink {}: {}", path.display(), e);
+                        diagnostics::report_message(&path, &message);
+                        continue;
+                    }
+                }
+            }
+            Ok(file_type) => file_type,
+            Err(e) => {
+                let message = format!("Failed to read file type of {}: {}", path.display(), e);
+                diagnostics::report_message(&path, &message);
+                continue;
+            }
+        };
+
+        if file_type.is_dir() {
+            pending.push((path, depth));
+        } else if file_type.is_file() && path.extension().is_some_and(|ext| ext == "zst") {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Check whether the final component of a path starts with a dot.
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn discovery_stops_at_the_maximum_depth_and_skips_other_files() {
+        let root = test_dir("dir");
+        fs::create_dir_all(root.join("2023/01")).unwrap();
+        write_zst(&root.join("a.zst"), b"a\n");
+        write_zst(&root.join("2023/b.zst"), b"b\n");
+        write_zst(&root.join("2023/01/c.zst"), b"c\n");
+        fs::write(root.join("2023/01/notes.txt"), b"ignored\n").unwrap();
+        let collect = |options: &DirOptions| {
+            let lines = Mutex::new(Vec::new());
+            par_zstd_lines_in_dir(&root, options, |line, _path| {
+                lines.lock().unwrap().push(line)
+            });
+            let mut lines = lines.into_inner().unwrap();
+            lines.sort();
+            lines
+        };
+
+        assert_eq!(collect(&DirOptions::default()), vec!["a", "b", "c"]);
+        assert_eq!(
+            collect(&DirOptions {
+                max_depth: Some(1),
+                ..DirOptions::default()
+            }),
+            vec!["a"]
+        );
+    }
+}
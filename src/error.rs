@@ -0,0 +1,740 @@
+//! The problems reported while processing files, passed to ``ZstdLinesConfig::on_error()``.
+
+use crate::checksum::{checksum_error, ChecksumMismatch};
+use crate::line_errors::{Abandoned, LineErrors};
+use crate::{is_stdin, is_tar_path, is_zip_file};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// A problem found while processing a file, telling where it happened and what went wrong.
+/// Displays as the message reported on stderr when no ``ZstdLinesConfig::on_error()`` callback is set.
+///
+/// # Example
+/// ```
+/// use zstd_lines::{ZstdLinesConfig, ZstdLinesError};
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_error");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// // A zstd magic number followed by garbage
+/// std::fs::write(dir.join("corrupt.zst"), b"\x28\xB5\x2F\xFDgarbage").unwrap();
+///
+/// let files = [dir.join("missing.zst"), dir.join("corrupt.zst")];
+/// let errors = ZstdLinesConfig::new()
+///     .report_failures(false)
+///     .try_run(&files, |_line, _path| {})
+///     .unwrap_err();
+/// assert!(matches!(&errors.failures[0], ZstdLinesError::Open { source, .. } if source.kind() == std::io::ErrorKind::NotFound));
+/// assert!(matches!(&errors.failures[1], ZstdLinesError::Decode { offset: Some(0), .. }));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ZstdLinesError {
+    /// The file could not be opened and was skipped.
+    Open { path: PathBuf, source: io::Error },
+    /// The file could not be decompressed or read to the end and was abandoned, lines in front of the failure were delivered.
+    Decode {
+        path: PathBuf,
+        /// Offset in the decompressed content of the file up to which it was decoded, if known.
+        offset: Option<u64>,
+        source: io::Error,
+    },
+    /// The zstd stream ends in the middle of a frame, e.g. after an interrupted download. Every complete line in front
+    /// of the end was delivered, followed by the cut off rest with ``LineContext::partial`` set.
+    Truncated {
+        path: PathBuf,
+        /// Number of bytes decompressed before the stream ended.
+        decompressed_bytes: u64,
+        /// Number of lines passed to the handler, including the partial one.
+        lines_delivered: u64,
+    },
+    /// No data could be read from the file for ``ZstdLinesConfig::file_timeout()``, so it was abandoned while the read
+    /// was still waiting. Lines in front of the stall were delivered.
+    Timeout {
+        path: PathBuf,
+        /// Number of compressed bytes read from the file before it stalled.
+        bytes_read: u64,
+        /// The timeout the read took longer than.
+        timeout: Duration,
+    },
+    /// The decompressed content does not match the checksum stored in its zstd frame, so the file is corrupted and was
+    /// abandoned. Lines in front of the corrupted frame were delivered.
+    ChecksumMismatch {
+        path: PathBuf,
+        /// Offset in the decompressed content up to which everything was delivered before the mismatch was detected.
+        offset: u64,
+    },
+    /// A tar header is corrupted, so the rest of the archive was abandoned.
+    TarHeader {
+        path: PathBuf,
+        /// Offset of the header in the decompressed archive.
+        block_offset: u64,
+        /// What is wrong with the header.
+        reason: String,
+    },
+    /// The file was abandoned by its ``ZstdLinesConfig::line_errors()`` policy, lines in front of it were delivered.
+    Abandoned {
+        path: PathBuf,
+        /// The tar entry or zip member the file was abandoned in.
+        entry: Option<String>,
+        /// The policy which abandoned the file.
+        policy: LineErrors,
+        /// Number of lines in a row which could not be read.
+        consecutive: u64,
+    },
+    /// A line could not be decoded and was skipped, the rest of the file is still read.
+    InvalidLine {
+        path: PathBuf,
+        /// The tar entry or zip member the line belongs to.
+        entry: Option<String>,
+        /// Number of the line, 0 for files in the zstd seekable format which are split across threads.
+        line_number: u64,
+        /// Offset of the line in the decompressed content of its file or entry.
+        byte_offset: u64,
+        /// Name of the encoding the line is not valid in.
+        encoding: &'static str,
+    },
+    /// A line longer than ``ZstdLinesConfig::max_line_len()`` was skipped.
+    LongLine {
+        path: PathBuf,
+        entry: Option<String>,
+        byte_offset: u64,
+        max_line_len: u64,
+    },
+    /// A zip member could not be read and was skipped, the other members are still read.
+    SkippedMember {
+        path: PathBuf,
+        member: String,
+        reason: String,
+    },
+}
+
+impl ZstdLinesError {
+    /// The file this problem was found in.
+    pub fn path(&self) -> &Path {
+        match self {
+            ZstdLinesError::Open { path, .. }
+            | ZstdLinesError::Decode { path, .. }
+            | ZstdLinesError::Truncated { path, .. }
+            | ZstdLinesError::Timeout { path, .. }
+            | ZstdLinesError::ChecksumMismatch { path, .. }
+            | ZstdLinesError::TarHeader { path, .. }
+            | ZstdLinesError::Abandoned { path, .. }
+            | ZstdLinesError::InvalidLine { path, .. }
+            | ZstdLinesError::LongLine { path, .. }
+            | ZstdLinesError::SkippedMember { path, .. } => path,
+        }
+    }
+
+    /// A short name of the problem, the ``kind`` field of ``tracing`` events.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            ZstdLinesError::Open { .. } => "open",
+            ZstdLinesError::Decode { .. } => "decode",
+            ZstdLinesError::Truncated { .. } => "truncated",
+            ZstdLinesError::Timeout { .. } => "timeout",
+            ZstdLinesError::ChecksumMismatch { .. } => "checksum_mismatch",
+            ZstdLinesError::TarHeader { .. } => "tar_header",
+            ZstdLinesError::Abandoned { .. } => "abandoned",
+            ZstdLinesError::InvalidLine { .. } => "invalid_line",
+            ZstdLinesError::LongLine { .. } => "long_line",
+            ZstdLinesError::SkippedMember { .. } => "skipped_member",
+        }
+    }
+
+    /// Copy a problem with a line or member, which is all the problems that do not hold an ``io::Error``.
+    pub(crate) fn copy_problem(&self) -> Option<Self> {
+        match self {
+            ZstdLinesError::Open { .. }
+            | ZstdLinesError::Decode { .. }
+            | ZstdLinesError::Truncated { .. }
+            | ZstdLinesError::Timeout { .. }
+            | ZstdLinesError::ChecksumMismatch { .. }
+            | ZstdLinesError::Abandoned { .. } => None,
+            ZstdLinesError::TarHeader {
+                path,
+                block_offset,
+                reason,
+            } => Some(ZstdLinesError::TarHeader {
+                path: path.clone(),
+                block_offset: *block_offset,
+                reason: reason.clone(),
+            }),
+            ZstdLinesError::InvalidLine {
+                path,
+                entry,
+                line_number,
+                byte_offset,
+                encoding,
+            } => Some(ZstdLinesError::InvalidLine {
+                path: path.clone(),
+                entry: entry.clone(),
+                line_number: *line_number,
+                byte_offset: *byte_offset,
+                encoding,
+            }),
+            ZstdLinesError::LongLine {
+                path,
+                entry,
+                byte_offset,
+                max_line_len,
+            } => Some(ZstdLinesError::LongLine {
+                path: path.clone(),
+                entry: entry.clone(),
+                byte_offset: *byte_offset,
+                max_line_len: *max_line_len,
+            }),
+            ZstdLinesError::SkippedMember {
+                path,
+                member,
+                reason,
+            } => Some(ZstdLinesError::SkippedMember {
+                path: path.clone(),
+                member: member.clone(),
+                reason: reason.clone(),
+            }),
+        }
+    }
+
+    /// Tell from the error a file failed with at which stage it failed.
+    pub(crate) fn from_io(path: &Path, error: io::Error) -> Self {
+        let path = path.to_path_buf();
+        if error.get_ref().is_some_and(|e| e.is::<OpenFailed>()) {
+            let source = match error.into_inner().map(|e| e.downcast::<OpenFailed>()) {
+                Some(Ok(open)) => open.0,
+                _ => unreachable!(),
+            };
+            return ZstdLinesError::Open { path, source };
+        }
+        if let Some(header) = error
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<TarHeaderError>())
+        {
+            return ZstdLinesError::TarHeader {
+                path,
+                block_offset: header.block_offset,
+                reason: header.reason.clone(),
+            };
+        }
+        if let Some(truncated) = find_in_entries::<TruncatedStream>(&error) {
+            return ZstdLinesError::Truncated {
+                path,
+                decompressed_bytes: truncated.decompressed_bytes,
+                lines_delivered: truncated.lines_delivered,
+            };
+        }
+        if let Some(mismatch) = find_in_entries::<ChecksumMismatch>(&error) {
+            return ZstdLinesError::ChecksumMismatch {
+                path,
+                offset: mismatch.offset,
+            };
+        }
+        if let Some(stalled) = root_cause(&error)
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<StalledRead>())
+        {
+            return ZstdLinesError::Timeout {
+                path,
+                bytes_read: stalled.bytes_read,
+                timeout: stalled.timeout,
+            };
+        }
+        if let Some((entry, abandoned)) = abandoned(&error) {
+            return ZstdLinesError::Abandoned {
+                path,
+                entry,
+                policy: abandoned.policy,
+                consecutive: abandoned.consecutive,
+            };
+        }
+        ZstdLinesError::Decode {
+            path,
+            offset: decoded_offset(&error),
+            source: error,
+        }
+    }
+
+    /// Turn a file which failed back into the error it failed with, so ``from_io()`` tells the same stage again.
+    pub(crate) fn into_io(self) -> io::Error {
+        match self {
+            ZstdLinesError::Open { source, .. } => {
+                io::Error::new(source.kind(), OpenFailed(source))
+            }
+            ZstdLinesError::Decode { source, .. } => source,
+            ZstdLinesError::TarHeader {
+                block_offset,
+                reason,
+                ..
+            } => tar_header_error(block_offset, reason),
+            ZstdLinesError::Truncated {
+                decompressed_bytes,
+                lines_delivered,
+                ..
+            } => with_lines_delivered(truncated_error(decompressed_bytes), lines_delivered),
+            ZstdLinesError::Timeout {
+                bytes_read,
+                timeout,
+                ..
+            } => stalled_error(bytes_read, timeout),
+            ZstdLinesError::ChecksumMismatch { path, offset } => checksum_error(&path, offset),
+            ZstdLinesError::Abandoned {
+                path,
+                entry,
+                policy,
+                consecutive,
+            } => {
+                let error = io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    Abandoned {
+                        policy,
+                        consecutive,
+                    },
+                );
+                match entry {
+                    Some(entry) => in_entry(entry_kind(&path), &entry, error),
+                    None => error,
+                }
+            }
+            other => io::Error::new(io::ErrorKind::InvalidData, other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ZstdLinesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZstdLinesError::Open { path, source } | ZstdLinesError::Decode { path, source, .. } => {
+                write_failure(f, path, source)
+            }
+            ZstdLinesError::TarHeader {
+                path,
+                block_offset,
+                reason,
+            } => write_failure(
+                f,
+                path,
+                &format_args!("{} at offset {}", reason, block_offset),
+            ),
+            ZstdLinesError::Truncated {
+                path,
+                decompressed_bytes,
+                ..
+            } => write_failure(
+                f,
+                path,
+                &TruncatedStream {
+                    decompressed_bytes: *decompressed_bytes,
+                    lines_delivered: 0,
+                },
+            ),
+            ZstdLinesError::Timeout {
+                path,
+                bytes_read,
+                timeout,
+            } => write_failure(
+                f,
+                path,
+                &StalledRead {
+                    bytes_read: *bytes_read,
+                    timeout: *timeout,
+                },
+            ),
+            ZstdLinesError::ChecksumMismatch { path, offset } => write_failure(
+                f,
+                path,
+                &ChecksumMismatch {
+                    path: path.clone(),
+                    offset: *offset,
+                },
+            ),
+            ZstdLinesError::Abandoned {
+                path,
+                entry,
+                policy,
+                consecutive,
+            } => {
+                let abandoned = Abandoned {
+                    policy: *policy,
+                    consecutive: *consecutive,
+                };
+                match entry {
+                    Some(entry) => write_failure(
+                        f,
+                        path,
+                        &format_args!("in {} {}: {}", entry_kind(path), entry, abandoned),
+                    ),
+                    None => write_failure(f, path, &abandoned),
+                }
+            }
+            ZstdLinesError::InvalidLine { path, encoding, .. } => write!(
+                f,
+                "Error reading line from {}: stream did not contain valid {}",
+                path.display(),
+                encoding
+            ),
+            ZstdLinesError::LongLine {
+                path,
+                entry,
+                byte_offset,
+                max_line_len,
+            } => {
+                write!(
+                    f,
+                    "Skipping line at byte {} is longer than {} bytes in ",
+                    byte_offset, max_line_len
+                )?;
+                if let Some(entry) = entry {
+                    write!(f, "{} in ", entry)?;
+                }
+                write!(f, "{}", path.display())
+            }
+            ZstdLinesError::SkippedMember {
+                path,
+                member,
+                reason,
+            } => write!(
+                f,
+                "Skipping zip member {} in {}: {}",
+                member,
+                path.display(),
+                reason
+            ),
+        }
+    }
+}
+
+impl Error for ZstdLinesError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ZstdLinesError::Open { source, .. } | ZstdLinesError::Decode { source, .. } => {
+                Some(source)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Describe a file which failed by what kind of input it is.
+pub(crate) fn write_failure(
+    f: &mut dyn fmt::Write,
+    path: &Path,
+    error: &dyn fmt::Display,
+) -> fmt::Result {
+    if is_stdin(path) {
+        write!(f, "Failed to process zst data from stdin: {}", error)
+    } else if is_tar_path(path) {
+        write!(
+            f,
+            "Failed to process tar.zst file {}: {}",
+            path.display(),
+            error
+        )
+    } else if is_zip_file(path) {
+        write!(
+            f,
+            "Failed to process zip file {}: {}",
+            path.display(),
+            error
+        )
+    } else {
+        write!(
+            f,
+            "Failed to process zst file {}: {}",
+            path.display(),
+            error
+        )
+    }
+}
+
+/// Marks the error of opening a file, it displays as the error itself.
+#[derive(Debug)]
+struct OpenFailed(io::Error);
+
+impl fmt::Display for OpenFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for OpenFailed {}
+
+/// Open a file to process, failing with an error reported as ``ZstdLinesError::Open``.
+pub(crate) fn open_file(path: &Path) -> io::Result<File> {
+    File::open(path).map_err(|e| io::Error::new(e.kind(), OpenFailed(e)))
+}
+
+/// A corrupted tar header, reported as ``ZstdLinesError::TarHeader``.
+#[derive(Debug)]
+struct TarHeaderError {
+    block_offset: u64,
+    reason: String,
+}
+
+impl fmt::Display for TarHeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.reason, self.block_offset)
+    }
+}
+
+impl Error for TarHeaderError {}
+
+/// The error for a tar header at `block_offset` which cannot be parsed.
+pub(crate) fn tar_header_error(block_offset: u64, reason: impl Into<String>) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        TarHeaderError {
+            block_offset,
+            reason: reason.into(),
+        },
+    )
+}
+
+/// Marks the end of a zstd stream in the middle of a frame.
+#[derive(Debug)]
+struct TruncatedStream {
+    decompressed_bytes: u64,
+    lines_delivered: u64,
+}
+
+impl fmt::Display for TruncatedStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "stream is truncated after {} decompressed bytes",
+            self.decompressed_bytes
+        )
+    }
+}
+
+impl Error for TruncatedStream {}
+
+/// The error for a zstd stream which ends in the middle of a frame after `decompressed_bytes` bytes, reported as ``ZstdLinesError::Truncated``.
+pub(crate) fn truncated_error(decompressed_bytes: u64) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        TruncatedStream {
+            decompressed_bytes,
+            lines_delivered: 0,
+        },
+    )
+}
+
+/// Check whether a stream failed because it is truncated.
+pub(crate) fn is_truncated(error: &io::Error) -> bool {
+    find_in_entries::<TruncatedStream>(error).is_some()
+}
+
+/// Tell the error of a truncated file how many lines of the file were delivered, other errors are returned unchanged.
+pub(crate) fn with_lines_delivered(mut error: io::Error, lines_delivered: u64) -> io::Error {
+    let mut inner = error.get_mut();
+    while let Some(e) = inner {
+        if let Some(truncated) = e.downcast_mut::<TruncatedStream>() {
+            truncated.lines_delivered = lines_delivered;
+            break;
+        }
+        inner = e
+            .downcast_mut::<InEntry>()
+            .and_then(|entry| entry.source.get_mut());
+    }
+    error
+}
+
+/// Marks a read which took longer than ``ZstdLinesConfig::file_timeout()``.
+#[derive(Debug)]
+struct StalledRead {
+    bytes_read: u64,
+    timeout: Duration,
+}
+
+impl fmt::Display for StalledRead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "no data could be read for {:?} after {} compressed bytes",
+            self.timeout, self.bytes_read
+        )
+    }
+}
+
+impl Error for StalledRead {}
+
+/// The error for a file which stalled after `bytes_read` bytes were read from it, reported as ``ZstdLinesError::Timeout``.
+pub(crate) fn stalled_error(bytes_read: u64, timeout: Duration) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        StalledRead {
+            bytes_read,
+            timeout,
+        },
+    )
+}
+
+/// Marks an error of the zstd decoder with how much was decoded before it, it displays as the error itself.
+#[derive(Debug)]
+struct DecodeFailed {
+    offset: u64,
+    source: io::Error,
+}
+
+impl fmt::Display for DecodeFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl Error for DecodeFailed {}
+
+/// The error for a zstd decoder which failed after decoding `offset` bytes, reported as ``ZstdLinesError::Decode``.
+pub(crate) fn decode_error(offset: u64, error: io::Error) -> io::Error {
+    io::Error::new(
+        error.kind(),
+        DecodeFailed {
+            offset,
+            source: error,
+        },
+    )
+}
+
+/// An error which happened in a tar entry or zip member, it displays prefixed with the entry.
+#[derive(Debug)]
+struct InEntry {
+    kind: &'static str,
+    name: String,
+    source: io::Error,
+}
+
+impl fmt::Display for InEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "in {} {}: {}", self.kind, self.name, self.source)
+    }
+}
+
+impl Error for InEntry {}
+
+/// Add the name of the tar entry or zip member an error occurred in to its message.
+pub(crate) fn in_entry(kind: &'static str, name: &str, error: io::Error) -> io::Error {
+    io::Error::new(
+        error.kind(),
+        InEntry {
+            kind,
+            name: name.to_string(),
+            source: error,
+        },
+    )
+}
+
+/// What the entries of an archive are called in messages.
+fn entry_kind(path: &Path) -> &'static str {
+    if is_zip_file(path) {
+        "zip member"
+    } else {
+        "tar entry"
+    }
+}
+
+/// Find the I/O error a file failed with, looking through the markers added on the way up from where it happened.
+pub(crate) fn root_cause(error: &io::Error) -> &io::Error {
+    let Some(inner) = error.get_ref() else {
+        return error;
+    };
+    if let Some(open) = inner.downcast_ref::<OpenFailed>() {
+        root_cause(&open.0)
+    } else if let Some(failed) = inner.downcast_ref::<DecodeFailed>() {
+        root_cause(&failed.source)
+    } else if let Some(entry) = inner.downcast_ref::<InEntry>() {
+        root_cause(&entry.source)
+    } else {
+        error
+    }
+}
+
+/// Find the marker `T` of an error, looking into errors of entries.
+fn find_in_entries<T: Error + 'static>(error: &io::Error) -> Option<&T> {
+    let inner = error.get_ref()?;
+    match inner.downcast_ref::<InEntry>() {
+        Some(entry) => find_in_entries(&entry.source),
+        None => inner.downcast_ref(),
+    }
+}
+
+/// Find the policy which abandoned a file and the entry it was abandoned in, looking into errors of entries.
+fn abandoned(error: &io::Error) -> Option<(Option<String>, &Abandoned)> {
+    let inner = error.get_ref()?;
+    if let Some(abandoned) = inner.downcast_ref::<Abandoned>() {
+        Some((None, abandoned))
+    } else if let Some(entry) = inner.downcast_ref::<InEntry>() {
+        abandoned(&entry.source)
+            .map(|(inner, abandoned)| (inner.or_else(|| Some(entry.name.clone())), abandoned))
+    } else {
+        None
+    }
+}
+
+/// Find how much of the file was decoded before the decoder failed, looking into errors of entries.
+fn decoded_offset(error: &io::Error) -> Option<u64> {
+    let inner = error.get_ref()?;
+    if let Some(failed) = inner.downcast_ref::<DecodeFailed>() {
+        Some(failed.offset)
+    } else if let Some(entry) = inner.downcast_ref::<InEntry>() {
+        decoded_offset(&entry.source)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use crate::ZstdLinesConfig;
+    use std::fs;
+
+    #[test]
+    fn broken_archives_and_truncated_files_are_told_apart() {
+        let dir = test_dir("error_kinds");
+        let mut archive = tar_archive(&[("a.txt", b"first\n")]);
+        // Overwrite the end of the archive with a block which is no header
+        archive[1024..1536].fill(1);
+        write_zst(&dir.join("broken.tar.zst"), &archive);
+        // An interrupted download, cut off in the middle
+        let content: String = (0..100_000).map(|i| format!("{}\n", i)).collect();
+        let compressed = zstd::encode_all(content.as_bytes(), 0).unwrap();
+        fs::write(
+            dir.join("truncated.zst"),
+            &compressed[..compressed.len() / 2],
+        )
+        .unwrap();
+
+        let errors = ZstdLinesConfig::new()
+            .report_failures(false)
+            .try_run(
+                [dir.join("broken.tar.zst"), dir.join("truncated.zst")],
+                |_line, _path| {},
+            )
+            .unwrap_err();
+        assert!(matches!(
+            &errors.failures[0],
+            ZstdLinesError::TarHeader {
+                block_offset: 1024,
+                ..
+            }
+        ));
+        // The complete lines in front of the end are delivered, and the cut off rest of the last one
+        assert!(matches!(
+            &errors.failures[1],
+            ZstdLinesError::Truncated { lines_delivered, .. } if *lines_delivered > 10_000
+        ));
+        assert_eq!(
+            errors.failures[0].to_string(),
+            format!(
+                "Failed to process tar.zst file {}: tar header checksum mismatch at offset 1024",
+                dir.join("broken.tar.zst").display()
+            )
+        );
+    }
+}
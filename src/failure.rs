@@ -0,0 +1,122 @@
+//! Returning the files which failed to process, for callers which cannot watch stderr.
+
+use crate::config::ZstdLinesConfig;
+use crate::error::ZstdLinesError;
+use rayon::prelude::*;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+/// The files which failed to process, returned by ``par_zstd_lines_checked()`` and ``ZstdLinesConfig::try_run()``.
+#[derive(Debug)]
+pub struct ZstdLinesErrors {
+    /// Every file which failed, in the order of the paths.
+    pub failures: Vec<ZstdLinesError>,
+}
+
+impl fmt::Display for ZstdLinesErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.failures.as_slice() {
+            [] => f.write_str("no file failed to process"),
+            [e] => e.fmt(f),
+            [e, rest @ ..] => write!(f, "{}, and {} more files failed", e, rest.len()),
+        }
+    }
+}
+
+impl Error for ZstdLinesErrors {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.failures.first().map(|e| e as &(dyn Error + 'static))
+    }
+}
+
+/// Process each line in zstd compressed files in parallel like ``par_zstd_lines()``, but return the files which failed
+/// instead of reporting them on stderr. A failing file never affects the other files, which are processed completely.
+/// Use ``ZstdLinesConfig::try_run()`` to report them on stderr as well.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_checked");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"1\n2\n"[..], 0).unwrap()).unwrap();
+///
+/// let result = zstd_lines::par_zstd_lines_checked([dir.join("a.zst"), dir.join("missing.zst")], |_line, _path| {});
+/// match result {
+///     Ok(()) => unreachable!(),
+///     Err(errors) => {
+///         for e in &errors.failures {
+///             println!("{} failed: {}", e.path().display(), e);
+///         }
+///         assert_eq!(errors.failures.len(), 1);
+///         assert!(errors.to_string().starts_with("Failed to process zst file"));
+///     }
+/// }
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_checked<I, F>(paths: I, line_handler: F) -> Result<(), ZstdLinesErrors>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    ZstdLinesConfig::new()
+        .report_failures(false)
+        .try_run(paths, line_handler)
+}
+
+/// Process each line in zstd compressed files in parallel, but stop everything at the first problem and return it,
+/// for validation jobs which only care whether all files are fine. A file which cannot be opened or decoded, a line
+/// which is not valid UTF-8 and the like stop all files after their current line, files which were not started yet are
+/// skipped. Nothing is reported on stderr. See ``ZstdLinesConfig::strict()`` for the same with other settings.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use zstd_lines::ZstdLinesError;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_strict_batch");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// // A corrupted file early in a large batch
+/// std::fs::write(dir.join("part-00.zst"), b"\x28\xB5\x2F\xFDgarbage").unwrap();
+/// let content: String = (0..10_000).map(|i| format!("{}\n", i)).collect();
+/// let mut files = vec![dir.join("part-00.zst")];
+/// for i in 1..16 {
+///     let file = dir.join(format!("part-{:02}.zst", i));
+///     std::fs::write(&file, zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+///     files.push(file);
+/// }
+///
+/// let handled = AtomicUsize::new(0);
+/// let error = zstd_lines::par_zstd_lines_strict(&files, |_line, _path| {
+///     handled.fetch_add(1, Ordering::Relaxed);
+/// })
+/// .unwrap_err();
+/// assert!(matches!(error, ZstdLinesError::Decode { .. }));
+/// assert_eq!(error.path(), dir.join("part-00.zst"));
+/// // The other files stop soon after instead of delivering all 150000 lines
+/// assert!(handled.into_inner() < 15 * 10_000 / 2);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_strict<I, F>(paths: I, line_handler: F) -> Result<(), ZstdLinesError>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    ZstdLinesConfig::new()
+        .strict(true)
+        .report_failures(false)
+        .on_error(|_path, _error| {})
+        .try_run(paths, line_handler)
+        .map_err(|mut errors| errors.failures.swap_remove(0))
+}
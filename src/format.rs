@@ -0,0 +1,343 @@
+//! Detection of the compression format of an input by its magic bytes.
+
+use crate::checksum::ChecksumReader;
+use crate::config::ZstdLinesConfig;
+use crate::frame::FrameReader;
+use crate::{is_tar_header, read_up_to, TAR_BLOCK_SIZE};
+use std::io::{self, BufReader, Cursor, Read};
+use std::path::Path;
+use zstd::stream::read::Decoder;
+
+/// Magic number of a zstd frame, little endian.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+/// Magic number of a gzip member.
+#[cfg(feature = "gzip")]
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+/// Magic number of an xz stream.
+#[cfg(feature = "xz")]
+const XZ_MAGIC: [u8; 6] = [0xFD, b'7', b'z', b'X', b'Z', 0x00];
+/// Magic number of a bzip2 stream.
+#[cfg(feature = "bzip2")]
+const BZIP2_MAGIC: [u8; 3] = *b"BZh";
+/// Magic number of an LZ4 frame.
+#[cfg(feature = "lz4")]
+const LZ4_MAGIC: [u8; 4] = [0x04, 0x22, 0x4D, 0x18];
+/// Magic number of the legacy LZ4 format, which is not supported.
+#[cfg(feature = "lz4")]
+const LZ4_LEGACY_MAGIC: [u8; 4] = [0x02, 0x21, 0x4C, 0x18];
+/// How many bytes are sniffed, enough for an uncompressed tar header.
+const SNIFF_LEN: usize = TAR_BLOCK_SIZE;
+/// Large enough for any zstd frame header, which is all that is sniffed where other formats are not considered.
+const FRAME_HEADER_MAX_SIZE: usize = 18;
+/// How many of the sniffed bytes are listed when the format is not recognized.
+const REPORTED_LEN: usize = 8;
+/// The largest window the zstd decoder supports on 64 bit platforms, accepted from every frame unless configured otherwise.
+const WINDOW_LOG_MAX: u32 = 31;
+
+/// The compression format of an input, detected by its magic bytes regardless of the file name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Compression {
+    /// Uncompressed text or an uncompressed tar archive.
+    None,
+    /// Zstd. The decoder keeps reading after the end of a frame, so files made of several concatenated frames,
+    /// including skippable frames in between, are decoded completely and lines spanning frames are reassembled.
+    Zstd,
+    /// Gzip, available with the `gzip` feature. Concatenated members are decoded as one stream.
+    /// Tar archives are recognized by their first header, whatever the name of the file.
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// XZ, available with the `xz` feature. Concatenated streams are decoded as one stream.
+    #[cfg(feature = "xz")]
+    Xz,
+    /// Bzip2, available with the `bzip2` feature. Concatenated streams, e.g. from ``pbzip2``, are decoded as one stream.
+    #[cfg(feature = "bzip2")]
+    Bzip2,
+    /// LZ4 frame format, available with the `lz4` feature.
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// The legacy LZ4 format, which is recognized only to report it as unsupported.
+    #[cfg(feature = "lz4")]
+    Lz4Legacy,
+}
+
+impl Compression {
+    /// Detect the compression format from the first bytes of an input.
+    /// Uncompressed content is only recognized if it looks like text or an uncompressed tar archive,
+    /// returns ``None`` if the format is not recognized.
+    pub(crate) fn from_magic(head: &[u8]) -> Option<Compression> {
+        if head.starts_with(&ZSTD_MAGIC) || is_skippable_frame(head) {
+            return Some(Compression::Zstd);
+        }
+        #[cfg(feature = "gzip")]
+        if head.starts_with(&GZIP_MAGIC) {
+            return Some(Compression::Gzip);
+        }
+        #[cfg(feature = "xz")]
+        if head.starts_with(&XZ_MAGIC) {
+            return Some(Compression::Xz);
+        }
+        #[cfg(feature = "bzip2")]
+        if head.starts_with(&BZIP2_MAGIC) {
+            return Some(Compression::Bzip2);
+        }
+        #[cfg(feature = "lz4")]
+        if head.starts_with(&LZ4_MAGIC) {
+            return Some(Compression::Lz4);
+        }
+        #[cfg(feature = "lz4")]
+        if head.starts_with(&LZ4_LEGACY_MAGIC) {
+            return Some(Compression::Lz4Legacy);
+        }
+        if looks_like_text(head) || is_tar_header(head) {
+            return Some(Compression::None);
+        }
+        None
+    }
+}
+
+/// Check whether the sniffed bytes look like uncompressed text, i.e. contain no binary control characters.
+/// Bytes above 0x7F are accepted since they may be part of multi-byte UTF-8 or legacy encodings.
+fn looks_like_text(head: &[u8]) -> bool {
+    head.iter()
+        .all(|&b| b >= 0x20 || matches!(b, b'\t' | b'\n' | 0x0B | 0x0C | b'\r' | 0x1B | 0x1E))
+}
+
+/// Check for the magic number of a zstd skippable frame (0x184D2A50 to 0x184D2A5F), which may precede the first data frame.
+fn is_skippable_frame(head: &[u8]) -> bool {
+    head.len() >= 4 && head[0] & 0xF0 == 0x50 && head[1..4] == [0x2A, 0x4D, 0x18]
+}
+
+/// Sniff the compression format of `reader` and wrap it in the matching decoder.
+/// The sniffed bytes are fed back in front of the remaining input.
+/// Inputs whose format is not recognized produce an error naming `label` and the bytes seen.
+pub(crate) fn decompress<'a, R>(
+    mut reader: R,
+    label: &'a Path,
+    config: &'a ZstdLinesConfig<'_>,
+) -> io::Result<Box<dyn Read + 'a>>
+where
+    R: Read + 'a,
+{
+    let mut head = vec![0; SNIFF_LEN];
+    let head_len = read_up_to(&mut reader, &mut head)?;
+    head.truncate(head_len);
+    let Some(compression) = Compression::from_magic(&head) else {
+        return Err(unrecognized_format(label, &head));
+    };
+    let reader = Cursor::new(head).chain(reader);
+
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Zstd => zstd_stream(reader, label, config)?,
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+        #[cfg(feature = "xz")]
+        Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+        #[cfg(feature = "bzip2")]
+        Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+        #[cfg(feature = "lz4")]
+        Compression::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(reader)),
+        #[cfg(feature = "lz4")]
+        Compression::Lz4Legacy => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the legacy LZ4 format is not supported, only the LZ4 frame format is",
+            ))
+        }
+    })
+}
+
+/// Decompress the content of a tar entry if it is zstd compressed itself, otherwise pass it through unchanged.
+/// Unlike ``decompress()``, content in other formats is not an error, it is simply not split into lines.
+pub(crate) fn decompress_entry<'a, R>(
+    mut reader: R,
+    label: &'a Path,
+    config: &'a ZstdLinesConfig<'_>,
+) -> io::Result<Box<dyn Read + 'a>>
+where
+    R: Read + 'a,
+{
+    let mut head = vec![0; FRAME_HEADER_MAX_SIZE];
+    let head_len = read_up_to(&mut reader, &mut head)?;
+    head.truncate(head_len);
+    let is_zstd = matches!(Compression::from_magic(&head), Some(Compression::Zstd));
+    let reader = Cursor::new(head).chain(reader);
+
+    if is_zstd {
+        zstd_stream(reader, label, config)
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Wrap `reader` in a zstd decoder, passing skippable frames to the callback in `config` if there is one.
+/// Not restricted to a single frame, so concatenated frames are decoded as one stream.
+fn zstd_stream<'a, R>(
+    reader: R,
+    label: &'a Path,
+    config: &'a ZstdLinesConfig<'_>,
+) -> io::Result<Box<dyn Read + 'a>>
+where
+    R: Read + 'a,
+{
+    Ok(match &config.on_skippable_frame {
+        Some(handler) => Box::new(ChecksumReader::new(
+            zstd_decoder(FrameReader::new(reader, &**handler, label), config)?,
+            label,
+            0,
+        )),
+        None => Box::new(ChecksumReader::new(zstd_decoder(reader, config)?, label, 0)),
+    })
+}
+
+/// Create a zstd decoder using the dictionary and the window limit from `config`, if any.
+/// Without a configured limit every frame may declare a window of up to ``2^WINDOW_LOG_MAX`` bytes,
+/// so archives compressed with ``--long`` decode without configuration, whatever frame needs the large window.
+/// The decoder only allocates the window a frame declares.
+pub(crate) fn zstd_decoder<'a, R: Read>(
+    reader: R,
+    config: &'a ZstdLinesConfig<'_>,
+) -> io::Result<Decoder<'a, BufReader<R>>> {
+    let capacity = config
+        .buffer_size
+        .unwrap_or_else(zstd::zstd_safe::DCtx::in_size);
+    let reader = BufReader::with_capacity(capacity, reader);
+    let mut decoder = match &config.dictionary {
+        Some(dictionary) => Decoder::with_prepared_dictionary(reader, dictionary)?,
+        None => Decoder::with_buffer(reader)?,
+    };
+    decoder.window_log_max(config.window_log_max.unwrap_or(WINDOW_LOG_MAX))?;
+    Ok(decoder)
+}
+
+/// Build the error for an input whose format is not recognized.
+fn unrecognized_format(label: &Path, head: &[u8]) -> io::Error {
+    let bytes: Vec<String> = head
+        .iter()
+        .take(REPORTED_LEN)
+        .map(|b| format!("{:02X}", b))
+        .collect();
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "unrecognized format for {} (first bytes: {})",
+            label.display(),
+            bytes.join(" ")
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{lines_of, test_dir, write_zst};
+    use std::fs;
+    #[cfg(any(feature = "gzip", feature = "xz", feature = "bzip2", feature = "lz4"))]
+    use std::io::Write;
+
+    #[test]
+    fn formats_are_detected_by_their_magic_bytes() {
+        let dir = test_dir("magic");
+        write_zst(&dir.join("part-00001"), b"line\n");
+        fs::write(
+            dir.join("image.png.zst"),
+            [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A],
+        )
+        .unwrap();
+
+        let (lines, result) = lines_of(&dir.join("part-00001"));
+        assert_eq!(lines, vec!["line"]);
+        result.unwrap();
+        let error = lines_of(&dir.join("image.png.zst")).1.unwrap_err();
+        assert!(error.to_string().contains("unrecognized format"));
+        assert!(error.to_string().contains("89 50 4E 47 0D 0A 1A 0A"));
+    }
+
+    #[test]
+    fn concatenated_zstd_frames_are_one_stream() {
+        let mut data = zstd::encode_all(&b"first\nsplit "[..], 0).unwrap();
+        // A skippable frame with a 4 byte payload
+        data.extend([0x50, 0x2A, 0x4D, 0x18, 4, 0, 0, 0, b'm', b'e', b't', b'a']);
+        data.extend(zstd::encode_all(&b"line\nlast\n"[..], 0).unwrap());
+
+        let mut lines = Vec::new();
+        crate::zstd_lines_bytes("frames", &data, |line, _label| lines.push(line)).unwrap();
+        assert_eq!(lines, vec!["first", "split line", "last"]);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzip_members_and_archives_are_decoded() {
+        use flate2::{write::GzEncoder, Compression};
+
+        let dir = test_dir("gzip");
+        let gzip = |content: &[u8]| {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(content).unwrap();
+            encoder.finish().unwrap()
+        };
+        let mut members = gzip(b"first\nsec");
+        members.extend(gzip(b"ond\n"));
+        fs::write(dir.join("dump.jsonl.gz"), members).unwrap();
+        assert_eq!(
+            lines_of(&dir.join("dump.jsonl.gz")).0,
+            vec!["first", "second"]
+        );
+
+        let archive = gzip(&crate::test_util::tar_archive(&[(
+            "a.jsonl",
+            b"{\"a\":1}\n",
+        )]));
+        for name in ["dump.tgz", "dump.tar.gz", "dump.gz"] {
+            fs::write(dir.join(name), &archive).unwrap();
+            assert_eq!(lines_of(&dir.join(name)).0, vec![r#"{"a":1}"#], "{}", name);
+        }
+    }
+
+    #[cfg(feature = "xz")]
+    #[test]
+    fn xz_streams_are_decoded_like_zstd() {
+        let dir = test_dir("xz");
+        let content = b"first\nsecond\nno trailing newline";
+        let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(content).unwrap();
+        fs::write(dir.join("dump.jsonl.xz"), encoder.finish().unwrap()).unwrap();
+        write_zst(&dir.join("dump.jsonl.zst"), content);
+
+        let (lines, result) = lines_of(&dir.join("dump.jsonl.xz"));
+        result.unwrap();
+        assert_eq!(lines, vec!["first", "second", "no trailing newline"]);
+        assert_eq!(lines, lines_of(&dir.join("dump.jsonl.zst")).0);
+    }
+
+    #[cfg(feature = "bzip2")]
+    #[test]
+    fn concatenated_bzip2_streams_are_one_stream() {
+        use bzip2::{write::BzEncoder, Compression};
+
+        let dir = test_dir("bzip2");
+        let mut data = Vec::new();
+        for part in [&b"first\nsec"[..], &b"ond\nthird\n"[..]] {
+            let mut encoder = BzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(part).unwrap();
+            data.extend(encoder.finish().unwrap());
+        }
+        fs::write(dir.join("dump.jsonl.bz2"), data).unwrap();
+        assert_eq!(
+            lines_of(&dir.join("dump.jsonl.bz2")).0,
+            vec!["first", "second", "third"]
+        );
+    }
+
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn lz4_frames_are_decoded() {
+        let dir = test_dir("lz4");
+        let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+        encoder.write_all(b"first\nsecond\n").unwrap();
+        fs::write(dir.join("dump.jsonl.lz4"), encoder.finish().unwrap()).unwrap();
+        assert_eq!(
+            lines_of(&dir.join("dump.jsonl.lz4")).0,
+            vec!["first", "second"]
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! Walking the frame structure of a zstd stream without decompressing it.
+
+use crate::config::SkippableFrameHandler;
+use crate::read_up_to;
+use std::io::{self, Read};
+use std::path::Path;
+
+const ZSTD_MAGIC: u32 = 0xFD2FB528;
+const SKIPPABLE_MAGIC_MASK: u32 = 0xFFFFFFF0;
+const SKIPPABLE_MAGIC: u32 = 0x184D2A50;
+const BLOCK_HEADER_SIZE: usize = 3;
+const CHECKSUM_SIZE: u64 = 4;
+
+/// Where the reader currently is in the frame structure.
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// At the start of a new frame.
+    FrameStart,
+    /// At the header of the next block in a data frame.
+    BlockHeader { checksum: bool },
+    /// Passing through `remaining` bytes of frame content, continuing with `next`.
+    Content { remaining: u64, next: Next },
+    /// The stream is not structured as expected, pass everything through and let the decoder report it.
+    Passthrough,
+}
+
+/// What follows the current block content.
+#[derive(Debug, Clone, Copy)]
+enum Next {
+    Block { checksum: bool },
+    Checksum,
+    FrameStart,
+}
+
+/// A reader over compressed zstd data which passes data frames through unchanged
+/// and hands skippable frames to a callback instead.
+pub(crate) struct FrameReader<'a, R> {
+    inner: R,
+    state: State,
+    /// Frame and block headers which have been parsed but not yet passed on.
+    pending: Vec<u8>,
+    pending_offset: usize,
+    on_skippable_frame: &'a SkippableFrameHandler<'a>,
+    label: &'a Path,
+}
+
+impl<'a, R: Read> FrameReader<'a, R> {
+    pub(crate) fn new(
+        inner: R,
+        on_skippable_frame: &'a SkippableFrameHandler<'a>,
+        label: &'a Path,
+    ) -> Self {
+        FrameReader {
+            inner,
+            state: State::FrameStart,
+            pending: Vec::new(),
+            pending_offset: 0,
+            on_skippable_frame,
+            label,
+        }
+    }
+
+    /// Read exactly `len` bytes into the pending header buffer. Returns false if the stream ended early.
+    fn read_pending(&mut self, len: usize) -> io::Result<bool> {
+        let start = self.pending.len();
+        self.pending.resize(start + len, 0);
+        let bytes_read = read_up_to(&mut self.inner, &mut self.pending[start..])?;
+        self.pending.truncate(start + bytes_read);
+        Ok(bytes_read == len)
+    }
+
+    /// Parse the next structural element, filling the pending buffer with bytes to pass on.
+    fn advance(&mut self) -> io::Result<()> {
+        self.pending.clear();
+        self.pending_offset = 0;
+
+        match self.state {
+            State::FrameStart => {
+                if !self.read_pending(4)? {
+                    // End of the stream, or trailing bytes which the decoder will complain about
+                    self.state = State::Passthrough;
+                    return Ok(());
+                }
+                let magic = u32::from_le_bytes(self.pending[..4].try_into().unwrap());
+                if magic & SKIPPABLE_MAGIC_MASK == SKIPPABLE_MAGIC {
+                    self.pending.clear();
+                    self.read_skippable_frame(magic)?;
+                } else if magic == ZSTD_MAGIC {
+                    self.read_frame_header()?;
+                } else {
+                    self.state = State::Passthrough;
+                }
+            }
+            State::BlockHeader { checksum } => {
+                if !self.read_pending(BLOCK_HEADER_SIZE)? {
+                    self.state = State::Passthrough;
+                    return Ok(());
+                }
+                let header =
+                    u32::from_le_bytes([self.pending[0], self.pending[1], self.pending[2], 0]);
+                let last = header & 1 == 1;
+                let block_type = (header >> 1) & 0b11;
+                let block_size = u64::from(header >> 3);
+                let remaining = match block_type {
+                    // Raw and compressed blocks store their size, RLE blocks a single byte
+                    0 | 2 => block_size,
+                    1 => 1,
+                    _ => {
+                        self.state = State::Passthrough;
+                        return Ok(());
+                    }
+                };
+                let next = match (last, checksum) {
+                    (false, _) => Next::Block { checksum },
+                    (true, true) => Next::Checksum,
+                    (true, false) => Next::FrameStart,
+                };
+                self.state = State::Content { remaining, next };
+            }
+            State::Content { .. } | State::Passthrough => {}
+        }
+        Ok(())
+    }
+
+    /// Parse the header of a data frame following its magic number.
+    fn read_frame_header(&mut self) -> io::Result<()> {
+        if !self.read_pending(1)? {
+            self.state = State::Passthrough;
+            return Ok(());
+        }
+        let descriptor = self.pending[4];
+        let content_size_flag = descriptor >> 6;
+        let single_segment = descriptor & 0b0010_0000 != 0;
+        let checksum = descriptor & 0b0000_0100 != 0;
+        let dictionary_id_flag = descriptor & 0b11;
+
+        let window_descriptor_size = if single_segment { 0 } else { 1 };
+        let dictionary_id_size = [0, 1, 2, 4][usize::from(dictionary_id_flag)];
+        let content_size_size = match content_size_flag {
+            0 if single_segment => 1,
+            0 => 0,
+            1 => 2,
+            2 => 4,
+            _ => 8,
+        };
+
+        if !self.read_pending(window_descriptor_size + dictionary_id_size + content_size_size)? {
+            self.state = State::Passthrough;
+            return Ok(());
+        }
+        self.state = State::BlockHeader { checksum };
+        Ok(())
+    }
+
+    /// Read the payload of a skippable frame and hand it to the callback.
+    fn read_skippable_frame(&mut self, magic: u32) -> io::Result<()> {
+        let mut size = [0; 4];
+        if read_up_to(&mut self.inner, &mut size)? < size.len() {
+            return Err(truncated_skippable_frame());
+        }
+        let size = u32::from_le_bytes(size) as usize;
+        let mut payload = vec![0; size];
+        if read_up_to(&mut self.inner, &mut payload)? < size {
+            return Err(truncated_skippable_frame());
+        }
+        (self.on_skippable_frame)(magic, &payload, self.label);
+        self.state = State::FrameStart;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for FrameReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if self.pending_offset < self.pending.len() {
+                let pending = &self.pending[self.pending_offset..];
+                let len = pending.len().min(buf.len());
+                buf[..len].copy_from_slice(&pending[..len]);
+                self.pending_offset += len;
+                return Ok(len);
+            }
+
+            match self.state {
+                State::Passthrough => return self.inner.read(buf),
+                State::Content { remaining: 0, next } => {
+                    self.state = match next {
+                        Next::Block { checksum } => State::BlockHeader { checksum },
+                        Next::Checksum => State::Content {
+                            remaining: CHECKSUM_SIZE,
+                            next: Next::FrameStart,
+                        },
+                        Next::FrameStart => State::FrameStart,
+                    };
+                }
+                State::Content { remaining, next } => {
+                    let len = buf
+                        .len()
+                        .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+                    let bytes_read = self.inner.read(&mut buf[..len])?;
+                    if bytes_read == 0 {
+                        // Truncated frame, the decoder reports this as an incomplete frame
+                        self.state = State::Passthrough;
+                        return Ok(0);
+                    }
+                    self.state = State::Content {
+                        remaining: remaining - bytes_read as u64,
+                        next,
+                    };
+                    return Ok(bytes_read);
+                }
+                State::FrameStart | State::BlockHeader { .. } => {
+                    self.advance()?;
+                    if self.pending.is_empty() && matches!(self.state, State::Passthrough) {
+                        return self.inner.read(buf);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn truncated_skippable_frame() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "zstd stream ended inside a skippable frame",
+    )
+}
@@ -0,0 +1,206 @@
+//! Searching zstd compressed files for lines matching a pattern, available with the `regex` feature.
+
+use crate::config::{Run, NUMBERED_CONFIG};
+use crate::context::LineContext;
+use crate::text::report_malformed;
+use crate::{collect_file, process_file};
+use memchr::memmem::Finder;
+use rayon::prelude::*;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+/// A line found by ``par_zstd_grep()`` and its siblings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrepMatch {
+    /// The file the line was read from.
+    pub path: PathBuf,
+    /// Number of the line within its file or archive entry, starting at 1.
+    pub line_number: u64,
+    /// The matching line.
+    pub line: String,
+}
+
+/// What lines are matched against.
+enum Matcher<'a> {
+    Regex(&'a Regex),
+    Fixed(Box<Finder<'a>>),
+}
+
+impl Matcher<'_> {
+    /// Check a line on the worker thread, only matching lines are copied.
+    /// Fixed strings are searched in the raw bytes, so only matching lines have to be valid UTF-8.
+    fn matches(
+        &self,
+        line: &[u8],
+        context: &LineContext<'_>,
+        config: &Run<'_>,
+    ) -> Option<GrepMatch> {
+        if let Matcher::Fixed(finder) = self {
+            finder.find(line)?;
+        }
+        let Ok(line) = std::str::from_utf8(line) else {
+            report_malformed(context, config, "UTF-8");
+            return None;
+        };
+        if let Matcher::Regex(pattern) = self {
+            if !pattern.is_match(line) {
+                return None;
+            }
+        }
+        Some(GrepMatch {
+            path: context.path.to_path_buf(),
+            line_number: context.line_number,
+            line: line.to_string(),
+        })
+    }
+}
+
+/// Find every line matching a regular expression in zstd compressed files, searching the files in parallel.
+/// Matches are returned in the order of the paths and lines. Since lines are numbered, files in the zstd seekable format
+/// are decoded on a single thread.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `pattern` - The regular expression lines have to match somewhere.
+///
+/// # Example
+/// ```
+/// use regex::Regex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_grep");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let content = "GET /index.html 200\nGET /missing 404\nPOST /login 500\n";
+/// std::fs::write(dir.join("access.log.zst"), zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+///
+/// let matches = zstd_lines::par_zstd_grep([dir.join("access.log.zst")], &Regex::new(r" [45]\d\d$").unwrap());
+/// let found: Vec<_> = matches.iter().map(|m| (m.line_number, m.line.as_str())).collect();
+/// assert_eq!(found, vec![(2, "GET /missing 404"), (3, "POST /login 500")]);
+/// assert_eq!(matches[0].path, dir.join("access.log.zst"));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_grep<I>(paths: I, pattern: &Regex) -> Vec<GrepMatch>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+{
+    collect_matches(paths, &Matcher::Regex(pattern))
+}
+
+/// Like ``par_zstd_grep()``, but finding every line containing a fixed string, which is a lot faster than an
+/// equivalent regular expression.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `needle` - The string lines have to contain.
+///
+/// # Example
+/// ```
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_grep_fixed");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let content = "{\"user\":\"a\",\"tag\":\"rust\"}\n{\"user\":\"b\",\"tag\":\"go\"}\n";
+/// std::fs::write(dir.join("posts.jsonl.zst"), zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+///
+/// let matches = zstd_lines::par_zstd_grep_fixed([dir.join("posts.jsonl.zst")], "\"rust\"");
+/// assert_eq!(matches.len(), 1);
+/// assert_eq!(matches[0].line_number, 1);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_grep_fixed<I>(paths: I, needle: &str) -> Vec<GrepMatch>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+{
+    collect_matches(paths, &Matcher::Fixed(Box::new(Finder::new(needle))))
+}
+
+/// Like ``par_zstd_grep()``, but passing every match to `match_handler` as soon as it is found instead of collecting them.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `pattern` - The regular expression lines have to match somewhere.
+/// * `match_handler` - A function or closure that will handle each matching line.
+///
+/// # Example
+/// ```
+/// use regex::Regex;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_grep_with");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let content: String = (0..1000).map(|i| format!("event {}\n", i)).collect();
+/// std::fs::write(dir.join("events.zst"), zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+///
+/// let count = AtomicUsize::new(0);
+/// zstd_lines::par_zstd_grep_with([dir.join("events.zst")], &Regex::new(r"^event \d*7$").unwrap(), |found| {
+///     assert!(found.line.ends_with('7'));
+///     count.fetch_add(1, Ordering::Relaxed);
+/// });
+/// assert_eq!(count.into_inner(), 100);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_grep_with<I, F>(paths: I, pattern: &Regex, match_handler: F)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(GrepMatch) + Sync + Send,
+{
+    for_each_match(paths, &Matcher::Regex(pattern), match_handler);
+}
+
+/// Like ``par_zstd_grep_fixed()``, but passing every match to `match_handler` as soon as it is found instead of collecting them.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `needle` - The string lines have to contain.
+/// * `match_handler` - A function or closure that will handle each matching line.
+pub fn par_zstd_grep_fixed_with<I, F>(paths: I, needle: &str, match_handler: F)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(GrepMatch) + Sync + Send,
+{
+    for_each_match(
+        paths,
+        &Matcher::Fixed(Box::new(Finder::new(needle))),
+        match_handler,
+    );
+}
+
+/// Collect the matching lines of all files in order.
+fn collect_matches<I>(paths: I, matcher: &Matcher<'_>) -> Vec<GrepMatch>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+{
+    let config = NUMBERED_CONFIG.start_run();
+    let line_handler =
+        |line: &mut Vec<u8>, context: &LineContext<'_>, matches: &mut Vec<GrepMatch>| {
+            matches.extend(matcher.matches(line, context, &config));
+        };
+    paths
+        .into_par_iter()
+        .flat_map_iter(|path| collect_file(path.as_ref(), &config, &line_handler))
+        .collect()
+}
+
+/// Pass the matching lines of all files to the match handler.
+fn for_each_match<I, F>(paths: I, matcher: &Matcher<'_>, match_handler: F)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(GrepMatch) + Sync + Send,
+{
+    let config = NUMBERED_CONFIG.start_run();
+    let line_handler = |line: &mut Vec<u8>, context: &LineContext<'_>| {
+        if let Some(found) = matcher.matches(line, context, &config) {
+            match_handler(found);
+        }
+    };
+    paths.into_par_iter().for_each(|path| {
+        process_file(path.as_ref(), &config, &line_handler);
+    });
+}
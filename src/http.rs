@@ -0,0 +1,59 @@
+//! Streaming zstd compressed files over HTTP(S), available with the `http` feature.
+
+use crate::diagnostics;
+use crate::{process_reader, ContentKind};
+use rayon::prelude::*;
+use std::io;
+use std::path::Path;
+use url::Url;
+
+/// Stream zstd compressed files over HTTP(S) and process them line-by-line and in parallel.
+/// Lines are delivered while the download is still in flight, the URL is passed to the line handler in place of a path.
+///
+/// Redirects are followed and gzip content encoding is removed before the zstd decoder sees the body.
+/// Failed or interrupted downloads are reported per URL like file failures.
+///
+/// # Arguments
+///
+/// * `urls` - The URLs to download.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```no_run
+/// use zstd_lines::Url;
+///
+/// let urls = vec![Url::parse("https://example.com/RC_2023-01.zst").unwrap()];
+/// zstd_lines::par_zstd_lines_urls(urls, |line, url| {
+///     println!("Decompressed line: {} from {}", line, url.display());
+/// });
+/// ```
+pub fn par_zstd_lines_urls<F>(urls: Vec<Url>, line_handler: F)
+where
+    F: Fn(String, &Path) + Sync + Send,
+{
+    let agent = ureq::Agent::new();
+    urls.into_par_iter().for_each(|url| {
+        if let Err(e) = process_url(&agent, &url, &line_handler) {
+            let message = format!("Failed to process zst url {}: {}", url, e);
+            diagnostics::report_message(Path::new(url.as_str()), &message);
+        }
+    });
+}
+
+/// Download a single URL and stream its body through the decoder.
+fn process_url<F>(agent: &ureq::Agent, url: &Url, line_handler: F) -> io::Result<()>
+where
+    F: FnMut(String, &Path),
+{
+    let response = agent
+        .request_url("GET", url)
+        .call()
+        .map_err(io::Error::other)?;
+    let reader = response.into_reader();
+    process_reader(
+        reader,
+        Path::new(url.as_str()),
+        ContentKind::Detect,
+        line_handler,
+    )
+}
@@ -0,0 +1,328 @@
+//! Preparing lists of input files before they are handed to rayon.
+
+use crate::diagnostics;
+use crate::{is_stdin, par_zstd_lines_from};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Policies applied to a list of input files before they are dispatched to rayon.
+#[derive(Debug, Clone)]
+pub struct InputOptions {
+    /// Whether to process files behind symbolic links. If disabled, symlinks are skipped.
+    pub follow_symlinks: bool,
+    /// Whether to skip files whose name starts with a dot.
+    pub skip_hidden: bool,
+    /// Whether to process every physical file only once, judged by device and inode number.
+    /// This is a no-op on platforms without inode numbers.
+    pub dedupe_by_inode: bool,
+}
+
+impl Default for InputOptions {
+    fn default() -> Self {
+        InputOptions {
+            follow_symlinks: true,
+            skip_hidden: false,
+            dedupe_by_inode: false,
+        }
+    }
+}
+
+/// An input file which was rejected while applying ``InputOptions``.
+#[derive(Debug)]
+pub enum InputError {
+    /// The path is a symbolic link whose target does not exist.
+    BrokenSymlink {
+        path: PathBuf,
+        target: Option<PathBuf>,
+    },
+    /// The path could not be inspected.
+    Io { path: PathBuf, source: io::Error },
+}
+
+impl InputError {
+    /// The input path this error belongs to.
+    pub fn path(&self) -> &Path {
+        match self {
+            InputError::BrokenSymlink { path, .. } | InputError::Io { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::BrokenSymlink {
+                path,
+                target: Some(target),
+            } => write!(
+                f,
+                "Broken symlink {} -> {}",
+                path.display(),
+                target.display()
+            ),
+            InputError::BrokenSymlink { path, target: None } => {
+                write!(f, "Broken symlink {}", path.display())
+            }
+            InputError::Io { path, source } => {
+                write!(f, "Failed to inspect {}: {}", path.display(), source)
+            }
+        }
+    }
+}
+
+impl Error for InputError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            InputError::BrokenSymlink { .. } => None,
+            InputError::Io { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Process each line in zstd compressed files in parallel after applying ``InputOptions`` to the list of files.
+/// Rejected inputs are reported on stderr and returned, all other files are processed as usual.
+///
+/// # Arguments
+///
+/// * `paths` - The files to process.
+/// * `options` - Symlink, hidden file and deduplication policies.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use zstd_lines::InputOptions;
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_input");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("a.jsonl.zst"), zstd::encode_all(&b"a\n"[..], 0).unwrap()).unwrap();
+/// std::fs::write(dir.join(".partial.jsonl.zst"), zstd::encode_all(&b"hidden\n"[..], 0).unwrap()).unwrap();
+///
+/// let options = InputOptions {
+///     skip_hidden: true,
+///     dedupe_by_inode: true,
+///     ..InputOptions::default()
+/// };
+/// let files = vec![dir.join("a.jsonl.zst"), dir.join("a.jsonl.zst"), dir.join(".partial.jsonl.zst")];
+/// let lines = Mutex::new(Vec::new());
+/// zstd_lines::par_zstd_lines_with_options(files, &options, |line, _path| lines.lock().unwrap().push(line));
+/// assert_eq!(lines.into_inner().unwrap(), vec!["a"]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_with_options<I, F>(
+    paths: I,
+    options: &InputOptions,
+    line_handler: F,
+) -> Vec<InputError>
+where
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    let (files, errors) = apply_input_options(paths, options);
+    for error in &errors {
+        diagnostics::report_message(error.path(), &error.to_string());
+    }
+    par_zstd_lines_from(files, line_handler);
+    errors
+}
+
+/// Filter a list of inputs according to `options`, returning the remaining files and all rejected inputs.
+fn apply_input_options<I>(paths: I, options: &InputOptions) -> (Vec<PathBuf>, Vec<InputError>)
+where
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+{
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    let mut seen = HashSet::new();
+
+    for path in paths {
+        let path = path.as_ref();
+        if is_stdin(path) {
+            files.push(path.to_path_buf());
+            continue;
+        }
+        if options.skip_hidden
+            && path
+                .file_name()
+                .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+        {
+            continue;
+        }
+
+        let link_metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                // Let the regular processing report files that do not exist at all
+                if e.kind() != io::ErrorKind::NotFound {
+                    errors.push(InputError::Io {
+                        path: path.to_path_buf(),
+                        source: e,
+                    });
+                } else {
+                    files.push(path.to_path_buf());
+                }
+                continue;
+            }
+        };
+
+        let metadata = if link_metadata.file_type().is_symlink() {
+            if !options.follow_symlinks {
+                continue;
+            }
+            match fs::metadata(path) {
+                Ok(metadata) => metadata,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    errors.push(InputError::BrokenSymlink {
+                        path: path.to_path_buf(),
+                        target: fs::read_link(path).ok(),
+                    });
+                    continue;
+                }
+                Err(e) => {
+                    errors.push(InputError::Io {
+                        path: path.to_path_buf(),
+                        source: e,
+                    });
+                    continue;
+                }
+            }
+        } else {
+            link_metadata
+        };
+
+        if options.dedupe_by_inode {
+            if let Some(id) = file_id(&metadata) {
+                if !seen.insert(id) {
+                    continue;
+                }
+            }
+        }
+        files.push(path.to_path_buf());
+    }
+
+    (files, errors)
+}
+
+/// Identify a physical file by device and inode number.
+#[cfg(unix)]
+fn file_id(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+/// Process each line in zstd compressed files in parallel, processing every file only once.
+///
+/// Every path is canonicalized before duplicates are removed, so ``./dump.zst``, ``dump.zst``
+/// and symlinks to the same file are only processed once. Paths which cannot be canonicalized,
+/// e.g. dangling symlinks, are kept as they are and reported when they fail to process.
+/// The line handler receives the path of every file as it was first given.
+///
+/// Returns the number of duplicates which were skipped.
+///
+/// # Arguments
+///
+/// * `paths` - The files to process.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_unique");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// # std::fs::write(dir.join("dump.zst"), zstd::encode_all(&b"a\nb\n"[..], 0).unwrap()).unwrap();
+/// let files = vec![dir.join("dump.zst"), dir.join(".").join("dump.zst")];
+/// let count = AtomicUsize::new(0);
+/// let duplicates = zstd_lines::par_zstd_lines_unique(files, |_line, _path| {
+///     count.fetch_add(1, Ordering::Relaxed);
+/// });
+/// assert_eq!(duplicates, 1);
+/// assert_eq!(count.into_inner(), 2);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_unique<I, F>(paths: I, line_handler: F) -> usize
+where
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    let (files, duplicates) = canonical_unique(paths);
+    par_zstd_lines_from(files, line_handler);
+    duplicates
+}
+
+/// Drop paths which canonicalize to the same file, keeping the first occurrence of every file as it was given.
+/// Returns the remaining paths and the number of duplicates which were dropped.
+fn canonical_unique<I>(paths: I) -> (Vec<PathBuf>, usize)
+where
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+{
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+    let mut duplicates = 0;
+
+    for path in paths {
+        let path = path.as_ref();
+        let canonical = if is_stdin(path) {
+            path.to_path_buf()
+        } else {
+            fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+        };
+        if seen.insert(canonical) {
+            files.push(path.to_path_buf());
+        } else {
+            duplicates += 1;
+        }
+    }
+
+    (files, duplicates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{test_dir, write_zst};
+    use std::sync::Mutex;
+
+    #[test]
+    fn unique_paths_are_kept_as_they_were_given() {
+        let dir = test_dir("unique_paths");
+        fs::write(dir.join("dump.zst"), b"").unwrap();
+        let relative = dir.join(".").join("dump.zst");
+
+        let (files, duplicates) = canonical_unique([&relative, &dir.join("dump.zst"), &relative]);
+        assert_eq!(files, vec![relative.clone()]);
+        assert_eq!(duplicates, 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn broken_symlinks_are_returned_and_the_other_files_processed() {
+        let dir = test_dir("broken_symlink");
+        write_zst(&dir.join("a.zst"), b"a\n");
+        std::os::unix::fs::symlink(dir.join("gone.zst"), dir.join("broken.zst")).unwrap();
+
+        let lines = Mutex::new(Vec::new());
+        let errors = par_zstd_lines_with_options(
+            vec![dir.join("a.zst"), dir.join("broken.zst")],
+            &InputOptions::default(),
+            |line, _path| lines.lock().unwrap().push(line),
+        );
+        assert_eq!(lines.into_inner().unwrap(), vec!["a"]);
+        assert!(
+            matches!(&errors[..], [InputError::BrokenSymlink { path, .. }] if *path == dir.join("broken.zst"))
+        );
+    }
+}
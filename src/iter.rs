@@ -0,0 +1,138 @@
+//! Lines of zstd compressed files as a rayon ``ParallelIterator``.
+
+use crate::config::{Run, ZstdLinesConfig, DEFAULT_CONFIG};
+use crate::text::decode_line;
+use crate::try_process_file_in_order;
+use rayon::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+
+/// A ``ParallelIterator`` over the lines of zstd compressed files and the paths they come from,
+/// created by ``par_zstd_lines_iter()`` or ``ZstdLinesConfig::lines_iter()``.
+///
+/// Files are processed in parallel, the lines of every file are decoded sequentially and handed to the
+/// adapters as they are decoded, so no file is held in memory. Files in the zstd seekable format are decoded
+/// on a single thread. Adapters which stop early like ``find_any()`` stop the files being processed after
+/// the line they are at.
+#[derive(Debug, Clone)]
+pub struct ZstdLinesIter<'c> {
+    paths: Vec<PathBuf>,
+    config: &'c ZstdLinesConfig<'c>,
+}
+
+/// Iterate over the lines in zstd compressed files in parallel with all the adapters of rayon's ``ParallelIterator``.
+/// Each item is the path of the file along with the decompressed line. Failures are reported on stderr like in
+/// ``par_zstd_lines()``.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+///
+/// # Example
+/// ```
+/// use rayon::prelude::*;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_lines_iter");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"1\n2\nx\n"[..], 0).unwrap()).unwrap();
+/// std::fs::write(dir.join("b.zst"), zstd::encode_all(&b"30\n"[..], 0).unwrap()).unwrap();
+///
+/// let total: u32 = zstd_lines::par_zstd_lines_iter([dir.join("a.zst"), dir.join("b.zst")])
+///     .filter_map(|(_path, line)| line.parse::<u32>().ok())
+///     .sum();
+/// assert_eq!(total, 33);
+///
+/// let found = zstd_lines::par_zstd_lines_iter([dir.join("a.zst"), dir.join("b.zst")])
+///     .find_any(|(_path, line)| line == "x");
+/// assert_eq!(found, Some((dir.join("a.zst"), "x".to_string())));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_iter<I>(paths: I) -> ZstdLinesIter<'static>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+{
+    ZstdLinesIter::new(&DEFAULT_CONFIG, paths)
+}
+
+impl<'c> ZstdLinesIter<'c> {
+    pub(crate) fn new<I>(config: &'c ZstdLinesConfig<'c>, paths: I) -> Self
+    where
+        I: IntoParallelIterator,
+        I::Item: AsRef<Path>,
+    {
+        ZstdLinesIter {
+            paths: paths
+                .into_par_iter()
+                .map(|path| path.as_ref().to_path_buf())
+                .collect(),
+            config,
+        }
+    }
+}
+
+impl ParallelIterator for ZstdLinesIter<'_> {
+    type Item = (PathBuf, String);
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        let paths = &self.paths;
+        self.config
+            .install(|run| bridge_unindexed(FileProducer { paths, run }, consumer))
+    }
+}
+
+/// Splits the files between rayon's threads and feeds the lines of each file into a folder.
+struct FileProducer<'a> {
+    paths: &'a [PathBuf],
+    /// Shared by all producers, so files still being processed stop once the consumer is full.
+    run: &'a Run<'a>,
+}
+
+impl UnindexedProducer for FileProducer<'_> {
+    type Item = (PathBuf, String);
+
+    fn split(self) -> (Self, Option<Self>) {
+        if self.paths.len() < 2 {
+            return (self, None);
+        }
+        let (left, right) = self.paths.split_at(self.paths.len() / 2);
+        (
+            FileProducer {
+                paths: left,
+                run: self.run,
+            },
+            Some(FileProducer {
+                paths: right,
+                run: self.run,
+            }),
+        )
+    }
+
+    fn fold_with<F>(self, folder: F) -> F
+    where
+        F: Folder<Self::Item>,
+    {
+        let config = self.run;
+        // The folder is consumed by every line, it is only missing while a line is being folded
+        let mut folder = Some(folder);
+        for path in self.paths {
+            if folder.as_ref().is_some_and(Folder::full) {
+                break;
+            }
+            // Failures are reported like those of every other file
+            let _ = try_process_file_in_order(path, config, |buffer, context| {
+                if let Some(line) = decode_line(buffer, context, config) {
+                    let next = folder.take().unwrap().consume((path.clone(), line));
+                    if next.full() {
+                        config.stop();
+                    }
+                    folder = Some(next);
+                }
+            });
+        }
+        folder.unwrap()
+    }
+}
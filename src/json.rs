@@ -0,0 +1,169 @@
+//! Deserializing JSON lines with serde, available with the `serde` feature.
+
+use crate::config::NUMBERED_CONFIG;
+use crate::context::LineContext;
+use crate::diagnostics;
+use crate::{collect_file, process_file};
+use rayon::prelude::*;
+use serde::de::DeserializeOwned;
+use std::path::Path;
+
+/// Process each line in zstd compressed JSON lines files in parallel, deserializing it into a `T` on the worker thread.
+/// Lines which cannot be deserialized are reported on stderr with their line number and skipped, empty lines are skipped silently.
+/// Since lines are numbered, files in the zstd seekable format are decoded on a single thread.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each deserialized line.
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_json_lines");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let content = b"{\"subreddit\":\"rust\",\"score\":12}\n\n{\"subreddit\":\"pics\",\"score\":3}\n";
+/// std::fs::write(dir.join("comments.zst"), zstd::encode_all(&content[..], 0).unwrap()).unwrap();
+///
+/// let scores = Mutex::new(Vec::new());
+/// zstd_lines::par_zstd_json_lines([dir.join("comments.zst")], |comment: HashMap<String, serde_json::Value>, _path| {
+///     scores.lock().unwrap().push(comment["score"].as_u64().unwrap());
+/// });
+/// assert_eq!(scores.into_inner().unwrap(), vec![12, 3]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_json_lines<I, T, F>(paths: I, line_handler: F)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    T: DeserializeOwned,
+    F: Fn(T, &Path) + Sync + Send,
+{
+    par_zstd_json_lines_with_errors(paths, line_handler, report_error);
+}
+
+/// Like ``par_zstd_json_lines()``, but passing lines which cannot be deserialized to `error_handler` instead of reporting them.
+/// The error handler receives the raw bytes of the line, the path, the number of the line starting at 1, and the error.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each deserialized line.
+/// * `error_handler` - A function or closure that will handle each line which cannot be deserialized.
+///
+/// # Example
+/// ```
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_json_errors");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let content = b"[1, 2]\n[3, \"four\"]\n{\"truncated\":\n[5, 6]\n";
+/// std::fs::write(dir.join("pairs.zst"), zstd::encode_all(&content[..], 0).unwrap()).unwrap();
+///
+/// let pairs = Mutex::new(Vec::new());
+/// let errors = Mutex::new(Vec::new());
+/// zstd_lines::par_zstd_json_lines_with_errors(
+///     [dir.join("pairs.zst")],
+///     |pair: (u32, u32), _path| pairs.lock().unwrap().push(pair),
+///     |line, _path, line_number, _error| {
+///         errors.lock().unwrap().push((line_number, String::from_utf8_lossy(line).into_owned()));
+///     },
+/// );
+/// assert_eq!(pairs.into_inner().unwrap(), vec![(1, 2), (5, 6)]);
+/// assert_eq!(errors.into_inner().unwrap(), vec![
+///     (2, "[3, \"four\"]".to_string()),
+///     (3, "{\"truncated\":".to_string()),
+/// ]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_json_lines_with_errors<I, T, F, E>(paths: I, line_handler: F, error_handler: E)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    T: DeserializeOwned,
+    F: Fn(T, &Path) + Sync + Send,
+    E: Fn(&[u8], &Path, u64, serde_json::Error) + Sync + Send,
+{
+    let config = NUMBERED_CONFIG.start_run();
+    let line_handler = |line: &mut Vec<u8>, context: &LineContext<'_>| {
+        if let Some(value) = deserialize(line, context, &error_handler) {
+            line_handler(value, context.path);
+        }
+    };
+    paths.into_par_iter().for_each(|path| {
+        process_file(path.as_ref(), &config, &line_handler);
+    });
+}
+
+/// Process each line in zstd compressed JSON lines files in parallel, deserializing it into a `T` and collecting what the
+/// line handler returns for it. Results are returned in the order of the paths and lines, lines which cannot be deserialized
+/// are reported on stderr and skipped like in ``par_zstd_json_lines()``.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each deserialized line and return its result.
+///
+/// # Example
+/// ```
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_json_map");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let content = b"{\"id\":1,\"tags\":[\"a\",\"b\"]}\n{\"id\":2,\"tags\":[]}\n";
+/// std::fs::write(dir.join("items.zst"), zstd::encode_all(&content[..], 0).unwrap()).unwrap();
+///
+/// let tag_counts = zstd_lines::par_zstd_json_lines_map([dir.join("items.zst")], |item: serde_json::Value, _path| {
+///     item["tags"].as_array().map_or(0, |tags| tags.len())
+/// });
+/// assert_eq!(tag_counts, vec![2, 0]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_json_lines_map<I, T, R, F>(paths: I, line_handler: F) -> Vec<R>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    T: DeserializeOwned,
+    R: Send,
+    F: Fn(T, &Path) -> R + Sync + Send,
+{
+    let config = NUMBERED_CONFIG.start_run();
+    let line_handler = |line: &mut Vec<u8>, context: &LineContext<'_>, results: &mut Vec<R>| {
+        if let Some(value) = deserialize(line, context, &report_error) {
+            results.push(line_handler(value, context.path));
+        }
+    };
+    paths
+        .into_par_iter()
+        .flat_map_iter(|path| collect_file(path.as_ref(), &config, &line_handler))
+        .collect()
+}
+
+/// Deserialize a single line, passing it to `error_handler` if it is malformed. Empty lines are skipped.
+fn deserialize<T, E>(line: &[u8], context: &LineContext<'_>, error_handler: &E) -> Option<T>
+where
+    T: DeserializeOwned,
+    E: Fn(&[u8], &Path, u64, serde_json::Error),
+{
+    if line.iter().all(u8::is_ascii_whitespace) {
+        return None;
+    }
+    match serde_json::from_slice(line) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            error_handler(line, context.path, context.line_number, e);
+            None
+        }
+    }
+}
+
+/// Report a line which cannot be deserialized on stderr.
+fn report_error(_line: &[u8], path: &Path, line_number: u64, error: serde_json::Error) {
+    let message = format!(
+        "Error parsing line {} from {}: {}",
+        line_number,
+        path.display(),
+        error
+    );
+    diagnostics::report_message(path, &message);
+}
@@ -10,7 +10,7 @@ Simply add this crate as a dependency:
 zstd_lines = { git = "https://github.com/uniQIndividual/zstd-lines" }
 ```
 
-You can then call ``par_zstd_lines()`` on a vector of files:
+You can then call ``par_zstd_lines()`` on a vector, slice or array of files:
 
 ```rust
 use zstd_lines::ParZstdLines;
@@ -20,16 +20,135 @@ let files = vec![PathBuf::from("file.jsonl.zst"), PathBuf::from("file.jsonl.tar.
 files.par_zstd_lines(|line, path| {
     println!("Decompressed line: {} in {:?}", line, path);
 });
+
+// Borrowed slices and array literals work just the same
+["a.jsonl.zst", "b.tar.zst"].par_zstd_lines(|line, path| {
+    println!("Decompressed line: {} in {:?}", line, path);
+});
 ```
 */
 
 use rayon::prelude::*;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Read};
-use std::path::Path;
-use zstd::stream::read::Decoder;
+use std::collections::{BTreeSet, HashSet};
+use std::ffi::OsStr;
+use std::hash::Hash;
+use std::io::{self, BufRead, Cursor, Read};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+mod archive;
+mod cancel;
+mod channel;
+mod checksum;
+mod config;
+mod context;
+mod count;
+#[cfg(feature = "csv")]
+mod csv_records;
+mod dedup;
+mod diagnostics;
+mod dir;
+mod error;
+mod failure;
+mod format;
+mod frame;
+#[cfg(feature = "regex")]
+mod grep;
+#[cfg(feature = "http")]
+mod http;
+mod input;
+mod iter;
+#[cfg(feature = "serde")]
+mod json;
+mod limit;
+mod line_errors;
+mod manifest;
+mod object;
+mod ordered;
+#[cfg(feature = "glob")]
+mod pattern;
+mod pipe;
+mod progress;
+#[cfg(feature = "indicatif")]
+mod progress_bars;
+mod report;
+mod retry;
+mod rewrite;
+mod sample;
+mod schedule;
+mod seekable;
+mod source;
+mod split;
+#[cfg(test)]
+mod test_util;
+mod text;
+mod timeout;
+mod unwind;
+#[cfg(feature = "zip")]
+mod zip_archive;
+
+pub use cancel::{par_zstd_lines_cancellable, CancellationToken, Cancelled};
+pub use channel::par_zstd_lines_channel;
+pub use checksum::{has_content_checksum, ChecksumMismatch};
+pub use config::{FileStats, ZstdLinesConfig};
+pub use context::LineContext;
+pub use count::par_zstd_count_lines;
+#[cfg(feature = "csv")]
+pub use csv_records::{par_zstd_csv_deserialize, par_zstd_csv_records, CsvOptions};
+pub use dedup::{par_zstd_lines_dedup, DedupOptions, DedupSummary};
+pub use dir::{par_zstd_lines_in_dir, DirOptions};
+pub use error::ZstdLinesError;
+pub use failure::{par_zstd_lines_checked, par_zstd_lines_strict, ZstdLinesErrors};
+#[cfg(feature = "regex")]
+pub use grep::{
+    par_zstd_grep, par_zstd_grep_fixed, par_zstd_grep_fixed_with, par_zstd_grep_with, GrepMatch,
+};
+#[cfg(feature = "http")]
+pub use http::par_zstd_lines_urls;
+pub use input::{par_zstd_lines_unique, par_zstd_lines_with_options, InputError, InputOptions};
+pub use iter::{par_zstd_lines_iter, ZstdLinesIter};
+#[cfg(feature = "serde")]
+pub use json::{par_zstd_json_lines, par_zstd_json_lines_map, par_zstd_json_lines_with_errors};
+pub use line_errors::LineErrors;
+pub use manifest::{par_zstd_lines_from_manifest, ManifestSummary};
+pub use object::{par_zstd_lines_objects, FsObjectSource, ObjectSource};
+pub use ordered::par_zstd_lines_ordered;
+#[cfg(feature = "glob")]
+pub use pattern::{par_zstd_lines_glob, GlobError};
+pub use pipe::{par_zstd_lines_pipes, zstd_lines_pipe, PipeOptions};
+pub use progress::{Progress, ProgressEvery};
+#[cfg(feature = "indicatif")]
+pub use progress_bars::ProgressBars;
+pub use report::{par_zstd_lines_report, FileReport, TarEntries};
+pub use retry::{transient_error, RetryPolicy};
+pub use rewrite::{par_zstd_rewrite, LineWriter, RewriteOptions};
+pub use source::{
+    par_zstd_lines_bytes, par_zstd_lines_readers, par_zstd_lines_sources, zstd_lines_bytes,
+    ContentKind, ReaderSource, ZstdSource,
+};
+pub use split::{group_split_parts, par_zstd_lines_parts};
+pub use text::{EmptyLines, LineEnding, LongLines, Utf8Policy};
+pub use unwind::{par_zstd_lines_catch_unwind, FilePanic};
+#[cfg(feature = "http")]
+pub use url::Url;
+
+use archive::{checksum_matches, is_zero_block, TarReader};
+use config::{is_stopped, FileCounter, Run, DEFAULT_CONFIG, NUMBERED_CONFIG};
+use format::{decompress, decompress_entry};
+use line_errors::LineProblems;
+use retry::Attempt;
+use source::{process_reader, process_reader_with};
+use text::{decode_line, decoded, decoded_sync, report_malformed, LineDecoding};
+#[cfg(feature = "zip")]
+use zip_archive::{is_zip_file, process_zip_file};
 
 const TAR_BLOCK_SIZE: usize = 512;
+const STDIN_PATH: &str = "-";
+/// The UTF-8 encoding of U+FEFF, written at the start of text files by some Windows tools.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+/// How much of a line which is too long is read at once while it is skipped.
+const LONG_LINE_CHUNK: u64 = 64 * 1024;
 
 /// Process zstd compressed files line-by-line and in parallel using stream decompression.
 ///
@@ -47,26 +166,241 @@ const TAR_BLOCK_SIZE: usize = 512;
 ///     println!("Decompressed line: {} in {:?}", line, path);
 /// });
 /// ```
-
+///
+/// Files which are not zstd compressed are split into lines as they are:
+/// ```
+/// use zstd_lines::ParZstdLines;
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_plain");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("dump.jsonl"), b"{\"a\":1}\n{\"a\":2}\n").unwrap();
+///
+/// let lines = Mutex::new(Vec::new());
+/// [dir.join("dump.jsonl")].par_zstd_lines(|line, _path| lines.lock().unwrap().push(line));
+/// assert_eq!(lines.into_inner().unwrap(), vec![r#"{"a":1}"#, r#"{"a":2}"#]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+///
+/// Files in the zstd seekable format, e.g. from ``t2sz`` or ``zstd --seekable``, are split into ranges of frames which are decoded in parallel.
+/// Lines spanning frames are still delivered once and in one piece, but the lines of such a file are no longer delivered in order:
+/// ```
+/// use zstd_lines::ParZstdLines;
+/// use std::path::PathBuf;
+///
+/// // Collected results keep the order of the lines
+/// let lines = [PathBuf::from("dump.jsonl.zst")].par_zstd_lines_map(|line, _path| line);
+/// ```
 pub trait ParZstdLines {
     /// Process each line in zstd compressed files in parallel using stream decompression.
-    /// Can be called on a vector of ``AsRef<Path>``, e.g. ``Path``, ``PathBuf``, ``String`` and ``str``
+    /// Can be called on a vector, slice, array, boxed slice, ``HashSet`` or ``BTreeSet`` of ``AsRef<Path>``, e.g. ``Path``, ``PathBuf``, ``String`` and ``str``
     /// It will attempt to treat .tar files as one continuous file, omitting all tar headers.
-    /// 
+    ///
     /// # Arguments
     ///
     /// * `line_handler` - A function or closure that will handle each decompressed line.
     ///
     /// # Example
     /// ```
+    /// use zstd_lines::ParZstdLines;
+    /// use std::path::PathBuf;
+    ///
     /// let files = vec![PathBuf::from("file.jsonl.zst"), PathBuf::from("file.jsonl.tar.zst")];
     /// files.par_zstd_lines(|line, path| {
     ///     println!("Decompressed line: {} in {:?}", line, path);
     /// });
     /// ```
+    ///
+    /// File names do not need to be valid UTF-8:
+    /// ```
+    /// # #[cfg(target_os = "linux")]
+    /// # {
+    /// use zstd_lines::ParZstdLines;
+    /// use std::ffi::OsStr;
+    /// use std::os::unix::ffi::OsStrExt;
+    /// use std::sync::Mutex;
+    ///
+    /// let dir = std::env::temp_dir().join("zstd_lines_doc_latin1");
+    /// std::fs::create_dir_all(&dir).unwrap();
+    /// // "caf\xe9.jsonl.zst" in latin-1
+    /// let file = dir.join(OsStr::from_bytes(b"caf\xe9.jsonl.zst"));
+    /// std::fs::write(&file, zstd::encode_all(&b"line\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let lines = Mutex::new(Vec::new());
+    /// vec![file].par_zstd_lines(|line, _path| lines.lock().unwrap().push(line));
+    /// assert_eq!(lines.into_inner().unwrap(), vec!["line"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// # }
+    /// ```
     fn par_zstd_lines<F>(&self, line_handler: F)
     where
         F: Fn(String, &Path) + Sync + Send;
+
+    /// Process each line in zstd compressed files in parallel, passing a ``LineContext`` with the path,
+    /// line number, byte offset and tar entry of every line instead of just the path.
+    ///
+    /// # Arguments
+    ///
+    /// * `line_handler` - A function or closure that will handle each decompressed line and its context.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::{LineContext, ParZstdLines};
+    /// use std::sync::Mutex;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_ctx_trait");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("dump.jsonl.zst"), zstd::encode_all(&b"first\nsecond\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let lines = Mutex::new(Vec::new());
+    /// vec![dir.join("dump.jsonl.zst")].par_zstd_lines_ctx(|line, ctx: &LineContext| {
+    ///     lines.lock().unwrap().push(format!("{}:{}@{} {}", ctx.path.display(), ctx.line_number, ctx.byte_offset, line));
+    /// });
+    /// let root = dir.join("dump.jsonl.zst").display().to_string();
+    /// assert_eq!(lines.into_inner().unwrap(), vec![
+    ///     format!("{}:1@0 first", root),
+    ///     format!("{}:2@6 second", root),
+    /// ]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    fn par_zstd_lines_ctx<F>(&self, line_handler: F)
+    where
+        F: Fn(String, &LineContext<'_>) + Sync + Send;
+
+    /// Process each line in zstd compressed files in parallel and collect what the line handler returns for every line,
+    /// see ``par_zstd_lines_map()``.
+    fn par_zstd_lines_map<R, F>(&self, line_handler: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(String, &Path) -> R + Sync + Send;
+
+    /// Process each line in zstd compressed files in parallel and collect the results the line handler returns ``Some`` for,
+    /// see ``par_zstd_lines_filter_map()``.
+    fn par_zstd_lines_filter_map<R, F>(&self, line_handler: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(String, &Path) -> Option<R> + Sync + Send;
+
+    /// Process each line in zstd compressed files in parallel and collect the lines matching `predicate`,
+    /// see ``par_zstd_lines_filter()``.
+    fn par_zstd_lines_filter<F>(&self, predicate: F) -> Vec<String>
+    where
+        F: Fn(&str, &Path) -> bool + Sync + Send;
+
+    /// Iterate over the lines in zstd compressed files in parallel with all the adapters of rayon's ``ParallelIterator``,
+    /// see ``par_zstd_lines_iter()``.
+    ///
+    /// # Example
+    /// ```
+    /// use rayon::prelude::*;
+    /// use zstd_lines::ParZstdLines;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_lines_iter_trait");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("dump.jsonl.zst"), zstd::encode_all(&b"b\na\nb\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let mut lines: Vec<String> = vec![dir.join("dump.jsonl.zst")]
+    ///     .par_zstd_lines_iter()
+    ///     .map(|(_path, line)| line)
+    ///     .collect();
+    /// lines.sort();
+    /// lines.dedup();
+    /// assert_eq!(lines, vec!["a", "b"]);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// ```
+    fn par_zstd_lines_iter(&self) -> ZstdLinesIter<'static>;
+}
+
+impl<T> ParZstdLines for [T]
+where
+    T: AsRef<Path> + Sync,
+{
+    fn par_zstd_lines<F>(&self, line_handler: F)
+    where
+        F: Fn(String, &Path) + Sync + Send,
+    {
+        par_zstd_lines_from(self, line_handler);
+    }
+
+    fn par_zstd_lines_ctx<F>(&self, line_handler: F)
+    where
+        F: Fn(String, &LineContext<'_>) + Sync + Send,
+    {
+        par_zstd_lines_ctx(self, line_handler);
+    }
+
+    fn par_zstd_lines_map<R, F>(&self, line_handler: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(String, &Path) -> R + Sync + Send,
+    {
+        par_zstd_lines_map(self, line_handler)
+    }
+
+    fn par_zstd_lines_filter_map<R, F>(&self, line_handler: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(String, &Path) -> Option<R> + Sync + Send,
+    {
+        par_zstd_lines_filter_map(self, line_handler)
+    }
+
+    fn par_zstd_lines_filter<F>(&self, predicate: F) -> Vec<String>
+    where
+        F: Fn(&str, &Path) -> bool + Sync + Send,
+    {
+        par_zstd_lines_filter(self, predicate)
+    }
+
+    fn par_zstd_lines_iter(&self) -> ZstdLinesIter<'static> {
+        par_zstd_lines_iter(self)
+    }
+}
+
+impl<T, const N: usize> ParZstdLines for [T; N]
+where
+    T: AsRef<Path> + Sync,
+{
+    fn par_zstd_lines<F>(&self, line_handler: F)
+    where
+        F: Fn(String, &Path) + Sync + Send,
+    {
+        par_zstd_lines_from(self, line_handler);
+    }
+
+    fn par_zstd_lines_ctx<F>(&self, line_handler: F)
+    where
+        F: Fn(String, &LineContext<'_>) + Sync + Send,
+    {
+        par_zstd_lines_ctx(self, line_handler);
+    }
+
+    fn par_zstd_lines_map<R, F>(&self, line_handler: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(String, &Path) -> R + Sync + Send,
+    {
+        par_zstd_lines_map(self, line_handler)
+    }
+
+    fn par_zstd_lines_filter_map<R, F>(&self, line_handler: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(String, &Path) -> Option<R> + Sync + Send,
+    {
+        par_zstd_lines_filter_map(self, line_handler)
+    }
+
+    fn par_zstd_lines_filter<F>(&self, predicate: F) -> Vec<String>
+    where
+        F: Fn(&str, &Path) -> bool + Sync + Send,
+    {
+        par_zstd_lines_filter(self, predicate)
+    }
+
+    fn par_zstd_lines_iter(&self) -> ZstdLinesIter<'static> {
+        par_zstd_lines_iter(self)
+    }
 }
 
 impl<T> ParZstdLines for Vec<T>
@@ -77,111 +411,2560 @@ where
     where
         F: Fn(String, &Path) + Sync + Send,
     {
-        self.par_iter().for_each(|path| {
-            let path = path.as_ref();
-            if let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) {
-                if stem.ends_with(".tar") {
-                    // Handle as .tar.zst file
-                    if let Err(e) = process_tar_zstd_file(path, &line_handler) {
-                        eprintln!("Failed to process tar.zst file {}: {}", path.display(), e);
-                    }
-                } else {
-                    // Handle as regular .zst files with a faster algorithm
-                    if let Err(e) = process_zstd_file(path, &line_handler) {
-                        eprintln!("Failed to process zst file {}: {}", path.display(), e);
-                    }
-                }
-            }
-        });
+        par_zstd_lines_from(self, line_handler);
+    }
+
+    fn par_zstd_lines_ctx<F>(&self, line_handler: F)
+    where
+        F: Fn(String, &LineContext<'_>) + Sync + Send,
+    {
+        par_zstd_lines_ctx(self, line_handler);
+    }
+
+    fn par_zstd_lines_map<R, F>(&self, line_handler: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(String, &Path) -> R + Sync + Send,
+    {
+        par_zstd_lines_map(self, line_handler)
+    }
+
+    fn par_zstd_lines_filter_map<R, F>(&self, line_handler: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(String, &Path) -> Option<R> + Sync + Send,
+    {
+        par_zstd_lines_filter_map(self, line_handler)
+    }
+
+    fn par_zstd_lines_filter<F>(&self, predicate: F) -> Vec<String>
+    where
+        F: Fn(&str, &Path) -> bool + Sync + Send,
+    {
+        par_zstd_lines_filter(self, predicate)
+    }
+
+    fn par_zstd_lines_iter(&self) -> ZstdLinesIter<'static> {
+        par_zstd_lines_iter(self)
     }
 }
 
-/// Process a regular zstd-compressed file, passing each line to the line handler function.
-fn process_zstd_file<F>(path: &Path, line_handler: &F) -> io::Result<()>
+impl<T> ParZstdLines for Box<[T]>
 where
-    F: Fn(String, &Path) + Sync + Send,
+    T: AsRef<Path> + Sync,
 {
-    let file = File::open(path)?;
-    let decoder = Decoder::new(file)?;
-    let reader = BufReader::new(decoder);
+    fn par_zstd_lines<F>(&self, line_handler: F)
+    where
+        F: Fn(String, &Path) + Sync + Send,
+    {
+        par_zstd_lines_from(self.as_ref(), line_handler);
+    }
 
-    for line in reader.lines() {
-        match line {
-            Ok(content) => line_handler(content, path),
-            Err(e) => eprintln!("Error reading line from {}: {}", path.display(), e),
-        }
+    fn par_zstd_lines_ctx<F>(&self, line_handler: F)
+    where
+        F: Fn(String, &LineContext<'_>) + Sync + Send,
+    {
+        par_zstd_lines_ctx(self.as_ref(), line_handler);
     }
 
-    Ok(())
+    fn par_zstd_lines_map<R, F>(&self, line_handler: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(String, &Path) -> R + Sync + Send,
+    {
+        par_zstd_lines_map(self.as_ref(), line_handler)
+    }
+
+    fn par_zstd_lines_filter_map<R, F>(&self, line_handler: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(String, &Path) -> Option<R> + Sync + Send,
+    {
+        par_zstd_lines_filter_map(self.as_ref(), line_handler)
+    }
+
+    fn par_zstd_lines_filter<F>(&self, predicate: F) -> Vec<String>
+    where
+        F: Fn(&str, &Path) -> bool + Sync + Send,
+    {
+        par_zstd_lines_filter(self.as_ref(), predicate)
+    }
+
+    fn par_zstd_lines_iter(&self) -> ZstdLinesIter<'static> {
+        par_zstd_lines_iter(self.as_ref())
+    }
 }
 
-/// Process a tar file line by line, skipping TAR headers and handling file boundaries.
-fn process_tar_zstd_file<F>(path: &Path, line_handler: &F) -> io::Result<()>
+impl<T> ParZstdLines for HashSet<T>
 where
-    F: Fn(String, &Path) + Sync + Send,
+    T: AsRef<Path> + Hash + Eq + Sync,
 {
-    let file = File::open(path)?;
-    let mut decoder = Decoder::new(file)?;
+    fn par_zstd_lines<F>(&self, line_handler: F)
+    where
+        F: Fn(String, &Path) + Sync + Send,
+    {
+        par_zstd_lines_from(self, line_handler);
+    }
 
-    let mut buffer = [0; TAR_BLOCK_SIZE];
-    let mut remainder = Vec::new(); // We want to delay working with Strings as long as possible
+    fn par_zstd_lines_ctx<F>(&self, line_handler: F)
+    where
+        F: Fn(String, &LineContext<'_>) + Sync + Send,
+    {
+        par_zstd_lines_ctx(self, line_handler);
+    }
 
-    loop {
-        let bytes_read = decoder.read(&mut buffer)?;
+    fn par_zstd_lines_map<R, F>(&self, line_handler: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(String, &Path) -> R + Sync + Send,
+    {
+        par_zstd_lines_map(self, line_handler)
+    }
 
-        if bytes_read == 0 {
-            break;
-        }
+    fn par_zstd_lines_filter_map<R, F>(&self, line_handler: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(String, &Path) -> Option<R> + Sync + Send,
+    {
+        par_zstd_lines_filter_map(self, line_handler)
+    }
 
-        // Check if the current 512-byte block is a TAR header indicating a new file
-        if is_tar_header(&buffer) {
-            // Send the remainder as a line if not empty
-            if !remainder.is_empty() {
-                if let Ok(line) = String::from_utf8(remainder.clone()) {
-                    line_handler(line, path);
-                }
-                remainder.clear();
-            }
-            continue;
-        }
+    fn par_zstd_lines_filter<F>(&self, predicate: F) -> Vec<String>
+    where
+        F: Fn(&str, &Path) -> bool + Sync + Send,
+    {
+        par_zstd_lines_filter(self, predicate)
+    }
 
-        let mut offset = 0;
-        // Iterate over the buffer, identifying newlines and storing the remainder
-        for i in 0..bytes_read {
-            if buffer[i] == b'\n' {
-                // Found a newline, extract the line
-                let end = i;
-                let mut line_bytes = remainder.clone(); // Include previous remainder
-                line_bytes.extend_from_slice(&buffer[offset..end]);
-                if let Ok(line) = String::from_utf8(line_bytes) {
-                    line_handler(line, path);
-                }
-                remainder.clear();
-                offset = i + 1;
-            }
-        }
+    fn par_zstd_lines_iter(&self) -> ZstdLinesIter<'static> {
+        par_zstd_lines_iter(self)
+    }
+}
 
-        // Store any remaining bytes after the last newline
-        if offset < bytes_read {
-            remainder.extend_from_slice(&buffer[offset..bytes_read]);
-        }
+impl<T> ParZstdLines for BTreeSet<T>
+where
+    T: AsRef<Path> + Ord + Sync,
+{
+    fn par_zstd_lines<F>(&self, line_handler: F)
+    where
+        F: Fn(String, &Path) + Sync + Send,
+    {
+        par_zstd_lines_from(self, line_handler);
     }
 
-    // Process any remaining content in remainder as a final line
-    if !remainder.is_empty() {
-        if let Ok(line) = String::from_utf8(remainder) {
-            line_handler(line, path);
-        }
+    fn par_zstd_lines_ctx<F>(&self, line_handler: F)
+    where
+        F: Fn(String, &LineContext<'_>) + Sync + Send,
+    {
+        par_zstd_lines_ctx(self, line_handler);
     }
 
-    Ok(())
+    fn par_zstd_lines_map<R, F>(&self, line_handler: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(String, &Path) -> R + Sync + Send,
+    {
+        par_zstd_lines_map(self, line_handler)
+    }
+
+    fn par_zstd_lines_filter_map<R, F>(&self, line_handler: F) -> Vec<R>
+    where
+        R: Send,
+        F: Fn(String, &Path) -> Option<R> + Sync + Send,
+    {
+        par_zstd_lines_filter_map(self, line_handler)
+    }
+
+    fn par_zstd_lines_filter<F>(&self, predicate: F) -> Vec<String>
+    where
+        F: Fn(&str, &Path) -> bool + Sync + Send,
+    {
+        par_zstd_lines_filter(self, predicate)
+    }
+
+    fn par_zstd_lines_iter(&self) -> ZstdLinesIter<'static> {
+        par_zstd_lines_iter(self)
+    }
 }
 
-/// Check if the provided 512-byte block is a TAR header by examining expected fields.
-fn is_tar_header(block: &[u8]) -> bool {
-    if block.len() != TAR_BLOCK_SIZE {
-        return false;
+/// Process each line in zstd compressed files in parallel, consuming any parallel iterator of paths.
+/// This allows feeding the rayon pipeline directly without collecting all paths into a vector first.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use rayon::prelude::*;
+/// use std::path::PathBuf;
+///
+/// let files = (0..4)
+///     .into_par_iter()
+///     .map(|i| PathBuf::from(format!("part-{}.jsonl.zst", i)));
+/// zstd_lines::par_zstd_lines_from(files, |line, path| {
+///     println!("Decompressed line: {} in {:?}", line, path);
+/// });
+/// ```
+pub fn par_zstd_lines_from<I, F>(paths: I, line_handler: F)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    DEFAULT_CONFIG.run(paths, line_handler);
+}
+
+/// Process each line in zstd compressed files in parallel, passing a ``LineContext`` with everything known about
+/// where the line comes from. The context is borrowed for the duration of the call, so filling it allocates nothing.
+/// Since the context includes the line number, files in the zstd seekable format are decoded on a single thread.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each decompressed line and its context.
+///
+/// # Example
+/// ```
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_ctx");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let mut tar = tar::Builder::new(Vec::new());
+/// for (name, content) in [("a.jsonl", &b"a1\na2\n"[..]), ("b.jsonl", &b"b1\n"[..])] {
+///     let mut header = tar::Header::new_ustar();
+///     header.set_size(content.len() as u64);
+///     tar.append_data(&mut header, name, content).unwrap();
+/// }
+/// std::fs::write(dir.join("dump.tar.zst"), zstd::encode_all(&tar.into_inner().unwrap()[..], 0).unwrap()).unwrap();
+///
+/// let lines = Mutex::new(Vec::new());
+/// zstd_lines::par_zstd_lines_ctx([dir.join("dump.tar.zst")], |line, ctx| {
+///     let entry = ctx.tar_entry.unwrap().to_owned();
+///     lines.lock().unwrap().push((entry, ctx.line_number, ctx.byte_offset, line));
+/// });
+/// assert_eq!(lines.into_inner().unwrap(), vec![
+///     ("a.jsonl".to_string(), 1, 0, "a1".to_string()),
+///     ("a.jsonl".to_string(), 2, 3, "a2".to_string()),
+///     ("b.jsonl".to_string(), 1, 0, "b1".to_string()),
+/// ]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_ctx<I, F>(paths: I, line_handler: F)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(String, &LineContext<'_>) + Sync + Send,
+{
+    NUMBERED_CONFIG.run_with_ctx(paths, line_handler);
+}
+
+/// Process each line in zstd compressed files in parallel, also passing the name of the tar entry each line comes from.
+/// The entry name is ``None`` for lines which are not part of a tar archive.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each decompressed line, receiving the path and the entry name.
+///
+/// # Example
+/// ```
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_with_entry");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let mut tar = tar::Builder::new(Vec::new());
+/// let mut header = tar::Header::new_ustar();
+/// header.set_size(8);
+/// tar.append_data(&mut header, "a.jsonl", &b"{\"a\":1}\n"[..]).unwrap();
+/// std::fs::write(dir.join("dump.tar.zst"), zstd::encode_all(&tar.into_inner().unwrap()[..], 0).unwrap()).unwrap();
+///
+/// let lines = Mutex::new(Vec::new());
+/// zstd_lines::par_zstd_lines_with_entry([dir.join("dump.tar.zst")], |line, _path, entry| {
+///     lines.lock().unwrap().push((line, entry.map(str::to_owned)));
+/// });
+/// assert_eq!(lines.into_inner().unwrap(), vec![(r#"{"a":1}"#.to_string(), Some("a.jsonl".to_string()))]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_with_entry<I, F>(paths: I, line_handler: F)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(String, &Path, Option<&str>) + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    let line_handler = decoded_sync(&config, |line, context| {
+        line_handler(line, context.path, context.tar_entry)
+    });
+    paths.into_par_iter().for_each(|path| {
+        process_file(path.as_ref(), &config, &line_handler);
+    });
+}
+
+/// Process each line in zstd compressed files in parallel, also passing the number of each line, starting at 1.
+/// Lines are numbered per file and per tar entry, so the numbers match what ``zstdcat file | nl -ba`` prints for plain files.
+/// Lines which are skipped because they are not valid UTF-8 are counted as well.
+/// Files in the zstd seekable format are decoded on a single thread, since numbering depends on all lines before.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each decompressed line and its number.
+///
+/// # Example
+/// ```
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_enumerated");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let content = b"first\nbroken \xFF\nthird\n";
+/// std::fs::write(dir.join("dump.jsonl.zst"), zstd::encode_all(&content[..], 0).unwrap()).unwrap();
+///
+/// let lines = Mutex::new(Vec::new());
+/// zstd_lines::par_zstd_lines_enumerated([dir.join("dump.jsonl.zst")], |line, _path, number| {
+///     lines.lock().unwrap().push((number, line));
+/// });
+/// // The invalid line is number 2, so the line after it is number 3
+/// assert_eq!(lines.into_inner().unwrap(), vec![(1, "first".to_string()), (3, "third".to_string())]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_enumerated<I, F>(paths: I, line_handler: F)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(String, &Path, u64) + Sync + Send,
+{
+    let config = NUMBERED_CONFIG.start_run();
+    let line_handler = decoded_sync(&config, |line, context| {
+        line_handler(line, context.path, context.line_number)
+    });
+    paths.into_par_iter().for_each(|path| {
+        process_file(path.as_ref(), &config, &line_handler);
+    });
+}
+
+/// Process each line in zstd compressed files in parallel, also passing the offset at which each line starts
+/// in the decompressed content. The offsets count the line breaks, so the next line starts right after the previous one.
+/// Within tar archives, offsets are relative to the content of the entry, headers are not counted.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each decompressed line and its offset.
+///
+/// # Example
+/// ```
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_offsets");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("dump.jsonl.zst"), zstd::encode_all(&b"first\r\nsecond\n"[..], 0).unwrap()).unwrap();
+///
+/// let lines = Mutex::new(Vec::new());
+/// zstd_lines::par_zstd_lines_with_offsets([dir.join("dump.jsonl.zst")], |line, _path, offset| {
+///     lines.lock().unwrap().push((offset, line));
+/// });
+/// assert_eq!(lines.into_inner().unwrap(), vec![(0, "first".to_string()), (7, "second".to_string())]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_with_offsets<I, F>(paths: I, line_handler: F)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(String, &Path, u64) + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    let line_handler = decoded_sync(&config, |line, context| {
+        line_handler(line, context.path, context.byte_offset)
+    });
+    paths.into_par_iter().for_each(|path| {
+        process_file(path.as_ref(), &config, &line_handler);
+    });
+}
+
+/// Process each line in zstd compressed files in parallel, passing the raw bytes of the line instead of a ``String``.
+/// Lines are neither validated nor decoded, so lines which are not valid UTF-8 are delivered as well and no ``String`` is allocated.
+/// The line break is removed like for every other line handler, see ``ZstdLinesConfig::line_ending()``.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle the bytes of each decompressed line.
+///
+/// # Example
+/// ```
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_byte_lines");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let content = b"{\"a\":1}\r\n{\"blob\":\"\xFF\xFE\"}\n";
+/// std::fs::write(dir.join("dump.jsonl.zst"), zstd::encode_all(&content[..], 0).unwrap()).unwrap();
+///
+/// let lines = Mutex::new(Vec::new());
+/// zstd_lines::par_zstd_byte_lines([dir.join("dump.jsonl.zst")], |line: &[u8], _path| {
+///     lines.lock().unwrap().push(line.to_vec());
+/// });
+/// assert_eq!(lines.into_inner().unwrap(), vec![b"{\"a\":1}".to_vec(), b"{\"blob\":\"\xFF\xFE\"}".to_vec()]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_byte_lines<I, F>(paths: I, line_handler: F)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(&[u8], &Path) + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    let line_handler =
+        |line: &mut Vec<u8>, context: &LineContext<'_>| line_handler(line, context.path);
+    paths.into_par_iter().for_each(|path| {
+        process_file(path.as_ref(), &config, &line_handler);
+    });
+}
+
+/// Process each line in zstd compressed files in parallel, passing a ``&str`` borrowed from a buffer which is reused for every line.
+/// Unlike ``par_zstd_lines()``, no ``String`` is allocated per line, the line only lives for the duration of the call.
+/// Copy it with ``to_owned()`` if it is needed afterwards. Lines which are not valid UTF-8 are reported and skipped.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_lines_ref");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let content = "{\"status\":\"ok\"}\n{\"status\":\"error\"}\n{\"status\":\"ok\"}\n";
+/// std::fs::write(dir.join("dump.jsonl.zst"), zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+///
+/// let errors = AtomicUsize::new(0);
+/// zstd_lines::par_zstd_lines_ref([dir.join("dump.jsonl.zst")], |line: &str, _path| {
+///     if line.contains("\"error\"") {
+///         errors.fetch_add(1, Ordering::Relaxed);
+///     }
+/// });
+/// assert_eq!(errors.into_inner(), 1);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_ref<I, F>(paths: I, line_handler: F)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(&str, &Path) + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    let line_handler =
+        |line: &mut Vec<u8>, context: &LineContext<'_>| match std::str::from_utf8(line) {
+            Ok(line) => line_handler(line, context.path),
+            Err(_) => report_malformed(context, &config, "UTF-8"),
+        };
+    paths.into_par_iter().for_each(|path| {
+        process_file(path.as_ref(), &config, &line_handler);
+    });
+}
+
+/// Process each line in zstd compressed files in parallel and collect what the line handler returns for every line.
+/// Results are collected by rayon on every thread and merged at the end, so the line handler needs no synchronization.
+/// They are returned in the order of the paths, and the results of every file in the order of its lines.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each decompressed line and return its result.
+///
+/// # Example
+/// ```
+/// use zstd_lines::ParZstdLines;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_map");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("a.jsonl.zst"), zstd::encode_all(&b"first\nsecond\n"[..], 0).unwrap()).unwrap();
+/// let mut tar = tar::Builder::new(Vec::new());
+/// let mut header = tar::Header::new_ustar();
+/// header.set_size(6);
+/// tar.append_data(&mut header, "b.jsonl", &b"third\n"[..]).unwrap();
+/// std::fs::write(dir.join("b.tar.zst"), zstd::encode_all(&tar.into_inner().unwrap()[..], 0).unwrap()).unwrap();
+///
+/// let lengths = zstd_lines::par_zstd_lines_map([dir.join("a.jsonl.zst"), dir.join("b.tar.zst")], |line, _path| line.len());
+/// assert_eq!(lengths, vec![5, 6, 5]);
+///
+/// let files = vec![dir.join("b.tar.zst"), dir.join("a.jsonl.zst")];
+/// assert_eq!(files.par_zstd_lines_map(|line, _path| line), vec!["third", "first", "second"]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_map<I, R, F>(paths: I, line_handler: F) -> Vec<R>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    R: Send,
+    F: Fn(String, &Path) -> R + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    let line_handler = |buffer: &mut Vec<u8>, context: &LineContext<'_>, results: &mut Vec<R>| {
+        if let Some(line) = decode_line(buffer, context, &config) {
+            results.push(line_handler(line, context.path));
+        }
+    };
+    paths
+        .into_par_iter()
+        .flat_map_iter(|path| collect_file(path.as_ref(), &config, &line_handler))
+        .collect()
+}
+
+/// Process each line in zstd compressed files in parallel and collect the results the line handler returns ``Some`` for.
+/// Lines the line handler returns ``None`` for cost nothing besides the call. Like for ``par_zstd_lines_map()``,
+/// results are returned in the order of the paths and lines, which does not depend on how the work is split across threads.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each decompressed line and return a result for the lines to keep.
+///
+/// # Example
+/// ```
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_filter_map");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let content = "{\"subreddit\":\"rust\",\"id\":1}\n{\"subreddit\":\"pics\",\"id\":2}\n{\"subreddit\":\"rust\",\"id\":3}\n";
+/// std::fs::write(dir.join("comments.zst"), zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+/// let mut tar = tar::Builder::new(Vec::new());
+/// let mut header = tar::Header::new_ustar();
+/// header.set_size(content.len() as u64);
+/// tar.append_data(&mut header, "comments.jsonl", content.as_bytes()).unwrap();
+/// std::fs::write(dir.join("comments.tar.zst"), zstd::encode_all(&tar.into_inner().unwrap()[..], 0).unwrap()).unwrap();
+///
+/// for file in ["comments.zst", "comments.tar.zst"] {
+///     let ids = zstd_lines::par_zstd_lines_filter_map([dir.join(file)], |line, _path| {
+///         if !line.contains("\"subreddit\":\"rust\"") {
+///             return None;
+///         }
+///         let id = line.rsplit(':').next()?.trim_end_matches('}');
+///         id.parse::<u64>().ok()
+///     });
+///     assert_eq!(ids, vec![1, 3]);
+/// }
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_filter_map<I, R, F>(paths: I, line_handler: F) -> Vec<R>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    R: Send,
+    F: Fn(String, &Path) -> Option<R> + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    let line_handler = |buffer: &mut Vec<u8>, context: &LineContext<'_>, results: &mut Vec<R>| {
+        if let Some(line) = decode_line(buffer, context, &config) {
+            results.extend(line_handler(line, context.path));
+        }
+    };
+    paths
+        .into_par_iter()
+        .flat_map_iter(|path| collect_file(path.as_ref(), &config, &line_handler))
+        .collect()
+}
+
+/// Process each line in zstd compressed files in parallel and collect the lines `predicate` returns ``true`` for.
+/// The predicate looks at the line before it is copied, so only matching lines are allocated as a ``String``.
+/// Lines are returned in the order of the paths and lines.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `predicate` - A function or closure deciding for each decompressed line whether it is kept.
+///
+/// # Example
+/// ```
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_filter");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let content = "GET /index.html 200\nGET /missing 404\nPOST /login 500\n";
+/// std::fs::write(dir.join("access.log.zst"), zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+///
+/// let errors = zstd_lines::par_zstd_lines_filter([dir.join("access.log.zst")], |line, _path| !line.ends_with(" 200"));
+/// assert_eq!(errors, vec!["GET /missing 404", "POST /login 500"]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_filter<I, F>(paths: I, predicate: F) -> Vec<String>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(&str, &Path) -> bool + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    let line_handler =
+        |buffer: &mut Vec<u8>, context: &LineContext<'_>, results: &mut Vec<String>| {
+            match std::str::from_utf8(buffer) {
+                Ok(line) if predicate(line, context.path) => results.push(line.to_owned()),
+                Ok(_) => {}
+                Err(_) => report_malformed(context, &config, "UTF-8"),
+            }
+        };
+    paths
+        .into_par_iter()
+        .flat_map_iter(|path| collect_file(path.as_ref(), &config, &line_handler))
+        .collect()
+}
+
+/// Process each line in zstd compressed files in parallel and fold all lines into a single value, like rayon's ``fold()`` and ``reduce()``.
+/// Every file, or every range of a file in the zstd seekable format, folds its lines into its own accumulator created by `init`,
+/// and accumulators are merged with `combine` at the end, so no state is shared between threads.
+/// `init` is called at least once per file and may be called more often, it should return an identity for `combine`.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `init` - Creates an empty accumulator.
+/// * `fold` - Adds a decompressed line to an accumulator.
+/// * `combine` - Merges two accumulators.
+///
+/// # Example
+/// ```
+/// use std::collections::HashMap;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_fold");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"rust\npics\nrust\n"[..], 0).unwrap()).unwrap();
+///
+/// let counts = zstd_lines::par_zstd_lines_fold(
+///     [dir.join("a.zst")],
+///     HashMap::new,
+///     |mut counts, line, _path| {
+///         *counts.entry(line).or_insert(0) += 1;
+///         counts
+///     },
+///     |mut counts, other| {
+///         for (line, count) in other {
+///             *counts.entry(line).or_insert(0) += count;
+///         }
+///         counts
+///     },
+/// );
+/// assert_eq!(counts, HashMap::from([("rust".to_string(), 2), ("pics".to_string(), 1)]));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_fold<P, A, I, F, C>(paths: P, init: I, fold: F, combine: C) -> A
+where
+    P: IntoParallelIterator,
+    P::Item: AsRef<Path>,
+    A: Send,
+    I: Fn() -> A + Sync + Send,
+    F: Fn(A, String, &Path) -> A + Sync + Send,
+    C: Fn(A, A) -> A + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    let line_handler =
+        |accumulator, buffer: &mut Vec<u8>, context: &LineContext<'_>| match decode_line(
+            buffer, context, &config,
+        ) {
+            Some(line) => fold(accumulator, line, context.path),
+            None => accumulator,
+        };
+    paths
+        .into_par_iter()
+        .map(|path| fold_file(path.as_ref(), &config, &init, &line_handler, &combine))
+        .reduce(&init, &combine)
+}
+
+/// Process each line in zstd compressed files in parallel with per-thread state, like rayon's ``map_init()``, e.g. for a compiled
+/// pattern or a database connection which should neither be shared between threads nor created for every line.
+/// `init` is called once for every part of the work rayon hands to a thread, which is at least once per worker thread taking part
+/// and at most once per file. The state is passed to the line handler for every line of the files in that part and dropped
+/// as soon as the part is done, so all states are dropped before this function returns. The lines of a file are handled on
+/// a single thread with the same state, so files in the zstd seekable format are not split across threads.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `init` - Creates the state of a thread.
+/// * `line_handler` - A function or closure that will handle each decompressed line with the state of its thread.
+///
+/// # Example
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_init");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"a\nb\nc\n"[..], 0).unwrap()).unwrap();
+///
+/// struct Connection { inserted: usize }
+///
+/// let inserted = AtomicUsize::new(0);
+/// zstd_lines::par_zstd_lines_init(
+///     [dir.join("a.zst")],
+///     || Connection { inserted: 0 },
+///     |connection, _line, _path| {
+///         connection.inserted += 1;
+///         inserted.fetch_add(1, Ordering::Relaxed);
+///     },
+/// );
+/// assert_eq!(inserted.into_inner(), 3);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_init<P, S, I, F>(paths: P, init: I, line_handler: F)
+where
+    P: IntoParallelIterator,
+    P::Item: AsRef<Path>,
+    I: Fn() -> S + Sync + Send,
+    F: Fn(&mut S, String, &Path) + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    paths.into_par_iter().for_each_init(init, |state, path| {
+        // Failures are reported like those of every other file
+        let _ = try_process_file_in_order(path.as_ref(), &config, |buffer, context| {
+            if let Some(line) = decode_line(buffer, context, &config) {
+                line_handler(state, line, context.path);
+            }
+        });
+    });
+}
+
+/// Process each line in zstd compressed files in parallel with a stateful line handler per file. `make_handler` is called once
+/// for every file, right before it is opened, and the handler it returns gets every line of that file in order on a single
+/// thread, so it can be a ``FnMut`` keeping e.g. the previous line without any locking. All tar entries and zip members of an
+/// archive go to the same handler. The handler is dropped once its file is done, which is where per-file results can be flushed.
+/// Files in the zstd seekable format are not split across threads, so their lines arrive in order as well.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `make_handler` - A function or closure that creates the line handler of a file.
+///
+/// # Example
+/// ```
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_per_file");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"1\n4\n9\n"[..], 0).unwrap()).unwrap();
+/// std::fs::write(dir.join("b.zst"), zstd::encode_all(&b"10\n20\n"[..], 0).unwrap()).unwrap();
+///
+/// // The differences between consecutive lines of every file
+/// let differences = Mutex::new(Vec::new());
+/// zstd_lines::par_zstd_lines_per_file([dir.join("a.zst"), dir.join("b.zst")], |_path| {
+///     let mut previous = None;
+///     let differences = &differences;
+///     move |line: String| {
+///         let value: i64 = line.parse().unwrap();
+///         if let Some(previous) = previous {
+///             differences.lock().unwrap().push(value - previous);
+///         }
+///         previous = Some(value);
+///     }
+/// });
+/// let mut differences = differences.into_inner().unwrap();
+/// differences.sort();
+/// assert_eq!(differences, vec![3, 5, 10]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_per_file<P, M, H>(paths: P, make_handler: M)
+where
+    P: IntoParallelIterator,
+    P::Item: AsRef<Path>,
+    M: Fn(&Path) -> H + Sync + Send,
+    H: FnMut(String),
+{
+    let config = DEFAULT_CONFIG.start_run();
+    paths.into_par_iter().for_each(|path| {
+        let path = path.as_ref();
+        let mut line_handler = make_handler(path);
+        // Failures are reported like those of every other file
+        let _ = try_process_file_in_order(path, &config, |buffer, context| {
+            if let Some(line) = decode_line(buffer, context, &config) {
+                line_handler(line);
+            }
+        });
+    });
+}
+
+/// Process each line in zstd compressed files in parallel with a line handler which can fail, returning the first error.
+/// Once the line handler returns an error, the file it happened in is abandoned, the other files being processed stop after
+/// the line they are at and files which were not started yet are skipped. Lines still being handled on other threads
+/// may return errors as well, only the first one is returned.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_try_lines");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("ok.zst"), zstd::encode_all(&b"1\n2\n3\n"[..], 0).unwrap()).unwrap();
+/// std::fs::write(dir.join("broken.zst"), zstd::encode_all(&b"4\nfive\n6\n"[..], 0).unwrap()).unwrap();
+///
+/// let handled = AtomicUsize::new(0);
+/// let store = |line: String| line.parse::<u64>().map(|_| ());
+/// let result = zstd_lines::par_zstd_try_lines([dir.join("ok.zst")], |line, _path| {
+///     handled.fetch_add(1, Ordering::Relaxed);
+///     store(line)
+/// });
+/// assert!(result.is_ok());
+/// assert_eq!(handled.swap(0, Ordering::Relaxed), 3);
+///
+/// let result = zstd_lines::par_zstd_try_lines([dir.join("broken.zst")], |line, _path| {
+///     handled.fetch_add(1, Ordering::Relaxed);
+///     store(line)
+/// });
+/// assert_eq!(result.unwrap_err().to_string(), "invalid digit found in string");
+/// // The line after the failing one is never handled
+/// assert_eq!(handled.into_inner(), 2);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+///
+/// Other files stop as well, whether they are being processed already or not:
+/// ```
+/// use std::collections::HashMap;
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_try_lines_cancel");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let content: String = (0..100_000).map(|i| format!("{}\n", i)).collect();
+/// let files: Vec<_> = (0..16)
+///     .map(|i| {
+///         let file = dir.join(format!("part-{}.zst", i));
+///         std::fs::write(&file, zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+///         file
+///     })
+///     .collect();
+///
+/// let handled = Mutex::new(HashMap::new());
+/// let pool = rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap();
+/// let result = pool.install(|| {
+///     zstd_lines::par_zstd_try_lines(&files, |line, path| {
+///         *handled.lock().unwrap().entry(path.to_path_buf()).or_insert(0) += 1;
+///         if path == files[0] && line == "100" { Err(line) } else { Ok(()) }
+///     })
+/// });
+/// assert_eq!(result, Err("100".to_string()));
+/// let handled = handled.into_inner().unwrap();
+/// assert_eq!(handled[&files[0]], 101);
+/// // The file on the other thread stops long before its end and the files which were not started yet are skipped
+/// assert!(handled.values().all(|&lines| lines < 100_000));
+/// assert!(handled.len() <= 2);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_try_lines<I, E, F>(paths: I, line_handler: F) -> Result<(), E>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    E: Send,
+    F: Fn(String, &Path) -> Result<(), E> + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    let error = Mutex::new(None);
+    let line_handler = decoded_sync(&config, |line, context| {
+        if let Err(e) = line_handler(line, context.path) {
+            error.lock().unwrap().get_or_insert(e);
+            config.stop();
+        }
+    });
+    paths.into_par_iter().for_each(|path| {
+        process_file(path.as_ref(), &config, &line_handler);
+    });
+    let error = error.lock().unwrap().take();
+    error.map_or(Ok(()), Err)
+}
+
+/// Process each line in zstd compressed files in parallel until the line handler returns ``ControlFlow::Break``.
+/// The file the line handler breaks in is closed right away, the other files being processed stop after the line they are at
+/// and files which were not started yet are skipped. Returns ``Break`` if the line handler broke out of the run.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each decompressed line and decide whether to go on.
+///
+/// # Example
+/// ```
+/// use std::ops::ControlFlow;
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_lines_while");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let content: String = (0..10_000).map(|i| format!("{{\"id\":{}}}\n", i)).collect();
+/// std::fs::write(dir.join("dump.jsonl.zst"), zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+///
+/// let seen = Mutex::new(Vec::new());
+/// let found = zstd_lines::par_zstd_lines_while([dir.join("dump.jsonl.zst")], |line, _path| {
+///     seen.lock().unwrap().push(line.clone());
+///     if line == r#"{"id":2}"# {
+///         ControlFlow::Break(())
+///     } else {
+///         ControlFlow::Continue(())
+///     }
+/// });
+/// assert!(found.is_break());
+/// assert_eq!(seen.into_inner().unwrap(), vec![r#"{"id":0}"#, r#"{"id":1}"#, r#"{"id":2}"#]);
+///
+/// let found = zstd_lines::par_zstd_lines_while([dir.join("dump.jsonl.zst")], |_line, _path| ControlFlow::Continue(()));
+/// assert!(found.is_continue());
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_while<I, F>(paths: I, line_handler: F) -> ControlFlow<()>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(String, &Path) -> ControlFlow<()> + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    let line_handler = decoded_sync(&config, |line, context| {
+        if line_handler(line, context.path).is_break() {
+            config.stop();
+        }
+    });
+    paths.into_par_iter().for_each(|path| {
+        process_file(path.as_ref(), &config, &line_handler);
+    });
+    if config.check_stopped().is_err() {
+        ControlFlow::Break(())
+    } else {
+        ControlFlow::Continue(())
+    }
+}
+
+/// Process the lines of zstd compressed files in parallel in batches of up to `batch_size` lines, e.g. for batched database inserts.
+/// A batch never mixes lines of different files or tar entries, the last batch of every file and entry is passed on even if it is not full.
+/// A `batch_size` of 0 is treated as 1.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `batch_size` - The maximum number of lines per batch.
+/// * `batch_handler` - A function or closure that will handle each batch of decompressed lines.
+///
+/// # Example
+/// ```
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_batches");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let mut tar = tar::Builder::new(Vec::new());
+/// for (name, content) in [("a.jsonl", &b"a1\na2\na3\n"[..]), ("b.jsonl", &b"b1\n"[..])] {
+///     let mut header = tar::Header::new_ustar();
+///     header.set_size(content.len() as u64);
+///     tar.append_data(&mut header, name, content).unwrap();
+/// }
+/// std::fs::write(dir.join("dump.tar.zst"), zstd::encode_all(&tar.into_inner().unwrap()[..], 0).unwrap()).unwrap();
+/// std::fs::write(dir.join("dump.zst"), zstd::encode_all(&b"1\n2\n3\n4\n5"[..], 0).unwrap()).unwrap();
+///
+/// for (file, expected) in [
+///     ("dump.tar.zst", vec![vec!["a1", "a2"], vec!["a3"], vec!["b1"]]),
+///     ("dump.zst", vec![vec!["1", "2"], vec!["3", "4"], vec!["5"]]),
+/// ] {
+///     let batches = Mutex::new(Vec::new());
+///     zstd_lines::par_zstd_line_batches([dir.join(file)], 2, |batch, _path| {
+///         batches.lock().unwrap().push(batch);
+///     });
+///     assert_eq!(batches.into_inner().unwrap(), expected);
+/// }
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_line_batches<I, F>(paths: I, batch_size: usize, batch_handler: F)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(Vec<String>, &Path) + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    let batch_size = batch_size.max(1);
+    paths.into_par_iter().for_each(|path| {
+        let path = path.as_ref();
+        let flush = |batch: Vec<String>| {
+            if !batch.is_empty() {
+                batch_handler(batch, path);
+            }
+        };
+        let fold = |mut batch: Vec<String>, buffer: &mut Vec<u8>, context: &LineContext<'_>| {
+            // The first line of a tar entry or zip member starts a new batch
+            if context.line_number == 1 {
+                flush(std::mem::take(&mut batch));
+            }
+            if let Some(line) = decode_line(buffer, context, &config) {
+                batch.push(line);
+                if batch.len() == batch_size {
+                    flush(std::mem::take(&mut batch));
+                }
+            }
+            batch
+        };
+        // Ranges of files in the zstd seekable format hand over the lines they did not pass on yet
+        let combine = |mut batch: Vec<String>, more: Vec<String>| {
+            batch.extend(more);
+            while batch.len() >= batch_size {
+                let rest = batch.split_off(batch_size);
+                flush(std::mem::replace(&mut batch, rest));
+            }
+            batch
+        };
+        flush(fold_file(path, &config, &Vec::new, &fold, &combine));
+    });
+}
+
+/// Process a single zstd compressed file line-by-line using stream decompression.
+///
+/// # Arguments
+///
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use zstd_lines::ZstdLines;
+/// use std::path::PathBuf;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_single");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// # let file = dir.join("dump.jsonl.zst");
+/// # std::fs::write(&file, zstd::encode_all(&b"{\"a\":1}\n{\"a\":2}\n"[..], 0).unwrap()).unwrap();
+/// # let mut tar = tar::Builder::new(Vec::new());
+/// # let mut header = tar::Header::new_ustar();
+/// # header.set_size(7);
+/// # header.set_cksum();
+/// # tar.append_data(&mut header, "part.jsonl", &b"{\"a\":3}"[..]).unwrap();
+/// # let tar_file = dir.join("dump.jsonl.tar.zst");
+/// # std::fs::write(&tar_file, zstd::encode_all(&tar.into_inner().unwrap()[..], 0).unwrap()).unwrap();
+/// let mut lines = Vec::new();
+/// PathBuf::from(&file).zstd_lines(|line, _path| lines.push(line))?;
+/// assert_eq!(lines, vec![r#"{"a":1}"#, r#"{"a":2}"#]);
+///
+/// let mut lines = Vec::new();
+/// tar_file.as_path().zstd_lines(|line, _path| lines.push(line))?;
+/// assert_eq!(lines, vec![r#"{"a":3}"#]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// Tar entries are walked using the size in their header, so content which happens to look like a header is still delivered:
+/// ```
+/// use zstd_lines::ZstdLines;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_tar_size");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// // A tar header is recognized by "ustar" at offset 257 of a block
+/// let mut tricky = String::from(r#"{"note":""#);
+/// tricky.push_str(&"x".repeat(257 - tricky.len()));
+/// tricky.push_str(r#"ustar", "n":1}"#);
+/// let content = format!("{}\n{}\n{{\"n\":2}}\n", tricky, "y".repeat(600));
+///
+/// let mut tar = tar::Builder::new(Vec::new());
+/// let mut header = tar::Header::new_ustar();
+/// header.set_size(content.len() as u64);
+/// tar.append_data(&mut header, "part.jsonl", content.as_bytes()).unwrap();
+/// let mut header = tar::Header::new_ustar();
+/// header.set_size(7);
+/// tar.append_data(&mut header, "last.jsonl", &b"{\"n\":3}"[..]).unwrap();
+/// let tar_file = dir.join("dump.tar.zst");
+/// std::fs::write(&tar_file, zstd::encode_all(&tar.into_inner().unwrap()[..], 0).unwrap()).unwrap();
+///
+/// let mut lines = Vec::new();
+/// tar_file.zstd_lines(|line, _path| lines.push(line))?;
+/// assert_eq!(lines, vec![tricky, "y".repeat(600), r#"{"n":2}"#.to_string(), r#"{"n":3}"#.to_string()]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// With the `zip` feature, the members of a zip archive are read one after another like tar entries:
+/// ```
+/// # #[cfg(feature = "zip")]
+/// # {
+/// use std::io::Write;
+/// use zstd_lines::ZstdLines;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_zstd_lines_zip");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+/// zip.start_file("a.jsonl", zip::write::SimpleFileOptions::default()).unwrap();
+/// zip.write_all(b"{\"a\":1}\n{\"a\":2}\n").unwrap();
+/// zip.start_file("b.jsonl", zip::write::SimpleFileOptions::default()).unwrap();
+/// zip.write_all(b"{\"b\":1}\n").unwrap();
+/// let zip_file = dir.join("export.zip");
+/// std::fs::write(&zip_file, zip.finish().unwrap().into_inner()).unwrap();
+///
+/// let mut lines = Vec::new();
+/// zip_file.zstd_lines(|line, _path| lines.push(line))?;
+/// assert_eq!(lines, vec![r#"{"a":1}"#, r#"{"a":2}"#, r#"{"b":1}"#]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// # }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+///
+/// Paths that cannot be processed are never skipped silently:
+/// ```
+/// use zstd_lines::ZstdLines;
+/// use std::path::Path;
+///
+/// for weird in ["", "..", "/", "some/dir/..", "missing.jsonl.zst", "missing.tar.zst"] {
+///     assert!(Path::new(weird).zstd_lines(|_line, _path| {}).is_err(), "{:?}", weird);
+/// }
+/// ```
+pub trait ZstdLines {
+    /// Process each line of a single zstd compressed file using stream decompression.
+    /// Makes the same .tar and zip decisions as ``par_zstd_lines()``, but returns any error instead of printing it.
+    ///
+    /// Called on a vector, slice, array, boxed slice or ``BTreeSet`` of paths, the files are processed one after another
+    /// in their order on the calling thread, so the lines arrive in the same order on every run. Processing stops at the
+    /// first file which fails, returning its error.
+    ///
+    /// # Arguments
+    ///
+    /// * `line_handler` - A function or closure that will handle each decompressed line.
+    ///
+    /// # Example
+    /// ```
+    /// use zstd_lines::ZstdLines;
+    ///
+    /// # let dir = std::env::temp_dir().join("zstd_lines_doc_sequential");
+    /// # std::fs::create_dir_all(&dir).unwrap();
+    /// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"1\n2\n"[..], 0).unwrap()).unwrap();
+    /// std::fs::write(dir.join("b.zst"), zstd::encode_all(&b"3\n4\n"[..], 0).unwrap()).unwrap();
+    ///
+    /// let mut lines = Vec::new();
+    /// vec![dir.join("b.zst"), dir.join("a.zst")].zstd_lines(|line, _path| lines.push(line))?;
+    /// assert_eq!(lines, vec!["3", "4", "1", "2"]);
+    ///
+    /// let mut count = 0;
+    /// assert!([dir.join("a.zst"), dir.join("missing.zst"), dir.join("b.zst")].zstd_lines(|_line, _path| count += 1).is_err());
+    /// assert_eq!(count, 2);
+    /// # std::fs::remove_dir_all(&dir).unwrap();
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    fn zstd_lines<F>(&self, line_handler: F) -> io::Result<()>
+    where
+        F: FnMut(String, &Path);
+}
+
+impl ZstdLines for Path {
+    fn zstd_lines<F>(&self, mut line_handler: F) -> io::Result<()>
+    where
+        F: FnMut(String, &Path),
+    {
+        let config = DEFAULT_CONFIG.start_run();
+        let line_handler = decoded(&config, |line, context| line_handler(line, context.path));
+        let stream_handler = split_lines(self, &config, line_handler);
+        process_file_streams(self, &config, stream_handler)
+    }
+}
+
+impl ZstdLines for PathBuf {
+    fn zstd_lines<F>(&self, line_handler: F) -> io::Result<()>
+    where
+        F: FnMut(String, &Path),
+    {
+        self.as_path().zstd_lines(line_handler)
+    }
+}
+
+impl<T> ZstdLines for [T]
+where
+    T: AsRef<Path>,
+{
+    fn zstd_lines<F>(&self, line_handler: F) -> io::Result<()>
+    where
+        F: FnMut(String, &Path),
+    {
+        zstd_lines_in_order(self, line_handler)
+    }
+}
+
+impl<T, const N: usize> ZstdLines for [T; N]
+where
+    T: AsRef<Path>,
+{
+    fn zstd_lines<F>(&self, line_handler: F) -> io::Result<()>
+    where
+        F: FnMut(String, &Path),
+    {
+        zstd_lines_in_order(self, line_handler)
+    }
+}
+
+impl<T> ZstdLines for Vec<T>
+where
+    T: AsRef<Path>,
+{
+    fn zstd_lines<F>(&self, line_handler: F) -> io::Result<()>
+    where
+        F: FnMut(String, &Path),
+    {
+        zstd_lines_in_order(self, line_handler)
+    }
+}
+
+impl<T> ZstdLines for Box<[T]>
+where
+    T: AsRef<Path>,
+{
+    fn zstd_lines<F>(&self, line_handler: F) -> io::Result<()>
+    where
+        F: FnMut(String, &Path),
+    {
+        zstd_lines_in_order(self, line_handler)
+    }
+}
+
+impl<T> ZstdLines for BTreeSet<T>
+where
+    T: AsRef<Path>,
+{
+    fn zstd_lines<F>(&self, line_handler: F) -> io::Result<()>
+    where
+        F: FnMut(String, &Path),
+    {
+        zstd_lines_in_order(self, line_handler)
+    }
+}
+
+/// Process the files one after another in the order of the paths, stopping at the first file which fails.
+fn zstd_lines_in_order<I, F>(paths: I, mut line_handler: F) -> io::Result<()>
+where
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+    F: FnMut(String, &Path),
+{
+    for path in paths {
+        path.as_ref().zstd_lines(&mut line_handler)?;
+    }
+    Ok(())
+}
+
+/// Process zstd compressed data from standard input line-by-line using stream decompression.
+/// Since there is no file name to look at, tar content is detected by sniffing the first decompressed block.
+/// Lines are delivered with the synthetic path ``-``.
+///
+/// ``par_zstd_lines()`` and ``zstd_lines()`` also treat the pseudo-path ``-`` as standard input.
+///
+/// # Arguments
+///
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```no_run
+/// zstd_lines::zstd_lines_stdin(|line, _path| {
+///     println!("Decompressed line: {}", line);
+/// })?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn zstd_lines_stdin<F>(line_handler: F) -> io::Result<()>
+where
+    F: FnMut(String, &Path),
+{
+    process_reader(
+        io::stdin().lock(),
+        Path::new(STDIN_PATH),
+        ContentKind::Detect,
+        line_handler,
+    )
+}
+
+/// Check whether a path is the pseudo-path for standard input.
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == STDIN_PATH
+}
+
+/// Check whether a path is a regular file, which can be seeked and asked for its size unlike FIFOs and character devices.
+fn is_regular_file(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.is_file())
+}
+
+/// Check whether a path should be treated as a .tar.zst, .tgz or uncompressed .tar file by looking at its file name.
+/// The name is inspected as raw bytes, so file names which are not valid UTF-8 work as well.
+/// Paths without a file name are treated as regular .zst files.
+fn is_tar_path(path: &Path) -> bool {
+    let is_tar = |name: &OsStr| name.as_encoded_bytes().ends_with(b".tar");
+    let is_tgz = |name: &OsStr| name.as_encoded_bytes().ends_with(b".tgz");
+    path.file_stem().is_some_and(is_tar)
+        || path
+            .file_name()
+            .is_some_and(|name| is_tar(name) || is_tgz(name))
+}
+
+/// Without the `zip` feature zip archives are not recognized, they are processed like any other file.
+#[cfg(not(feature = "zip"))]
+fn is_zip_file(_path: &Path) -> bool {
+    false
+}
+
+/// Without the `zip` feature zip archives cannot be processed, ``is_zip_file()`` never lets them get here.
+#[cfg(not(feature = "zip"))]
+fn process_zip_file<S>(_path: &Path, _config: &Run<'_>, _stream_handler: S) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "zip archives require the zip feature",
+    ))
+}
+
+/// Process each line in zstd compressed files in parallel, passing per-file data to the line handler.
+/// The data is borrowed for every line, so it is never cloned.
+///
+/// # Arguments
+///
+/// * `files` - Pairs of a path and the data belonging to that file.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use std::path::PathBuf;
+///
+/// struct Meta {
+///     subreddit: &'static str,
+/// }
+///
+/// let files = vec![
+///     (PathBuf::from("RC_2023-01.zst"), Meta { subreddit: "rust" }),
+///     (PathBuf::from("RC_2023-02.zst"), Meta { subreddit: "zstd" }),
+/// ];
+/// zstd_lines::par_zstd_lines_with(files, |line, path, meta| {
+///     println!("Decompressed line: {} in {:?} of r/{}", line, path, meta.subreddit);
+/// });
+/// ```
+pub fn par_zstd_lines_with<P, T, F>(files: Vec<(P, T)>, line_handler: F)
+where
+    P: AsRef<Path> + Sync,
+    T: Sync,
+    F: Fn(String, &Path, &T) + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    files.par_iter().for_each(|(path, data)| {
+        let line_handler = decoded_sync(&config, |line, context| {
+            line_handler(line, context.path, data)
+        });
+        process_file(path.as_ref(), &config, &line_handler);
+    });
+}
+
+/// Decide whether a path is a .tar.zst or a regular .zst file and process it accordingly.
+/// Failures are reported on stderr unless ``ZstdLinesConfig::report_failures()`` is turned off,
+/// returns whether the file was processed successfully.
+fn process_file<F>(path: &Path, config: &Run<'_>, line_handler: &F) -> bool
+where
+    F: Fn(&mut Vec<u8>, &LineContext<'_>) + Sync + Send,
+{
+    try_process_file(path, config, line_handler).is_ok()
+}
+
+/// Like ``process_file()``, but returning the error the file failed with.
+/// Files skipped because processing was stopped fail with the error recognized by ``is_stopped()``.
+fn try_process_file<F>(path: &Path, config: &Run<'_>, line_handler: &F) -> io::Result<()>
+where
+    F: Fn(&mut Vec<u8>, &LineContext<'_>) + Sync + Send,
+{
+    process_tracked(path, config, |counter, attempt| {
+        let line_handler = |line: &mut Vec<u8>, context: &LineContext<'_>| {
+            counter.count(config, line);
+            line_handler(line, &attempt.deliver(context));
+        };
+        match seekable_frames(path, config) {
+            // Independently decodable frames are split across threads
+            Some(frames) => seekable::process_seekable_file(path, &frames, config, &line_handler),
+            None => process_file_sequential(path, config, line_handler),
+        }
+    })
+}
+
+/// Like ``try_process_file()``, but passing every line of the file to the line handler in order on the current thread,
+/// files in the zstd seekable format are not split across threads.
+fn try_process_file_in_order<F>(
+    path: &Path,
+    config: &Run<'_>,
+    mut line_handler: F,
+) -> io::Result<()>
+where
+    F: FnMut(&mut Vec<u8>, &LineContext<'_>),
+{
+    process_tracked(path, config, |counter, attempt| {
+        process_file_sequential(path, config, |line, context| {
+            counter.count(config, line);
+            line_handler(line, &attempt.deliver(context));
+        })
+    })
+}
+
+/// Process a single file with everything the configuration does for every file: it is skipped once processing was stopped,
+/// waits for a slot of ``max_concurrent_files()``, is passed to the file hooks, retried and its failure reported.
+/// `process` makes a single attempt, counting every line with the counter and delivering it through the attempt.
+fn process_tracked<P>(path: &Path, config: &Run<'_>, mut process: P) -> io::Result<()>
+where
+    P: FnMut(&FileCounter, &Attempt) -> io::Result<()>,
+{
+    config.check_start()?;
+    let _slot = config.file_slots.acquire(config.max_concurrent_files);
+    // Processing may have been stopped while waiting for a slot
+    config.check_start()?;
+    let _span = diagnostics::file_span(path);
+    config.file_started(path);
+    let counter = FileCounter::default();
+    let attempt = Attempt::new();
+    let result = retry::with_retries(path, config, &attempt, || process(&counter, &attempt));
+    let result = config.report_failure(path, result);
+    config.file_finished(path, counter, &result);
+    result
+}
+
+/// Like ``process_file()``, but the line handler pushes its results for every line into a vector,
+/// and the results of the whole file are returned in the order of the lines.
+/// Failures are reported on stderr, the results of the lines in front of the failure are still returned.
+fn collect_file<R, F>(path: &Path, config: &Run<'_>, line_handler: &F) -> Vec<R>
+where
+    R: Send,
+    F: Fn(&mut Vec<u8>, &LineContext<'_>, &mut Vec<R>) + Sync + Send,
+{
+    let fold = |mut results, line: &mut Vec<u8>, context: &LineContext<'_>| {
+        line_handler(line, context, &mut results);
+        results
+    };
+    let combine = |mut results: Vec<R>, more: Vec<R>| {
+        results.extend(more);
+        results
+    };
+    fold_file(path, config, &Vec::new, &fold, &combine)
+}
+
+/// Like ``process_file()``, but threading an accumulator created by `init` through the line handler for every line.
+/// When a file is split across threads, every range folds into its own accumulator, and they are merged with `combine` in order.
+/// Failures are reported on stderr, the accumulator holds the lines in front of the failure.
+fn fold_file<A, I, F, C>(
+    path: &Path,
+    config: &Run<'_>,
+    init: &I,
+    line_handler: &F,
+    combine: &C,
+) -> A
+where
+    A: Send,
+    I: Fn() -> A + Sync + Send,
+    F: Fn(A, &mut Vec<u8>, &LineContext<'_>) -> A + Sync + Send,
+    C: Fn(A, A) -> A + Sync + Send,
+{
+    // The accumulator of a failed attempt is dropped when the file is retried
+    let mut accumulator = None;
+    // Failures are reported, the accumulator holds the lines in front of them
+    let _ = process_tracked(path, config, |counter, attempt| {
+        let line_handler = |accumulator, line: &mut Vec<u8>, context: &LineContext<'_>| {
+            counter.count(config, line);
+            line_handler(accumulator, line, &attempt.deliver(context))
+        };
+        let (attempt_accumulator, result) = match seekable_frames(path, config) {
+            Some(frames) => {
+                seekable::fold_seekable_file(path, &frames, config, init, &line_handler, combine)
+            }
+            None => {
+                let mut accumulator = Some(init());
+                let result = process_file_sequential(path, config, |line, context| {
+                    accumulator = accumulator.take().map(|a| line_handler(a, line, context));
+                });
+                (accumulator.unwrap_or_else(init), result)
+            }
+        };
+        accumulator = Some(attempt_accumulator);
+        result
+    });
+    accumulator.unwrap_or_else(init)
+}
+
+/// Read the seek table of a regular file in the zstd seekable format, so its frames can be split across threads.
+fn seekable_frames(path: &Path, config: &ZstdLinesConfig<'_>) -> Option<Vec<seekable::SeekFrame>> {
+    if is_stdin(path) || !is_regular_file(path) || is_tar_path(path) || is_zip_file(path) {
+        return None;
+    }
+    let frames = seekable::seek_table(path, config)?;
+    // Tar archives with any other name are only recognized by their content
+    seekable::starts_plain(path, config).then_some(frames)
+}
+
+/// Split a file into lines from start to end on the current thread.
+fn process_file_sequential<F>(path: &Path, config: &Run<'_>, mut line_handler: F) -> io::Result<()>
+where
+    F: FnMut(&mut Vec<u8>, &LineContext<'_>),
+{
+    // Counted for the error of a truncated file, which tells how many lines made it to the handler
+    let mut delivered = 0;
+    let line_handler = |line: &mut Vec<u8>, context: &LineContext<'_>| {
+        delivered += 1;
+        line_handler(line, context);
+    };
+    let result = process_file_streams(path, config, split_lines(path, config, line_handler));
+    result.map_err(|e| error::with_lines_delivered(e, delivered))
+}
+
+/// Decide by name and content how a file is decompressed and unpacked, passing the decompressed content
+/// to the stream handler along with the name of the entry, once per tar entry and zip member or once for the whole file.
+fn process_file_streams<S>(path: &Path, config: &Run<'_>, stream_handler: S) -> io::Result<()>
+where
+    S: FnMut(&mut dyn BufRead, Option<&str>) -> io::Result<()>,
+{
+    if is_stdin(path) {
+        process_reader_with(
+            io::stdin().lock(),
+            Path::new(STDIN_PATH),
+            ContentKind::Detect,
+            config,
+            stream_handler,
+        )
+    } else if is_tar_path(path) {
+        // Handle as .tar.zst file
+        process_tar_zstd_file(path, config, stream_handler)
+    } else if is_zip_file(path) {
+        process_zip_file(path, config, stream_handler)
+    } else {
+        // Handle as regular .zst files with a faster algorithm, this includes paths without a file stem
+        process_zstd_file(path, config, stream_handler)
+    }
+}
+
+/// Create a stream handler which splits every stream into lines, passing each line to the line handler function.
+fn split_lines<'a, F>(
+    path: &'a Path,
+    config: &'a Run<'a>,
+    mut line_handler: F,
+) -> impl FnMut(&mut dyn BufRead, Option<&str>) -> io::Result<()> + 'a
+where
+    F: FnMut(&mut Vec<u8>, &LineContext<'_>) + 'a,
+{
+    // Shared by all lines of the file, so handlers can keep the path without copying it
+    let path: Arc<Path> = Arc::from(path);
+    move |reader, entry| process_lines(reader, &path, entry, config, &mut line_handler)
+}
+
+/// Add the name of the tar entry or zip member an error occurred in to its message.
+/// Stopping early is passed on as it is, so it is still recognized.
+fn entry_error(kind: &'static str, name: &str, error: io::Error) -> io::Error {
+    if is_stopped(&error) {
+        return error;
+    }
+    error::in_entry(kind, name, error)
+}
+
+/// Report a file which failed to process on stderr.
+#[cfg(feature = "csv")]
+fn report_failure(path: &Path, result: &io::Result<()>) {
+    if let Err(e) = result {
+        // Stopping is requested by the caller, it is not a failure of the file
+        if !is_stopped(e) {
+            let mut message = String::new();
+            let _ = error::write_failure(&mut message, path, e);
+            diagnostics::report_message(path, &message);
+        }
+    }
+}
+
+/// Process a regular zstd-compressed file, passing its decompressed content to the stream handler.
+/// Files which are not zstd compressed are passed on as they are.
+/// Whether the content is a tar archive is decided by its first decompressed block, not by the name,
+/// so archives with names like ``dump.gz`` are walked like a .tar file.
+fn process_zstd_file<S>(path: &Path, config: &Run<'_>, stream_handler: S) -> io::Result<()>
+where
+    S: FnMut(&mut dyn BufRead, Option<&str>) -> io::Result<()>,
+{
+    let file = config.open_input(path)?;
+    process_reader_with(file, path, ContentKind::Detect, config, stream_handler)
+}
+
+/// Split decompressed content into lines, passing each line to the line handler function.
+/// Like ``BufRead::lines()``, a trailing ``\n`` or ``\r\n`` is removed from every line, see ``ZstdLinesConfig::line_ending()``.
+/// Lines from a tar archive carry the name of their `entry`.
+fn process_lines<R, F>(
+    mut reader: R,
+    path: &Arc<Path>,
+    entry: Option<&str>,
+    config: &Run<'_>,
+    mut line_handler: F,
+) -> io::Result<()>
+where
+    R: BufRead,
+    F: FnMut(&mut Vec<u8>, &LineContext<'_>),
+{
+    let mut buffer = Vec::new();
+    let mut context = LineContext {
+        path,
+        shared_path: path,
+        line_number: 0,
+        byte_offset: 0,
+        tar_entry: entry,
+        partial: false,
+        attempt: 1,
+        decoding: LineDecoding::new(path, config),
+    };
+    let mut position = 0;
+    let mut stream_bytes = report::StreamBytes::start();
+    let problems = LineProblems::start(config);
+    // Whatever is left after the lines taken by ``take_lines()`` is not decompressed any further
+    while !config.takes_no_more(context.line_number) {
+        // Decompression errors are returned right away, they would be returned again on every following read
+        let mut truncated = None;
+        let (bytes_read, too_long) = match read_line(&mut reader, &mut buffer, config) {
+            Ok(read) => read,
+            // What was decoded of the last line of a truncated stream is still delivered, flagged as partial
+            Err(e) if error::is_truncated(&e) && !buffer.is_empty() => {
+                truncated = Some(e);
+                context.partial = true;
+                (buffer.len(), false)
+            }
+            Err(e) => return Err(e),
+        };
+        if bytes_read == 0 {
+            break;
+        }
+        context.byte_offset = position;
+        position += bytes_read as u64;
+        stream_bytes.add(bytes_read);
+        if context.line_number == 0 && config.strip_bom {
+            strip_bom(&mut buffer);
+        }
+        // Lines which cannot be decoded and skipped empty lines are counted as well, so numbers match the decompressed content
+        context.line_number += 1;
+        if too_long {
+            long_line(&context, config)?;
+        } else if config.skips_line(context.line_number) {
+            buffer.clear();
+        } else {
+            emit_line(&mut buffer, &context, config, &mut line_handler);
+        }
+        if let Some(e) = truncated {
+            return Err(e);
+        }
+        problems.check(config)?;
+        config.check_stopped()?;
+    }
+
+    Ok(())
+}
+
+/// Read the next line into the empty `buffer` like ``read_until()``, but without holding more than ``ZstdLinesConfig::max_line_len()`` bytes of it.
+/// Returns the number of bytes read and whether the line was too long, in which case it is read up to its end and left out of the buffer.
+fn read_line<R>(reader: &mut R, buffer: &mut Vec<u8>, config: &Run<'_>) -> io::Result<(usize, bool)>
+where
+    R: BufRead + ?Sized,
+{
+    // The line break is allowed on top of the longest line
+    let limit = config.max_line_len.saturating_add(1);
+    let mut bytes_read = reader.take(limit).read_until(config.delimiter, buffer)?;
+    if (bytes_read as u64) < limit || buffer.last() == Some(&config.delimiter) {
+        return Ok((bytes_read, false));
+    }
+    loop {
+        buffer.clear();
+        let skipped = reader
+            .take(LONG_LINE_CHUNK)
+            .read_until(config.delimiter, buffer)?;
+        bytes_read += skipped;
+        if skipped == 0 || buffer.last() == Some(&config.delimiter) {
+            buffer.clear();
+            return Ok((bytes_read, true));
+        }
+    }
+}
+
+/// Report a line which is longer than ``ZstdLinesConfig::max_line_len()``, or fail with an error if ``ZstdLinesConfig::long_lines()`` says so.
+fn long_line(context: &LineContext<'_>, config: &Run<'_>) -> io::Result<()> {
+    match config.long_lines {
+        LongLines::Skip => {
+            config.report(&ZstdLinesError::LongLine {
+                path: context.path.to_path_buf(),
+                entry: context.tar_entry.map(str::to_string),
+                byte_offset: context.byte_offset,
+                max_line_len: config.max_line_len,
+            });
+            Ok(())
+        }
+        LongLines::Abort => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "line at byte {} is longer than {} bytes",
+                context.byte_offset, config.max_line_len
+            ),
+        )),
+    }
+}
+
+/// Remove a UTF-8 byte order mark from the start of the first line.
+fn strip_bom(buffer: &mut Vec<u8>) {
+    if buffer.starts_with(UTF8_BOM) {
+        buffer.drain(..UTF8_BOM.len());
+    }
+}
+
+/// Strip the line ending from a line read with ``read_until()`` and pass the raw line to the line handler, unless it is skipped as empty or left out of a sample.
+/// The handler may take over the buffer, it is left empty for the next line either way.
+fn emit_line<F>(
+    buffer: &mut Vec<u8>,
+    context: &LineContext<'_>,
+    config: &ZstdLinesConfig<'_>,
+    line_handler: &mut F,
+) where
+    F: FnMut(&mut Vec<u8>, &LineContext<'_>),
+{
+    config.trim(buffer);
+    if config.sampling.includes(context) && !config.empty_lines.skips(buffer) {
+        line_handler(buffer, context);
+    }
+    buffer.clear();
+}
+
+/// Process a tar file line by line, skipping tar headers and handling entry boundaries, compressed or not.
+/// The name is only a hint, content which does not start with a tar header is split into lines as it is.
+fn process_tar_zstd_file<S>(path: &Path, config: &Run<'_>, stream_handler: S) -> io::Result<()>
+where
+    S: FnMut(&mut dyn BufRead, Option<&str>) -> io::Result<()>,
+{
+    let file = config.open_input(path)?;
+    let decoder = decompress(file, path, config)?;
+    process_sniffed(decoder, path, config, true, stream_handler)
+}
+
+/// Walk decompressed tar content, passing the content of every regular file to the stream handler along with its name.
+/// Entries are walked by the size in their header up to the end of the archive, zstd compressed entries are decompressed.
+fn process_tar_entries<R, S>(
+    decoder: R,
+    path: &Path,
+    config: &Run<'_>,
+    mut stream_handler: S,
+) -> io::Result<()>
+where
+    R: Read,
+    S: FnMut(&mut dyn BufRead, Option<&str>) -> io::Result<()>,
+{
+    // Headers are read block by block, so they are staged in a buffer instead of reading the decoder 512 bytes at a time
+    let mut archive = TarReader::new(config.buffered(decoder));
+    let tally = report::current();
+    if let Some(tally) = &tally {
+        tally.tar_archive();
+    }
+    while let Some(entry) = archive.next_entry()? {
+        let name = String::from_utf8_lossy(&entry.name);
+        let skipped = !entry.is_regular()
+            || entry.size == 0
+            || config
+                .entry_filter
+                .as_ref()
+                .is_some_and(|filter| !filter(&name, entry.size));
+        if let Some(tally) = &tally {
+            tally.tar_entry(skipped);
+        }
+        if skipped {
+            continue;
+        }
+        // Members which are compressed themselves are decoded on the fly
+        let mut reader = config.buffered(decompress_entry(&mut archive, path, config)?);
+        stream_handler(&mut reader, Some(&name)).map_err(|e| entry_error("tar entry", &name, e))?;
+    }
+    Ok(())
+}
+
+/// Decide between walking a tar archive and passing the content on as it is by sniffing the first decompressed block.
+/// Files named like a tar archive (`tar_name`) only need a valid checksum, old tar implementations wrote no ustar magic.
+fn process_sniffed<R, S>(
+    mut decoder: R,
+    path: &Path,
+    config: &Run<'_>,
+    tar_name: bool,
+    mut stream_handler: S,
+) -> io::Result<()>
+where
+    R: Read,
+    S: FnMut(&mut dyn BufRead, Option<&str>) -> io::Result<()>,
+{
+    let mut head = [0; TAR_BLOCK_SIZE];
+    let head_len = read_up_to(&mut decoder, &mut head)?;
+    let head = &head[..head_len];
+    let is_tar = if tar_name {
+        head_len == TAR_BLOCK_SIZE && (checksum_matches(head) || is_zero_block(head))
+    } else {
+        is_tar_header(head)
+    };
+    let reader = Cursor::new(head).chain(decoder);
+
+    if is_tar {
+        process_tar_entries(reader, path, config, stream_handler)
+    } else {
+        stream_handler(&mut config.buffered(reader), None)
+    }
+}
+
+/// Read until the buffer is full or the reader is exhausted, returning the number of bytes read.
+fn read_up_to<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Check if the provided 512-byte block is a TAR header by examining the ustar magic and the header checksum.
+fn is_tar_header(block: &[u8]) -> bool {
+    block.len() == TAR_BLOCK_SIZE && &block[257..262] == b"ustar" && checksum_matches(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn sequential_lines_match_parallel_lines() {
+        let dir = test_dir("sequential_parallel");
+        #[cfg_attr(not(feature = "zip"), allow(unused_mut))]
+        let mut files = vec![dir.join("a.jsonl.zst"), dir.join("b.tar.zst")];
+        write_zst(&files[0], b"1\r\n2\n\n3");
+        tar_zst(&files[1], &[("x.jsonl", b"4\n5"), ("y.jsonl", b"6\n")]);
+        #[cfg(feature = "zip")]
+        {
+            use std::io::Write;
+            let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+            zip.start_file("z.jsonl", zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(b"7\n8").unwrap();
+            files.push(dir.join("c.zip"));
+            fs::write(&files[2], zip.finish().unwrap().into_inner()).unwrap();
+        }
+
+        let mut sequential = Vec::new();
+        files
+            .zstd_lines(|line, path| sequential.push((path.to_path_buf(), line)))
+            .unwrap();
+        let parallel = Mutex::new(Vec::new());
+        files.par_zstd_lines(|line, path| {
+            parallel.lock().unwrap().push((path.to_path_buf(), line));
+        });
+        // Files are processed in parallel, the lines of every file in their order
+        let mut parallel = parallel.into_inner().unwrap();
+        parallel.sort_by_key(|(path, _)| files.iter().position(|file| file == path));
+        assert_eq!(sequential, parallel);
+        assert_eq!(sequential.len(), if cfg!(feature = "zip") { 9 } else { 7 });
+    }
+
+    #[test]
+    fn tar_long_names_are_no_entries() {
+        let dir = test_dir("tar_long_name");
+        let long_name = format!("exports/{}.jsonl", "x".repeat(200));
+        let mut tar = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(8);
+        tar.append_data(&mut header, &long_name, &b"{\"a\":1}\n"[..])
+            .unwrap();
+        let path = dir.join("dump.tar.zst");
+        write_zst(&path, &tar.into_inner().unwrap());
+
+        let (lines, result) = lines_of(&path);
+        result.unwrap();
+        assert_eq!(lines, vec![r#"{"a":1}"#]);
+    }
+
+    #[test]
+    fn tar_pax_headers_are_no_entries() {
+        let dir = test_dir("tar_pax");
+        // As written by bsdtar or Python's tarfile
+        let archive = typed_tar_archive(&[
+            (
+                tar::EntryType::XGlobalHeader,
+                "pax_global_header",
+                None,
+                b"20 comment=exported\n",
+            ),
+            (
+                tar::EntryType::XHeader,
+                "./PaxHeaders/data.jsonl",
+                None,
+                b"30 mtime=1699999999.123456789\n",
+            ),
+            (
+                tar::EntryType::Regular,
+                "data.jsonl",
+                None,
+                b"{\"a\":1}\n{\"a\":2}\n",
+            ),
+        ]);
+        let path = dir.join("dump.tar.zst");
+        write_zst(&path, &archive);
+
+        let (lines, result) = lines_of(&path);
+        result.unwrap();
+        assert_eq!(lines, vec![r#"{"a":1}"#, r#"{"a":2}"#]);
+    }
+
+    #[test]
+    fn tar_entries_which_are_no_regular_files_are_skipped() {
+        let dir = test_dir("tar_types");
+        let archive = typed_tar_archive(&[
+            (tar::EntryType::Directory, "exports/", None, b""),
+            (
+                tar::EntryType::Regular,
+                "exports/a.jsonl",
+                None,
+                b"{\"a\":1}\n",
+            ),
+            (
+                tar::EntryType::Symlink,
+                "exports/latest.jsonl",
+                Some("a.jsonl"),
+                b"",
+            ),
+            (
+                tar::EntryType::Link,
+                "exports/copy.jsonl",
+                Some("exports/a.jsonl"),
+                b"",
+            ),
+            // Whatever content other entries claim to have is skipped as well
+            (tar::EntryType::Fifo, "exports/pipe", None, b"not a line\n"),
+            (
+                tar::EntryType::Regular,
+                "exports/b.jsonl",
+                None,
+                b"{\"b\":2}\n",
+            ),
+        ]);
+        let path = dir.join("dump.tar.zst");
+        write_zst(&path, &archive);
+
+        let (lines, result) = lines_of(&path);
+        result.unwrap();
+        assert_eq!(lines, vec![r#"{"a":1}"#, r#"{"b":2}"#]);
+    }
+
+    #[test]
+    fn tar_padding_is_ignored_and_truncation_reported() {
+        let dir = test_dir("tar_end");
+        let mut archive = tar_archive(&[("a.jsonl", b"{\"a\":1}\n{\"a\":2}\n{\"a\":3}")]);
+        // Blocking factor 20 pads archives to multiples of 10240 bytes
+        archive.resize(10240, 0);
+        write_zst(&dir.join("padded.tar.zst"), &archive);
+        let (lines, result) = lines_of(&dir.join("padded.tar.zst"));
+        result.unwrap();
+        assert_eq!(lines, vec![r#"{"a":1}"#, r#"{"a":2}"#, r#"{"a":3}"#]);
+
+        // What is there is delivered before the error
+        write_zst(&dir.join("truncated.tar.zst"), &archive[..512 + 20]);
+        let (lines, result) = lines_of(&dir.join("truncated.tar.zst"));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(lines, vec![r#"{"a":1}"#, r#"{"a":2}"#]);
+    }
+
+    #[test]
+    fn nested_zstd_entries_are_decompressed() {
+        let dir = test_dir("tar_nested");
+        let nested = zstd::encode_all(&b"{\"a\":1}\n{\"a\":2}\n"[..], 0).unwrap();
+        let archive = tar_archive(&[
+            ("part-1.jsonl.zst", &nested),
+            ("part-2.jsonl", b"{\"b\":1}\n"),
+        ]);
+        let path = dir.join("export.tar.zst");
+        write_zst(&path, &archive);
+
+        assert_eq!(
+            entry_lines(&path),
+            vec![
+                r#"part-1.jsonl.zst {"a":1}"#,
+                r#"part-1.jsonl.zst {"a":2}"#,
+                r#"part-2.jsonl {"b":1}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn sparse_entries_deliver_their_data() {
+        let dir = test_dir("tar_sparse");
+        let mut tar = tar::Builder::new(Vec::new());
+        // `tar -S --format=gnu`: regions of 8 bytes at 0 and 1 MiB, with octal offsets and sizes in the header
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::GNUSparse);
+        header.set_path("a.jsonl").unwrap();
+        header.set_size(16);
+        let gnu = header.as_gnu_mut().unwrap();
+        gnu.sparse[0].offset = *b"00000000000\0";
+        gnu.sparse[0].numbytes = *b"00000000010\0";
+        gnu.sparse[1].offset = *b"00004000000\0";
+        gnu.sparse[1].numbytes = *b"00000000010\0";
+        gnu.realsize = *b"00004000010\0";
+        header.set_cksum();
+        tar.append(&header, &b"{\"a\":1}\n{\"b\":2}\n"[..]).unwrap();
+
+        // `tar -S --format=posix`: GNU sparse 1.0, with the decimal sparse map padded to a block in front of the data
+        let records = "22 GNU.sparse.major=1\n22 GNU.sparse.minor=0\n27 GNU.sparse.name=c.jsonl\n31 GNU.sparse.realsize=1048584\n";
+        let mut header = tar::Header::new_ustar();
+        header.set_entry_type(tar::EntryType::XHeader);
+        header.set_path("./PaxHeaders/c.jsonl").unwrap();
+        header.set_size(records.len() as u64);
+        header.set_cksum();
+        tar.append(&header, records.as_bytes()).unwrap();
+        let mut content = b"2\n0\n8\n1048576\n8\n".to_vec();
+        content.resize(512, 0);
+        content.extend_from_slice(b"{\"c\":3}\n{\"d\":4}\n");
+        let mut header = tar::Header::new_ustar();
+        header.set_size(content.len() as u64);
+        tar.append_data(&mut header, "./GNUSparseFile.0/c.jsonl", &content[..])
+            .unwrap();
+        let path = dir.join("sparse.tar.zst");
+        write_zst(&path, &tar.into_inner().unwrap());
+
+        // Holes are left out
+        assert_eq!(
+            entry_lines(&path),
+            vec![
+                r#"a.jsonl {"a":1}"#,
+                r#"a.jsonl {"b":2}"#,
+                r#"c.jsonl {"c":3}"#,
+                r#"c.jsonl {"d":4}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn tar_headers_with_signed_checksums_are_accepted() {
+        let dir = test_dir("tar_checksum");
+        let mut archive = tar_archive(&[("a.jsonl", b"{\"a\":1}\n"), ("b.jsonl", b"{\"b\":2}\n")]);
+        // Old tar implementations stored the sum of signed bytes, which differs for names with non-ASCII characters
+        archive[1024..1024 + 7].copy_from_slice("é.jsonl".as_bytes()[..7].try_into().unwrap());
+        archive[1024 + 148..1024 + 156].fill(b' ');
+        let sum: i64 = archive[1024..1536]
+            .iter()
+            .map(|&b| i64::from(b as i8))
+            .sum();
+        archive[1024 + 148..1024 + 155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+        assert!(is_tar_header(&archive[1024..1536]));
+
+        let path = dir.join("signed.tar.zst");
+        write_zst(&path, &archive);
+        let (lines, result) = lines_of(&path);
+        result.unwrap();
+        assert_eq!(lines, vec![r#"{"a":1}"#, r#"{"b":2}"#]);
+    }
+
+    #[test]
+    fn corrupted_tar_headers_are_reported() {
+        let dir = test_dir("tar_corrupted");
+        let mut archive = tar_archive(&[("a.jsonl", b"{\"a\":1}\n"), ("b.jsonl", b"{\"b\":2}\n")]);
+        assert!(is_tar_header(&archive[1024..1536]));
+        archive[1024] = b'c';
+        assert!(!is_tar_header(&archive[1024..1536]));
+
+        // Instead of being treated as content
+        let path = dir.join("corrupted.tar.zst");
+        write_zst(&path, &archive);
+        let (_lines, result) = lines_of(&path);
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "tar header checksum mismatch at offset 1024"
+        );
+    }
+
+    #[test]
+    fn tar_content_is_sniffed_whatever_the_name() {
+        let dir = test_dir("tar_sniff");
+        // A renamed archive is still walked entry by entry
+        let archive = tar_archive(&[("a.jsonl", b"{\"a\":1}\n")]);
+        write_zst(&dir.join("dump.zst"), &archive);
+        let (lines, result) = lines_of(&dir.join("dump.zst"));
+        result.unwrap();
+        assert_eq!(lines, vec![r#"{"a":1}"#]);
+
+        // While plain content named like an archive is split into lines as it is
+        write_zst(&dir.join("dump.tar.zst"), b"{\"b\":1}\n");
+        let (lines, result) = lines_of(&dir.join("dump.tar.zst"));
+        result.unwrap();
+        assert_eq!(lines, vec![r#"{"b":1}"#]);
+    }
+
+    #[test]
+    fn concurrent_runs_of_one_config_never_stop_each_other() {
+        use std::sync::atomic::{AtomicBool, AtomicU64};
+        use std::thread;
+        use std::time::{Duration, Instant};
+
+        let dir = test_dir("concurrent_runs");
+        let slow: Vec<u8> = (0..100)
+            .flat_map(|i| format!("{}\n", i).into_bytes())
+            .collect();
+        write_zst(&dir.join("slow.zst"), &slow);
+        write_zst(&dir.join("bad.zst"), b"\xFF\n");
+        write_zst(&dir.join("other.zst"), b"1\n2\n");
+
+        let reported = AtomicBool::new(false);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let config = ZstdLinesConfig::new()
+            .strict(true)
+            .thread_pool(&pool)
+            .on_error(|_path, _error| reported.store(true, Ordering::Relaxed));
+        let slow_lines = AtomicU64::new(0);
+        let result = config.try_run(
+            [dir.join("slow.zst"), dir.join("bad.zst")],
+            |_line, path| {
+                if path.ends_with("slow.zst") && slow_lines.fetch_add(1, Ordering::Relaxed) == 0 {
+                    // Wait for the bad file to stop the run
+                    let started = Instant::now();
+                    while !reported.load(Ordering::Relaxed)
+                        && started.elapsed() < Duration::from_secs(5)
+                    {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                    thread::sleep(Duration::from_millis(50));
+                    // Another run with the same configuration neither resumes this one nor is stopped by it
+                    let lines = AtomicU64::new(0);
+                    config
+                        .try_run([dir.join("other.zst")], |_line, _path| {
+                            lines.fetch_add(1, Ordering::Relaxed);
+                        })
+                        .unwrap();
+                    assert_eq!(lines.into_inner(), 2);
+                }
+            },
+        );
+        assert_eq!(slow_lines.into_inner(), 1);
+        let errors = result.unwrap_err();
+        assert!(matches!(
+            errors.failures[..],
+            [ZstdLinesError::InvalidLine { line_number: 1, .. }]
+        ));
+    }
+
+    #[test]
+    fn seekable_tar_content_is_walked_entry_by_entry() {
+        let dir = test_dir("seekable_tar");
+        let archive = tar_archive(&[("a.jsonl", b"1\n2\n"), ("b.jsonl", b"3\n")]);
+        let chunks: Vec<&[u8]> = archive.chunks(TAR_BLOCK_SIZE).collect();
+        let path = dir.join("dump.zst");
+        write_seekable(&path, &chunks);
+        assert!(seekable::seek_table(&path, &DEFAULT_CONFIG).is_some());
+        assert!(seekable_frames(&path, &DEFAULT_CONFIG).is_none());
+        assert_eq!(
+            entry_lines(&path),
+            vec!["a.jsonl 1", "a.jsonl 2", "b.jsonl 3"]
+        );
+
+        // Plain content is still split across threads
+        let path = dir.join("plain.zst");
+        write_seekable(&path, &[b"1\n2", b"\n3\n"]);
+        assert!(seekable_frames(&path, &DEFAULT_CONFIG).is_some());
+    }
+
+    #[test]
+    fn split_parts_and_objects_sniff_tar_content() {
+        let dir = test_dir("parts_objects_sniff");
+        let archive = tar_archive(&[("a.jsonl", b"1\n"), ("b.jsonl", b"2\n")]);
+        let data = zstd::encode_all(&archive[..], 0).unwrap();
+        let (head, tail) = data.split_at(data.len() / 2);
+        fs::write(dir.join("dump.zst.000"), head).unwrap();
+        fs::write(dir.join("dump.zst.001"), tail).unwrap();
+        fs::write(dir.join("dump.jsonl.zst"), &data).unwrap();
+
+        let lines = Mutex::new(Vec::new());
+        par_zstd_lines_parts(
+            vec![vec![dir.join("dump.zst.000"), dir.join("dump.zst.001")]],
+            |line, _path| lines.lock().unwrap().push(line),
+        );
+        assert_eq!(lines.into_inner().unwrap(), vec!["1", "2"]);
+
+        let lines = Mutex::new(Vec::new());
+        par_zstd_lines_objects(
+            &FsObjectSource::new(&*dir),
+            vec!["dump.jsonl.zst".to_string()],
+            |line, _path| lines.lock().unwrap().push(line),
+        );
+        assert_eq!(lines.into_inner().unwrap(), vec!["1", "2"]);
+    }
+
+    #[test]
+    fn files_waiting_for_a_slot_are_skipped_once_stopped() {
+        use std::thread;
+        use std::time::Duration;
+
+        let dir = test_dir("slot_stop");
+        write_zst(&dir.join("bad.zst"), b"\xFF\n");
+        write_zst(&dir.join("a.zst"), b"1\n");
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let started = Mutex::new(Vec::new());
+        let config = ZstdLinesConfig::new()
+            .strict(true)
+            .on_error(|_path, _error| {})
+            .thread_pool(&pool)
+            .max_concurrent_files(1)
+            .on_file_start(|path| {
+                started.lock().unwrap().push(path.to_path_buf());
+                // Keep the slot while the other file waits for it
+                if path.ends_with("bad.zst") {
+                    thread::sleep(Duration::from_millis(100));
+                }
+            });
+        // Collected files go through the same bookkeeping as the others
+        config.run_ordered(
+            [dir.join("bad.zst"), dir.join("a.zst")],
+            usize::MAX,
+            |_line, _path| {},
+        );
+        drop(config);
+        assert_eq!(started.into_inner().unwrap(), vec![dir.join("bad.zst")]);
+    }
+
+    #[test]
+    fn lines_iter_processes_files_with_the_settings_of_its_config() {
+        let dir = test_dir("lines_iter_config");
+        write_zst(&dir.join("a.zst"), b"1\n2\n3\n");
+        let errors = Mutex::new(Vec::new());
+        let finished = Mutex::new(Vec::new());
+        let config = ZstdLinesConfig::new()
+            .take_lines(2)
+            .on_error(|path, _error| errors.lock().unwrap().push(path.to_path_buf()))
+            .on_file_end(|path, result| {
+                let lines = result.map(|stats| stats.lines).ok();
+                finished.lock().unwrap().push((path.to_path_buf(), lines));
+            });
+        let mut lines: Vec<String> = config
+            .lines_iter([dir.join("a.zst"), dir.join("missing.zst")])
+            .map(|(_path, line)| line)
+            .collect();
+        lines.sort();
+        assert_eq!(lines, vec!["1", "2"]);
+        drop(config);
+        assert_eq!(errors.into_inner().unwrap(), vec![dir.join("missing.zst")]);
+        let mut finished = finished.into_inner().unwrap();
+        finished.sort();
+        assert_eq!(
+            finished,
+            vec![
+                (dir.join("a.zst"), Some(2)),
+                (dir.join("missing.zst"), None)
+            ]
+        );
+    }
+
+    #[test]
+    fn breaking_in_one_file_bounds_the_lines_of_the_others() {
+        use std::sync::atomic::AtomicBool;
+
+        let dir = test_dir("lines_while_many_files");
+        let content: String = (0..100_000).map(|i| format!("{}\n", i)).collect();
+        let files: Vec<_> = (0..8)
+            .map(|i| {
+                let file = dir.join(format!("part-{}.zst", i));
+                write_zst(&file, content.as_bytes());
+                file
+            })
+            .collect();
+
+        let broken = AtomicBool::new(false);
+        let after_break = Mutex::new(HashMap::new());
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        let found = pool.install(|| {
+            par_zstd_lines_while(&files, |line, path| {
+                if broken.load(Ordering::SeqCst) {
+                    *after_break
+                        .lock()
+                        .unwrap()
+                        .entry(path.to_path_buf())
+                        .or_insert(0) += 1;
+                }
+                if path == files[0] && line == "50000" {
+                    broken.store(true, Ordering::SeqCst);
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })
+        });
+        assert!(found.is_break());
+        let after_break = after_break.into_inner().unwrap();
+        // The other files being processed stop soon after the break, files which were not started yet are skipped
+        assert!(!after_break.contains_key(&files[0]));
+        assert!(after_break.len() <= 3);
+        assert!(after_break.values().all(|&lines| lines < 1_000));
+    }
+
+    /// The equivalent of ``zstd --long=28``, a frame which declares a window above the default limit of the decoder.
+    fn long_frame(content: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+
+        let mut encoder = zstd::stream::write::Encoder::new(Vec::new(), 3).unwrap();
+        encoder.long_distance_matching(true).unwrap();
+        encoder.window_log(28).unwrap();
+        encoder.write_all(content).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn large_windows_are_accepted_from_any_frame() {
+        let dir = test_dir("window_of_any_frame");
+        let mut skippable_first = vec![0x50, 0x2A, 0x4D, 0x18, 4, 0, 0, 0, 1, 2, 3, 4];
+        skippable_first.extend(long_frame(b"long\n"));
+        fs::write(dir.join("skippable_first.zst"), skippable_first).unwrap();
+        let mut long_later = zstd::encode_all(&b"short\n"[..], 0).unwrap();
+        long_later.extend(long_frame(b"long\n"));
+        fs::write(dir.join("long_later.zst"), long_later).unwrap();
+
+        let (lines, result) = lines_of(&dir.join("skippable_first.zst"));
+        assert_eq!(lines, vec!["long"]);
+        result.unwrap();
+        let (lines, result) = lines_of(&dir.join("long_later.zst"));
+        assert_eq!(lines, vec!["short", "long"]);
+        result.unwrap();
+
+        let config = ZstdLinesConfig::new().window_log_max(27);
+        for file in ["skippable_first.zst", "long_later.zst"] {
+            let errors = config
+                .try_run([dir.join(file)], |_line, _path| {})
+                .unwrap_err();
+            assert!(errors.to_string().contains("too much memory"), "{}", errors);
+        }
+    }
+
+    #[test]
+    fn tar_archives_are_read_compressed_uncompressed_and_by_content() {
+        let dir = test_dir("tar_plain");
+        let archive = tar_archive(&[("a.jsonl", b"{\"a\":1}\n")]);
+        fs::write(dir.join("dump.tar"), &archive).unwrap();
+        write_zst(&dir.join("dump.tar.zst"), &archive);
+        // Archives are also recognized by their first header
+        fs::write(dir.join("dump.bin"), &archive).unwrap();
+
+        for name in ["dump.tar", "dump.tar.zst", "dump.bin"] {
+            assert_eq!(entry_lines(&dir.join(name)), vec![r#"a.jsonl {"a":1}"#]);
+        }
+    }
+
+    #[test]
+    fn sets_deliver_the_same_lines_as_vectors() {
+        let dir = test_dir("sets");
+        let files: Vec<_> = (0..3).map(|i| dir.join(format!("{}.zst", i))).collect();
+        for (i, file) in files.iter().enumerate() {
+            write_zst(file, format!("{}a\n{}b\n", i, i).as_bytes());
+        }
+        fn collect<P: ParZstdLines + ?Sized>(files: &P) -> Vec<String> {
+            let lines = Mutex::new(Vec::new());
+            files.par_zstd_lines(|line, _path| lines.lock().unwrap().push(line));
+            let mut lines = lines.into_inner().unwrap();
+            lines.sort();
+            lines
+        }
+        let hash_set: HashSet<_> = files.iter().cloned().collect();
+        let btree_set: BTreeSet<_> = files.iter().cloned().collect();
+
+        let from_vec = collect(&files);
+        assert_eq!(from_vec.len(), 6);
+        assert_eq!(from_vec, collect(&hash_set));
+        assert_eq!(from_vec, collect(&btree_set));
+    }
+
+    #[test]
+    fn plain_files_are_split_like_compressed_ones() {
+        let dir = test_dir("plain");
+        let content = b"{\"a\":1}\n{\"a\":2}";
+        write_zst(&dir.join("dump.zst"), content);
+        fs::write(dir.join("dump.jsonl"), content).unwrap();
+
+        assert_eq!(
+            lines_of(&dir.join("dump.zst")).0,
+            lines_of(&dir.join("dump.jsonl")).0
+        );
+    }
+
+    #[test]
+    fn lines_spanning_seekable_frames_are_delivered_once() {
+        let dir = test_dir("seekable_spanning");
+        let path = dir.join("dump.zst");
+        write_seekable(
+            &path,
+            &[
+                b"first\nlo",
+                b"ng line spanning",
+                b" three frames\n",
+                b"x\n",
+                b"y\nlast",
+            ],
+        );
+
+        let lines = Mutex::new(Vec::new());
+        [&path].par_zstd_lines(|line, _path| lines.lock().unwrap().push(line));
+        let mut lines = lines.into_inner().unwrap();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec!["first", "last", "long line spanning three frames", "x", "y"]
+        );
+        // Collected results keep the order of the lines
+        let lines = [&path].par_zstd_lines_map(|line, _path| line);
+        assert_eq!(
+            lines,
+            vec!["first", "long line spanning three frames", "x", "y", "last"]
+        );
+    }
+
+    #[test]
+    fn entry_names_are_passed_with_their_lines() {
+        let dir = test_dir("with_entry");
+        let long_name = format!("exports/{}/part.jsonl", "d".repeat(100));
+        tar_zst(
+            &dir.join("dump.tar.zst"),
+            &[("a.jsonl", b"a1\na2"), (long_name.as_str(), b"b1\n")],
+        );
+        write_zst(&dir.join("plain.zst"), b"c1\n");
+
+        let lines = Mutex::new(Vec::new());
+        par_zstd_lines_with_entry(
+            [dir.join("dump.tar.zst"), dir.join("plain.zst")],
+            |line, _path, entry| lines.lock().unwrap().push((line, entry.map(str::to_owned))),
+        );
+        let mut lines = lines.into_inner().unwrap();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                ("a1".to_string(), Some("a.jsonl".to_string())),
+                ("a2".to_string(), Some("a.jsonl".to_string())),
+                ("b1".to_string(), Some(long_name)),
+                ("c1".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn lines_are_numbered_per_tar_entry() {
+        let dir = test_dir("enumerated");
+        let content = b"first\nbroken \xFF\nthird\n";
+        tar_zst(
+            &dir.join("dump.tar.zst"),
+            &[("a.jsonl", content), ("b.jsonl", content)],
+        );
+
+        let lines = Mutex::new(Vec::new());
+        par_zstd_lines_enumerated([dir.join("dump.tar.zst")], |line, _path, number| {
+            lines.lock().unwrap().push((number, line))
+        });
+        let mut lines = lines.into_inner().unwrap();
+        lines.sort();
+        let line = |number, line: &str| (number, line.to_string());
+        assert_eq!(
+            lines,
+            vec![
+                line(1, "first"),
+                line(1, "first"),
+                line(3, "third"),
+                line(3, "third")
+            ]
+        );
+    }
+
+    #[test]
+    fn offsets_point_at_the_lines_in_files_and_tar_entries() {
+        let dir = test_dir("offsets");
+        // Long enough for lines to span the internal buffers
+        let content: String = (0..2000)
+            .map(|i| format!("{}{}\r\n", i, "x".repeat(i % 97)))
+            .collect();
+        write_zst(&dir.join("dump.zst"), content.as_bytes());
+        tar_zst(
+            &dir.join("dump.tar.zst"),
+            &[("dump.jsonl", content.as_bytes())],
+        );
+
+        for file in ["dump.zst", "dump.tar.zst"] {
+            let lines = Mutex::new(Vec::new());
+            par_zstd_lines_with_offsets([dir.join(file)], |line, _path, offset| {
+                lines.lock().unwrap().push((offset, line))
+            });
+            let lines = lines.into_inner().unwrap();
+            assert_eq!(lines.len(), 2000);
+            for (offset, line) in lines {
+                let start = offset as usize;
+                assert_eq!(
+                    &content[start..start + line.len() + 2],
+                    format!("{}\r\n", line)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn byte_lines_of_tar_entries_are_not_validated() {
+        let dir = test_dir("byte_lines");
+        tar_zst(
+            &dir.join("dump.tar.zst"),
+            &[("dump.jsonl", b"{\"a\":1}\r\n{\"blob\":\"\xFF\xFE\"}\n")],
+        );
+
+        let lines = Mutex::new(Vec::new());
+        par_zstd_byte_lines([dir.join("dump.tar.zst")], |line: &[u8], _path| {
+            lines.lock().unwrap().push(line.to_vec())
+        });
+        assert_eq!(
+            lines.into_inner().unwrap(),
+            vec![b"{\"a\":1}".to_vec(), b"{\"blob\":\"\xFF\xFE\"}".to_vec()]
+        );
+    }
+
+    #[test]
+    fn folds_count_the_last_line_of_a_tar_entry_without_line_break() {
+        let dir = test_dir("fold");
+        write_zst(&dir.join("a.zst"), b"rust\npics\nrust");
+        tar_zst(&dir.join("b.tar.zst"), &[("b.txt", b"rust\npics")]);
+
+        let counts = par_zstd_lines_fold(
+            [dir.join("a.zst"), dir.join("b.tar.zst")],
+            HashMap::new,
+            |mut counts, line, _path| {
+                *counts.entry(line).or_insert(0) += 1;
+                counts
+            },
+            |mut counts, other| {
+                for (line, count) in other {
+                    *counts.entry(line).or_insert(0) += count;
+                }
+                counts
+            },
+        );
+        assert_eq!(
+            counts,
+            HashMap::from([("rust".to_string(), 3), ("pics".to_string(), 2)])
+        );
+    }
+
+    #[test]
+    fn every_state_is_dropped_once_all_files_are_done() {
+        static OPEN: AtomicUsize = AtomicUsize::new(0);
+        struct Connection;
+        impl Drop for Connection {
+            fn drop(&mut self) {
+                OPEN.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+
+        let dir = test_dir("init");
+        let files: Vec<_> = (0..4)
+            .map(|i| dir.join(format!("part-{}.zst", i)))
+            .collect();
+        for file in &files {
+            write_zst(file, b"a\nb\nc\n");
+        }
+        let inserted = AtomicUsize::new(0);
+        par_zstd_lines_init(
+            &files,
+            || {
+                OPEN.fetch_add(1, Ordering::Relaxed);
+                Connection
+            },
+            |_connection, _line, _path| {
+                inserted.fetch_add(1, Ordering::Relaxed);
+            },
+        );
+        assert_eq!(inserted.into_inner(), 12);
+        assert_eq!(OPEN.load(Ordering::Relaxed), 0);
     }
-    let ustar_magic = &block[257..262];
-    ustar_magic == b"ustar"
 }
@@ -0,0 +1,74 @@
+//! Limiting how many files are processed at once, see ``ZstdLinesConfig::max_concurrent_files()``.
+
+use std::cell::Cell;
+use std::sync::{Condvar, Mutex};
+
+thread_local! {
+    /// Number of files the current thread is processing, more than one when rayon runs another file
+    /// on the thread while it waits for work of the first one.
+    static HELD: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The slots for files processed with a configuration, shared by all its runs.
+#[derive(Debug)]
+pub(crate) struct FileSlots {
+    in_use: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl FileSlots {
+    pub(crate) const fn new() -> Self {
+        FileSlots {
+            in_use: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Wait until fewer than `max` files are processed, unless the limit is off.
+    /// A thread which already holds a slot never waits, since the slot it holds could only be freed by itself.
+    pub(crate) fn acquire(&self, max: usize) -> Slot<'_> {
+        if max == usize::MAX {
+            return Slot {
+                slots: None,
+                counted: false,
+            };
+        }
+        let held = HELD.get();
+        HELD.set(held + 1);
+        if held > 0 {
+            return Slot {
+                slots: None,
+                counted: true,
+            };
+        }
+        let mut in_use = self.in_use.lock().unwrap();
+        while *in_use >= max {
+            in_use = self.freed.wait(in_use).unwrap();
+        }
+        *in_use += 1;
+        Slot {
+            slots: Some(self),
+            counted: true,
+        }
+    }
+}
+
+/// A slot held while a file is processed, it is freed when dropped.
+pub(crate) struct Slot<'a> {
+    /// The slots to free, ``None`` if the file did not wait for a slot.
+    slots: Option<&'a FileSlots>,
+    /// The file counts for ``HELD``, unless the limit is off.
+    counted: bool,
+}
+
+impl Drop for Slot<'_> {
+    fn drop(&mut self) {
+        if let Some(slots) = self.slots {
+            *slots.in_use.lock().unwrap() -= 1;
+            slots.freed.notify_one();
+        }
+        if self.counted {
+            HELD.set(HELD.get() - 1);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Deciding what happens to a file once its lines cannot be read, see ``ZstdLinesConfig::line_errors()``.
+
+use crate::config::ZstdLinesConfig;
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// What happens to a file with lines which cannot be decoded or are longer than ``ZstdLinesConfig::max_line_len()``.
+/// Errors of the decompression itself always abandon the file, since the decoder cannot resume after them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineErrors {
+    /// Report and skip the line and go on with the file, until `max_consecutive` lines in a row could not be read.
+    /// The file is abandoned then, since it is most likely corrupted. Unlimited by default.
+    ContinueFile { max_consecutive: u64 },
+    /// Report the line and abandon the file, the other files are still processed.
+    SkipFile,
+    /// Report the line and stop all files, like ``ZstdLinesConfig::strict()``.
+    Abort,
+}
+
+impl Default for LineErrors {
+    fn default() -> Self {
+        LineErrors::ContinueFile {
+            max_consecutive: u64::MAX,
+        }
+    }
+}
+
+/// The error a file abandoned by its ``LineErrors`` policy fails with.
+#[derive(Debug)]
+pub(crate) struct Abandoned {
+    pub(crate) policy: LineErrors,
+    pub(crate) consecutive: u64,
+}
+
+impl fmt::Display for Abandoned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.policy {
+            LineErrors::SkipFile => {
+                f.write_str("abandoned at the first line which could not be read")
+            }
+            _ => write!(
+                f,
+                "abandoned after {} lines in a row which could not be read",
+                self.consecutive
+            ),
+        }
+    }
+}
+
+impl Error for Abandoned {}
+
+thread_local! {
+    /// Problems with lines of the stream being split on this thread: whether the current line has one,
+    /// and how many lines in a row had one.
+    static LINE_PROBLEMS: Cell<(bool, u64)> = const { Cell::new((false, 0)) };
+}
+
+/// Keeps track of the problems with the lines of a single stream, restoring the state of an outer stream when it is dropped,
+/// in case a line handler splits another file on the same thread.
+pub(crate) struct LineProblems {
+    outer: Option<(bool, u64)>,
+}
+
+impl LineProblems {
+    /// Start counting the problems of a stream, only needed if the policy can abandon files.
+    pub(crate) fn start(config: &ZstdLinesConfig<'_>) -> Self {
+        LineProblems {
+            outer: (config.line_errors != LineErrors::default())
+                .then(|| LINE_PROBLEMS.replace((false, 0))),
+        }
+    }
+
+    /// Check after every line whether the file is abandoned.
+    pub(crate) fn check(&self, config: &ZstdLinesConfig<'_>) -> io::Result<()> {
+        if self.outer.is_none() {
+            return Ok(());
+        }
+        let (problem, consecutive) = LINE_PROBLEMS.get();
+        if !problem {
+            LINE_PROBLEMS.set((false, 0));
+            return Ok(());
+        }
+        let consecutive = consecutive + 1;
+        LINE_PROBLEMS.set((false, consecutive));
+        let abandoned = match config.line_errors {
+            LineErrors::ContinueFile { max_consecutive } => consecutive >= max_consecutive,
+            LineErrors::SkipFile => true,
+            // All files are stopped already when the problem is reported
+            LineErrors::Abort => false,
+        };
+        if abandoned {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                Abandoned {
+                    policy: config.line_errors,
+                    consecutive,
+                },
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LineProblems {
+    fn drop(&mut self) {
+        if let Some(outer) = self.outer {
+            LINE_PROBLEMS.set(outer);
+        }
+    }
+}
+
+/// Note that the current line could not be read, called on the thread splitting its stream.
+pub(crate) fn line_problem() {
+    LINE_PROBLEMS.with(|problems| problems.set((true, problems.get().1)));
+}
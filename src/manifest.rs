@@ -0,0 +1,85 @@
+//! Reading the list of files to process from a manifest file.
+
+use crate::config::DEFAULT_CONFIG;
+use crate::process_file;
+use crate::text::decoded_sync;
+use rayon::prelude::*;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// How many files of a manifest were attempted and how many of them failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ManifestSummary {
+    /// Number of files listed in the manifest.
+    pub attempted: usize,
+    /// Number of files which could not be processed.
+    pub failed: usize,
+}
+
+/// Read a list of files from a manifest and process them line-by-line and in parallel.
+///
+/// The manifest lists one path per line. Whitespace is trimmed, blank lines and lines starting with ``#`` are skipped.
+/// Relative paths are resolved against the directory containing the manifest.
+/// Files which fail to process are reported individually and do not abort the batch.
+///
+/// # Arguments
+///
+/// * `manifest_path` - The manifest file to read.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use zstd_lines::ManifestSummary;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_manifest");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// # std::fs::write(dir.join("a.jsonl.zst"), zstd::encode_all(&b"a\n"[..], 0).unwrap()).unwrap();
+/// std::fs::write(dir.join("files.txt"), "# nightly dumps\na.jsonl.zst\n\n  missing.jsonl.zst  \n").unwrap();
+///
+/// let summary = zstd_lines::par_zstd_lines_from_manifest(dir.join("files.txt"), |line, path| {
+///     println!("Decompressed line: {} in {:?}", line, path);
+/// })?;
+/// assert_eq!(summary, ManifestSummary { attempted: 2, failed: 1 });
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn par_zstd_lines_from_manifest<P, F>(
+    manifest_path: P,
+    line_handler: F,
+) -> io::Result<ManifestSummary>
+where
+    P: AsRef<Path>,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    let manifest_path = manifest_path.as_ref();
+    let files = read_manifest(manifest_path)?;
+
+    let failed = AtomicUsize::new(0);
+    let line_handler = decoded_sync(&config, |line, context| line_handler(line, context.path));
+    files.par_iter().for_each(|path| {
+        if !process_file(path, &config, &line_handler) {
+            failed.fetch_add(1, Ordering::Relaxed);
+        }
+    });
+
+    Ok(ManifestSummary {
+        attempted: files.len(),
+        failed: failed.into_inner(),
+    })
+}
+
+/// Parse a manifest into a list of paths, resolving relative paths against the manifest's directory.
+fn read_manifest(manifest_path: &Path) -> io::Result<Vec<PathBuf>> {
+    let content = fs::read_to_string(manifest_path)?;
+    let base = manifest_path.parent().unwrap_or(Path::new(""));
+
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line))
+        .collect())
+}
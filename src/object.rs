@@ -0,0 +1,85 @@
+//! Streaming objects from pluggable object stores such as S3, GCS or Azure.
+
+use crate::diagnostics;
+use crate::{process_reader, ContentKind};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// A store of zstd compressed objects which can be opened by key.
+/// Implement this for your object store SDK to stream objects without syncing them to disk first.
+pub trait ObjectSource: Sync {
+    /// Open the object stored under `key` for reading.
+    fn open(&self, key: &str) -> io::Result<Box<dyn Read + Send>>;
+}
+
+/// An ``ObjectSource`` which resolves keys as paths relative to a root directory.
+#[derive(Debug, Clone)]
+pub struct FsObjectSource {
+    root: PathBuf,
+}
+
+impl FsObjectSource {
+    /// Create a source which opens keys relative to `root`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FsObjectSource { root: root.into() }
+    }
+}
+
+impl ObjectSource for FsObjectSource {
+    fn open(&self, key: &str) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(File::open(self.root.join(key))?))
+    }
+}
+
+/// Stream objects from an ``ObjectSource`` and process them line-by-line and in parallel.
+/// The key is passed to the line handler in place of a path, and errors are reported per key.
+/// Tar archives are recognized by their first decompressed block, whatever their key.
+///
+/// # Arguments
+///
+/// * `source` - The object store to read from.
+/// * `keys` - The keys of the objects to process.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use zstd_lines::FsObjectSource;
+/// use std::sync::Mutex;
+///
+/// # let root = std::env::temp_dir().join("zstd_lines_doc_objects");
+/// # std::fs::create_dir_all(root.join("dumps")).unwrap();
+/// # std::fs::write(root.join("dumps/a.jsonl.zst"), zstd::encode_all(&b"a\n"[..], 0).unwrap()).unwrap();
+/// let source = FsObjectSource::new(&root);
+/// let keys = vec!["dumps/a.jsonl.zst".to_string()];
+///
+/// let lines = Mutex::new(Vec::new());
+/// zstd_lines::par_zstd_lines_objects(&source, keys, |line, key| {
+///     lines.lock().unwrap().push(format!("{}:{}", key.display(), line));
+/// });
+/// assert_eq!(lines.into_inner().unwrap(), vec!["dumps/a.jsonl.zst:a"]);
+/// # std::fs::remove_dir_all(&root).unwrap();
+/// ```
+pub fn par_zstd_lines_objects<S, F>(source: &S, keys: Vec<String>, line_handler: F)
+where
+    S: ObjectSource + ?Sized,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    keys.par_iter().for_each(|key| {
+        if let Err(e) = process_object(source, key, &line_handler) {
+            let message = format!("Failed to process zst object {}: {}", key, e);
+            diagnostics::report_message(Path::new(key), &message);
+        }
+    });
+}
+
+/// Open a single object and stream it through the decoder.
+fn process_object<S, F>(source: &S, key: &str, line_handler: F) -> io::Result<()>
+where
+    S: ObjectSource + ?Sized,
+    F: FnMut(String, &Path),
+{
+    let label = Path::new(key);
+    process_reader(source.open(key)?, label, ContentKind::Detect, line_handler)
+}
@@ -0,0 +1,245 @@
+//! Delivering lines decoded in parallel to a single consumer in the order of the files, see ``par_zstd_lines_ordered()``.
+
+use crate::collect_file;
+use crate::config::ZstdLinesConfig;
+use crate::context::LineContext;
+use crate::text::decode_line;
+use rayon::prelude::*;
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+thread_local! {
+    /// Number of files the current thread is decoding, more than one when rayon runs another file
+    /// on the thread while it waits for work of the first one.
+    static DECODING: Cell<usize> = const { Cell::new(0) };
+}
+
+/// The files of an ordered run, shared by the workers and the consumer.
+struct Ordered {
+    state: Mutex<State>,
+    changed: Condvar,
+}
+
+struct State {
+    /// Index of the file which is delivered next.
+    head: usize,
+    /// Decoded files waiting to be delivered, with their paths and lines.
+    decoded: Vec<Option<(PathBuf, Vec<String>)>>,
+    /// Number of bytes of the lines in ``decoded``, and of the file being delivered.
+    buffered: usize,
+    /// All files were decoded, or the workers failed.
+    finished: bool,
+    /// The consumer is gone, the files which were not started yet are skipped.
+    closed: bool,
+}
+
+impl Ordered {
+    fn update(&self, update: impl FnOnce(&mut State)) {
+        update(&mut self.state.lock().unwrap());
+        self.changed.notify_all();
+    }
+}
+
+/// Marks the run as finished or closed when dropped, also when a worker or the consumer panics,
+/// so the other side does not wait forever.
+struct Finish<'a>(&'a Ordered, fn(&mut State));
+
+impl Drop for Finish<'_> {
+    fn drop(&mut self) {
+        self.0.update(self.1);
+    }
+}
+
+/// A file decoded on the current thread, see ``DECODING``.
+struct Decoding;
+
+impl Decoding {
+    fn enter() -> Self {
+        DECODING.set(DECODING.get() + 1);
+        Decoding
+    }
+}
+
+impl Drop for Decoding {
+    fn drop(&mut self) {
+        DECODING.set(DECODING.get() - 1);
+    }
+}
+
+/// Decode the files in parallel and pass their lines to `line_handler` on the calling thread, file by file in the order of the paths.
+pub(crate) fn run_ordered<I, F>(
+    config: &ZstdLinesConfig<'_>,
+    paths: I,
+    max_buffered_bytes: usize,
+    mut line_handler: F,
+) where
+    I: ParallelIterator,
+    I::Item: AsRef<Path>,
+    F: FnMut(String, &Path),
+{
+    let paths: Vec<I::Item> = paths.collect();
+    config.progress_files(Some(paths.len()));
+    let ordered = Ordered {
+        state: Mutex::new(State {
+            head: 0,
+            decoded: (0..paths.len()).map(|_| None).collect(),
+            buffered: 0,
+            finished: false,
+            closed: false,
+        }),
+        changed: Condvar::new(),
+    };
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            let _finished = Finish(&ordered, |state| state.finished = true);
+            config.install(|run| {
+                let collect_line =
+                    |buffer: &mut Vec<u8>, context: &LineContext<'_>, lines: &mut Vec<String>| {
+                        if let Some(line) = decode_line(buffer, context, run) {
+                            lines.push(line);
+                        }
+                    };
+                let decode_file = |index: usize, path: &Path| {
+                    {
+                        let mut state = ordered.state.lock().unwrap();
+                        // A thread which already decodes a file never waits, the file it decodes could be the next one
+                        while DECODING.get() == 0
+                            && !state.closed
+                            && index != state.head
+                            && state.buffered >= max_buffered_bytes
+                        {
+                            state = ordered.changed.wait(state).unwrap();
+                        }
+                        if state.closed {
+                            return;
+                        }
+                    }
+                    let _decoding = Decoding::enter();
+                    let lines = collect_file(path, run, &collect_line);
+                    let bytes = lines.iter().map(String::len).sum::<usize>();
+                    ordered.update(|state| {
+                        state.buffered += bytes;
+                        state.decoded[index] = Some((path.to_path_buf(), lines));
+                    });
+                };
+
+                paths
+                    .into_par_iter()
+                    .enumerate()
+                    .for_each(|(index, path)| decode_file(index, path.as_ref()));
+            });
+        });
+
+        let _closed = Finish(&ordered, |state| state.closed = true);
+        loop {
+            let (path, lines) = {
+                let mut state = ordered.state.lock().unwrap();
+                let head = state.head;
+                let file = loop {
+                    match state.decoded.get_mut(head).map(Option::take) {
+                        Some(Some(file)) => break Some(file),
+                        Some(None) if !state.finished => {
+                            state = ordered.changed.wait(state).unwrap()
+                        }
+                        _ => break None,
+                    }
+                };
+                let Some(file) = file else {
+                    return;
+                };
+                state.head += 1;
+                drop(state);
+                ordered.changed.notify_all();
+                file
+            };
+            let bytes = lines.iter().map(String::len).sum::<usize>();
+            for line in lines {
+                line_handler(line, &path);
+            }
+            ordered.update(|state| state.buffered -= bytes);
+        }
+    });
+}
+
+/// Process each line in zstd compressed files in parallel, but pass the lines to `line_handler` on the calling thread
+/// grouped by file in the order of the paths, and the lines of every file in their order, for consumers like
+/// append-only logs. The line handler does not need to be thread-safe.
+///
+/// Every file is decoded completely before its first line is delivered, so the lines of a file arrive only once
+/// the whole file is decoded, and decoded files wait in memory until all files in front of them are delivered.
+/// Once the waiting lines hold `max_buffered_bytes` or more, no file besides the next one to deliver is started,
+/// so a slow file at the head throttles the decoding instead of letting the buffer grow. Files already being decoded
+/// are finished all the same, so the buffer can exceed the limit by the files in flight. A large limit gives the most
+/// throughput, ``usize::MAX`` buffers everything, 0 only starts the file which is delivered next, one file at a time.
+///
+/// Files are started in the order of the paths, ``ZstdLinesConfig::largest_first()`` does not apply. Failures are
+/// reported on stderr, the lines in front of the failure are still delivered. See ``ZstdLinesConfig::run_ordered()``
+/// for the same with other settings.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `max_buffered_bytes` - The number of bytes of decoded lines waiting for delivery at which decoding is throttled.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_ordered");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let files: Vec<_> = (0..8).map(|i| dir.join(format!("part-{}.zst", i))).collect();
+/// for (i, file) in files.iter().enumerate() {
+///     let content: String = (0..1000).map(|line| format!("{} {}\n", i, line)).collect();
+///     std::fs::write(file, zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+/// }
+///
+/// let mut lines = Vec::new();
+/// zstd_lines::par_zstd_lines_ordered(&files, 1 << 20, |line, _path| lines.push(line));
+/// let expected: Vec<String> = (0..8).flat_map(|i| (0..1000).map(move |line| format!("{} {}", i, line))).collect();
+/// assert_eq!(lines, expected);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_ordered<I, F>(paths: I, max_buffered_bytes: usize, line_handler: F)
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: FnMut(String, &Path),
+{
+    ZstdLinesConfig::new().run_ordered(paths, max_buffered_bytes, line_handler);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn files_wait_for_the_ones_in_front_without_room_for_lines() {
+        let dir = test_dir("ordered");
+        let files: Vec<_> = (0..8)
+            .map(|i| dir.join(format!("part-{}.zst", i)))
+            .collect();
+        let content: String = (0..1000).map(|line| format!("{}\n", line)).collect();
+        for file in &files {
+            write_zst(file, content.as_bytes());
+        }
+
+        let decoded = AtomicUsize::new(0);
+        let mut delivered = 0;
+        let mut last = None;
+        ZstdLinesConfig::new()
+            .on_file_end(|_path, _result| {
+                decoded.fetch_add(1, Ordering::SeqCst);
+            })
+            .run_ordered(&files, 0, |_line, path| {
+                if last.as_deref() != Some(path) {
+                    delivered += 1;
+                    last = Some(path.to_path_buf());
+                }
+                assert!(decoded.load(Ordering::SeqCst) <= delivered + 1);
+            });
+        assert_eq!(delivered, 8);
+    }
+}
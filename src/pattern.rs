@@ -0,0 +1,89 @@
+//! Glob pattern input, available with the `glob` feature.
+
+use crate::diagnostics;
+use crate::par_zstd_lines_from;
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+/// Errors that prevent ``par_zstd_lines_glob()`` from processing any files.
+#[derive(Debug)]
+pub enum GlobError {
+    /// The pattern itself is malformed.
+    Pattern(glob::PatternError),
+    /// The pattern is valid but did not match a single file.
+    NoMatches(String),
+}
+
+impl fmt::Display for GlobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GlobError::Pattern(e) => write!(f, "Invalid glob pattern: {}", e),
+            GlobError::NoMatches(pattern) => {
+                write!(f, "Glob pattern {} did not match any files", pattern)
+            }
+        }
+    }
+}
+
+impl Error for GlobError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GlobError::Pattern(e) => Some(e),
+            GlobError::NoMatches(_) => None,
+        }
+    }
+}
+
+impl From<glob::PatternError> for GlobError {
+    fn from(e: glob::PatternError) -> Self {
+        GlobError::Pattern(e)
+    }
+}
+
+/// Expand a glob pattern and process all matching files line-by-line and in parallel.
+/// Directories are skipped and paths that cannot be read while expanding are reported like other failures.
+///
+/// Returns the number of files that were processed, or ``GlobError::NoMatches`` if nothing matched.
+///
+/// # Arguments
+///
+/// * `pattern` - A glob pattern such as ``data/RC_2023-*.zst``.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use zstd_lines::GlobError;
+///
+/// match zstd_lines::par_zstd_lines_glob("data/RC_2023-*.zst", |line, path| {
+///     println!("Decompressed line: {} in {:?}", line, path);
+/// }) {
+///     Ok(count) => println!("Processed {} files", count),
+///     Err(GlobError::NoMatches(pattern)) => eprintln!("Nothing matched {}", pattern),
+///     Err(e) => eprintln!("{}", e),
+/// }
+/// ```
+pub fn par_zstd_lines_glob<F>(pattern: &str, line_handler: F) -> Result<usize, GlobError>
+where
+    F: Fn(String, &Path) + Sync + Send,
+{
+    let files: Vec<_> = glob::glob(pattern)?
+        .filter_map(|entry| match entry {
+            Ok(path) => Some(path),
+            Err(e) => {
+                let message = format!("Failed to read {}: {}", e.path().display(), e.error());
+                diagnostics::report_message(e.path(), &message);
+                None
+            }
+        })
+        .filter(|path| !path.is_dir())
+        .collect();
+
+    if files.is_empty() {
+        return Err(GlobError::NoMatches(pattern.to_string()));
+    }
+
+    let count = files.len();
+    par_zstd_lines_from(files, line_handler);
+    Ok(count)
+}
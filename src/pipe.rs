@@ -0,0 +1,135 @@
+//! Reading from named pipes (FIFOs) and character devices, see ``ZstdLinesConfig::pipe_options()``.
+
+use crate::config::ZstdLinesConfig;
+use crate::text::decoded;
+use crate::{process_file_streams, split_lines};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Options for reading zstd compressed data from named pipes.
+#[derive(Debug, Clone)]
+pub struct PipeOptions {
+    /// How long to keep waiting for a new writer after the pipe reports end-of-file.
+    /// A producer that closes and reopens the pipe within this window does not truncate the stream.
+    pub eof_retry_window: Duration,
+    /// How often to check for new data while waiting.
+    pub poll_interval: Duration,
+}
+
+impl Default for PipeOptions {
+    fn default() -> Self {
+        PipeOptions {
+            eof_retry_window: Duration::ZERO,
+            poll_interval: Duration::from_millis(50),
+        }
+    }
+}
+
+/// Process zstd compressed data from a named pipe or character device line-by-line, like ``ZstdLines::zstd_lines()``
+/// with ``ZstdLinesConfig::pipe_options()`` set to `options`.
+/// The input is only ever read sequentially, it is never seeked or asked for its size.
+/// Regular files work as well, the retry window only applies to pipes.
+///
+/// # Arguments
+///
+/// * `path` - The pipe to read from.
+/// * `options` - Controls how end-of-file on the pipe is handled.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```no_run
+/// use zstd_lines::PipeOptions;
+/// use std::time::Duration;
+///
+/// // mkfifo out && zstd -c big.jsonl > out
+/// let options = PipeOptions {
+///     eof_retry_window: Duration::from_secs(5),
+///     ..PipeOptions::default()
+/// };
+/// zstd_lines::zstd_lines_pipe("out", &options, |line, _path| {
+///     println!("Decompressed line: {}", line);
+/// })?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn zstd_lines_pipe<P, F>(path: P, options: &PipeOptions, mut line_handler: F) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    F: FnMut(String, &Path),
+{
+    let path = path.as_ref();
+    let config = ZstdLinesConfig::new().pipe_options(options.clone());
+    let config = config.start_run();
+    let line_handler = decoded(&config, |line, context| line_handler(line, context.path));
+    let stream_handler = split_lines(path, &config, line_handler);
+    process_file_streams(path, &config, stream_handler)
+}
+
+/// Process zstd compressed data from several named pipes or character devices line-by-line and in parallel,
+/// like ``ZstdLinesConfig::run()`` with ``ZstdLinesConfig::pipe_options()`` set to `options`.
+///
+/// # Arguments
+///
+/// * `paths` - The pipes to read from.
+/// * `options` - Controls how end-of-file on the pipes is handled.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+pub fn par_zstd_lines_pipes<T, F>(paths: &[T], options: &PipeOptions, line_handler: F)
+where
+    T: AsRef<Path> + Sync,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    ZstdLinesConfig::new()
+        .pipe_options(options.clone())
+        .run(paths.par_iter(), line_handler);
+}
+
+/// A sequential reader which keeps polling a pipe for a while after it reported end-of-file.
+pub(crate) struct PipeReader {
+    file: File,
+    options: PipeOptions,
+}
+
+impl PipeReader {
+    pub(crate) fn new(file: File, options: &PipeOptions) -> Self {
+        PipeReader {
+            file,
+            options: options.clone(),
+        }
+    }
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.file.read(buf)?;
+        if bytes_read > 0 || buf.is_empty() {
+            return Ok(bytes_read);
+        }
+
+        // All writers closed the pipe, wait for a new writer to show up
+        let deadline = Instant::now() + self.options.eof_retry_window;
+        while Instant::now() < deadline {
+            thread::sleep(self.options.poll_interval);
+            let bytes_read = self.file.read(buf)?;
+            if bytes_read > 0 {
+                return Ok(bytes_read);
+            }
+        }
+        Ok(0)
+    }
+}
+
+/// Check whether an open file is a FIFO, by its type alone.
+#[cfg(unix)]
+pub(crate) fn is_pipe(file: &File) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+    file.metadata()
+        .is_ok_and(|metadata| metadata.file_type().is_fifo())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn is_pipe(_file: &File) -> bool {
+    false
+}
@@ -0,0 +1,286 @@
+//! Reporting the progress of a run while it is going on, see ``ZstdLinesConfig::on_progress()``.
+
+use crate::config::ZstdLinesConfig;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+#[cfg(feature = "indicatif")]
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "indicatif")]
+use std::sync::Arc;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The number of files of a run which is not known up front.
+const UNKNOWN: u64 = u64::MAX;
+
+/// How far a run has come, passed to ``ZstdLinesConfig::on_progress()``.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// Number of files of the run, ``None`` if the paths are not known up front, like those of an unindexed parallel iterator.
+    pub files_total: Option<u64>,
+    /// Number of files which were finished, successfully or not.
+    pub files_done: u64,
+    /// Number of the finished files which failed.
+    pub files_failed: u64,
+    /// Number of lines read, including lines which could not be decoded, like ``FileStats::lines``.
+    pub lines_done: u64,
+    /// Number of compressed bytes read from the files.
+    pub compressed_bytes: u64,
+    /// Time since the run was started.
+    pub elapsed: Duration,
+    /// The run is finished, this is the last call for it.
+    pub finished: bool,
+}
+
+/// When ``ZstdLinesConfig::on_progress()`` is called while a run is going on, besides once at its end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressEvery {
+    /// Every time this much time passed, called from a thread of its own.
+    Interval(Duration),
+    /// Every time this many lines were read, called from the thread which read the last of them.
+    Lines(u64),
+}
+
+/// Counted by the threads processing the files of a run, each count is a single atomic increment.
+#[derive(Debug)]
+pub(crate) struct ProgressCounters {
+    files_total: AtomicU64,
+    files_done: AtomicU64,
+    files_failed: AtomicU64,
+    lines_done: AtomicU64,
+    compressed_bytes: AtomicU64,
+    /// Time the run was started, set before any file.
+    started: Mutex<Option<Instant>>,
+    /// The files being processed, tracked for ``ZstdLinesConfig::progress_bars()``.
+    #[cfg(feature = "indicatif")]
+    pub(crate) in_flight: Option<Arc<FilesInFlight>>,
+}
+
+impl ProgressCounters {
+    pub(crate) const fn new() -> Self {
+        ProgressCounters {
+            files_total: AtomicU64::new(UNKNOWN),
+            files_done: AtomicU64::new(0),
+            files_failed: AtomicU64::new(0),
+            lines_done: AtomicU64::new(0),
+            compressed_bytes: AtomicU64::new(0),
+            started: Mutex::new(None),
+            #[cfg(feature = "indicatif")]
+            in_flight: None,
+        }
+    }
+
+    /// Start counting a new run.
+    fn reset(&self) {
+        for counter in [
+            &self.files_done,
+            &self.files_failed,
+            &self.lines_done,
+            &self.compressed_bytes,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        self.files_total.store(UNKNOWN, Ordering::Relaxed);
+        *self.started.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Note the number of files of the run.
+    pub(crate) fn files(&self, total: usize) {
+        self.files_total.store(total as u64, Ordering::Relaxed);
+    }
+
+    /// Count a line read, returning the number of lines read so far.
+    pub(crate) fn line(&self) -> u64 {
+        self.lines_done.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// Count a finished file.
+    pub(crate) fn file(&self, failed: bool) {
+        self.files_done.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.files_failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Count the compressed bytes read from `reader`, the file at `path`.
+    #[cfg_attr(not(feature = "indicatif"), allow(unused_variables))]
+    pub(crate) fn counted<R>(&self, path: &Path, reader: R) -> Counted<'_, R> {
+        Counted {
+            inner: reader,
+            bytes: Some(&self.compressed_bytes),
+            #[cfg(feature = "indicatif")]
+            file_bytes: self
+                .in_flight
+                .as_ref()
+                .and_then(|in_flight| in_flight.counter(path)),
+        }
+    }
+
+    pub(crate) fn snapshot(&self, finished: bool) -> Progress {
+        let files_total = self.files_total.load(Ordering::Relaxed);
+        Progress {
+            files_total: (files_total != UNKNOWN).then_some(files_total),
+            files_done: self.files_done.load(Ordering::Relaxed),
+            files_failed: self.files_failed.load(Ordering::Relaxed),
+            lines_done: self.lines_done.load(Ordering::Relaxed),
+            compressed_bytes: self.compressed_bytes.load(Ordering::Relaxed),
+            elapsed: self
+                .started
+                .lock()
+                .unwrap()
+                .map_or(Duration::ZERO, |started| started.elapsed()),
+            finished,
+        }
+    }
+}
+
+/// Run `op` as a run of `config`, calling its progress handler while it goes on and once at its end.
+pub(crate) fn track<R, OP>(config: &ZstdLinesConfig<'_>, op: OP) -> R
+where
+    OP: FnOnce() -> R,
+{
+    let Some((every, handler)) = &config.on_progress else {
+        return op();
+    };
+    config.progress.reset();
+    let done = Mutex::new(false);
+    let changed = Condvar::new();
+    let (done, changed) = (&done, &changed);
+    let result = thread::scope(|scope| {
+        if let ProgressEvery::Interval(interval) = *every {
+            scope.spawn(move || {
+                let mut finished = done.lock().unwrap();
+                while !*finished {
+                    let (now, timeout) = changed.wait_timeout(finished, interval).unwrap();
+                    finished = now;
+                    if timeout.timed_out() && !*finished {
+                        handler(&config.progress.snapshot(false));
+                    }
+                }
+            });
+        }
+        // The ticker is woken up and joined even if a file panics
+        let _finished = Finished(done, changed);
+        op()
+    });
+    handler(&config.progress.snapshot(true));
+    result
+}
+
+/// Wakes up the ticker of ``track()`` when dropped.
+struct Finished<'a>(&'a Mutex<bool>, &'a Condvar);
+
+impl Drop for Finished<'_> {
+    fn drop(&mut self) {
+        *self.0.lock().unwrap() = true;
+        self.1.notify_all();
+    }
+}
+
+/// A file being processed, see ``FilesInFlight``.
+#[cfg(feature = "indicatif")]
+#[derive(Debug)]
+pub(crate) struct FileInFlight {
+    /// Tells apart files processed at the same time under the same path.
+    pub(crate) id: u64,
+    pub(crate) path: PathBuf,
+    /// Size of the compressed file, ``None`` if it cannot be read, like for standard input and FIFOs.
+    pub(crate) size: Option<u64>,
+    compressed_bytes: Arc<AtomicU64>,
+}
+
+#[cfg(feature = "indicatif")]
+impl FileInFlight {
+    /// Number of compressed bytes read from the file so far.
+    pub(crate) fn compressed_bytes(&self) -> u64 {
+        self.compressed_bytes.load(Ordering::Relaxed)
+    }
+}
+
+/// The files being processed along with the compressed bytes read from each of them.
+#[cfg(feature = "indicatif")]
+#[derive(Debug, Default)]
+pub(crate) struct FilesInFlight {
+    next_id: AtomicU64,
+    files: Mutex<Vec<FileInFlight>>,
+}
+
+#[cfg(feature = "indicatif")]
+impl FilesInFlight {
+    /// Track a file from its start.
+    pub(crate) fn start(&self, path: &Path) {
+        let file = FileInFlight {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            path: path.to_path_buf(),
+            size: std::fs::metadata(path)
+                .ok()
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len()),
+            compressed_bytes: Arc::default(),
+        };
+        self.files.lock().unwrap().push(file);
+    }
+
+    /// Stop tracking a file once it is finished, successfully or not.
+    pub(crate) fn finish(&self, path: &Path) {
+        let mut files = self.files.lock().unwrap();
+        if let Some(index) = files.iter().position(|file| file.path == path) {
+            files.remove(index);
+        }
+    }
+
+    fn counter(&self, path: &Path) -> Option<Arc<AtomicU64>> {
+        let files = self.files.lock().unwrap();
+        let file = files.iter().find(|file| file.path == path)?;
+        Some(Arc::clone(&file.compressed_bytes))
+    }
+
+    /// Look at every file being processed, in the order they were started.
+    pub(crate) fn for_each(&self, f: impl FnMut(&FileInFlight)) {
+        self.files.lock().unwrap().iter().for_each(f);
+    }
+}
+
+/// A compressed file which counts the bytes read from it, see ``ZstdLinesConfig::counted()``.
+pub(crate) struct Counted<'a, R> {
+    inner: R,
+    /// The counter of the run, ``None`` if nobody is interested in it.
+    bytes: Option<&'a AtomicU64>,
+    /// The counter of the file, if it is tracked.
+    #[cfg(feature = "indicatif")]
+    file_bytes: Option<Arc<AtomicU64>>,
+}
+
+impl<R> Counted<'_, R> {
+    /// Read from `reader` without counting.
+    pub(crate) fn uncounted(reader: R) -> Self {
+        Counted {
+            inner: reader,
+            bytes: None,
+            #[cfg(feature = "indicatif")]
+            file_bytes: None,
+        }
+    }
+}
+
+impl<R: Read> Read for Counted<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        if let Some(bytes) = self.bytes {
+            bytes.fetch_add(read as u64, Ordering::Relaxed);
+        }
+        #[cfg(feature = "indicatif")]
+        if let Some(bytes) = &self.file_bytes {
+            bytes.fetch_add(read as u64, Ordering::Relaxed);
+        }
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for Counted<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
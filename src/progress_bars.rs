@@ -0,0 +1,98 @@
+//! Showing the progress of a run with indicatif bars, see ``ZstdLinesConfig::progress_bars()``.
+
+use crate::progress::{FilesInFlight, Progress};
+use indicatif::{HumanBytes, HumanCount, MultiProgress, ProgressBar, ProgressStyle};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Which bars ``ZstdLinesConfig::progress_bars()`` shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProgressBars {
+    /// A single bar with the files done out of all files, the lines read and the compressed bytes read.
+    #[default]
+    Overall,
+    /// The overall bar, and a bar for every file being processed with the compressed bytes read out of its size.
+    PerFile,
+}
+
+/// The bars of a configuration, updated by its progress handler.
+pub(crate) struct Bars {
+    multi: MultiProgress,
+    overall: ProgressBar,
+    /// The files being processed, ``None`` without ``ProgressBars::PerFile``.
+    in_flight: Option<Arc<FilesInFlight>>,
+    /// The bars of the files being processed by the id of the file.
+    files: Mutex<HashMap<u64, ProgressBar>>,
+}
+
+impl Bars {
+    pub(crate) fn new(multi: &MultiProgress, in_flight: Option<Arc<FilesInFlight>>) -> Self {
+        let overall = multi.add(
+            ProgressBar::no_length().with_style(
+                ProgressStyle::with_template(
+                    "{elapsed_precise} {wide_bar} {pos}/{len} files, {msg}",
+                )
+                .unwrap(),
+            ),
+        );
+        Bars {
+            multi: multi.clone(),
+            overall,
+            in_flight,
+            files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Show the progress of a run. Bars of files which were finished since the last update are removed,
+    /// whether they succeeded or failed.
+    pub(crate) fn update(&self, progress: &Progress) {
+        // Calls from several threads at once take turns
+        let mut files = self.files.lock().unwrap();
+        if self.overall.is_finished() {
+            // The configuration is run again
+            self.overall.reset();
+        }
+        match progress.files_total {
+            Some(total) => self.overall.set_length(total),
+            None => self.overall.unset_length(),
+        }
+        self.overall.set_position(progress.files_done);
+        let mut message = format!(
+            "{} lines, {} read",
+            HumanCount(progress.lines_done),
+            HumanBytes(progress.compressed_bytes)
+        );
+        if progress.files_failed > 0 {
+            message.push_str(&format!(", {} failed", progress.files_failed));
+        }
+        self.overall.set_message(message);
+
+        if let Some(in_flight) = &self.in_flight {
+            let mut seen = HashMap::with_capacity(files.len());
+            in_flight.for_each(|file| {
+                let bar = files.remove(&file.id).unwrap_or_else(|| {
+                    let bar = self.multi.add(
+                        ProgressBar::new(file.size.unwrap_or(0)).with_style(
+                            ProgressStyle::with_template(
+                                "{wide_msg} {bytes}/{total_bytes} {bar:40}",
+                            )
+                            .unwrap(),
+                        ),
+                    );
+                    bar.set_message(file.path.display().to_string());
+                    bar
+                });
+                bar.set_position(file.compressed_bytes());
+                seen.insert(file.id, bar);
+            });
+            for bar in files.values() {
+                bar.finish_and_clear();
+                self.multi.remove(bar);
+            }
+            *files = seen;
+        }
+        if progress.finished {
+            self.overall.finish();
+        }
+    }
+}
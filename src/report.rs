@@ -0,0 +1,237 @@
+//! Collecting what happened to every file, for audits and dashboards which need more than the lines.
+
+use crate::config::{is_stopped, Run, ZstdLinesConfig};
+use crate::context::LineContext;
+use crate::error::ZstdLinesError;
+use crate::schedule;
+use crate::text::decode_line;
+use crate::try_process_file;
+use rayon::prelude::*;
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// What happened to a single file, returned by ``par_zstd_lines_report()`` and ``ZstdLinesConfig::run_with_reports()``.
+#[derive(Debug)]
+pub struct FileReport {
+    /// The file as it was passed in.
+    pub path: PathBuf,
+    /// Whether the file was processed to its end, or the error it failed with.
+    pub result: Result<(), ZstdLinesError>,
+    /// The file was not finished because processing was stopped by another file, see ``ZstdLinesConfig::strict()``.
+    /// Its ``result`` is ``Ok`` then.
+    pub stopped: bool,
+    /// Number of lines passed to the handler.
+    pub lines_delivered: u64,
+    /// Number of lines skipped because they could not be decoded.
+    pub invalid_lines: u64,
+    /// Number of decompressed bytes which were split into lines, including their line breaks. Tar headers are not counted.
+    pub decompressed_bytes: u64,
+    /// How long the file took from start to end.
+    pub elapsed: Duration,
+    /// The entries of a tar archive, ``None`` for files which are no tar archive.
+    pub tar_entries: Option<TarEntries>,
+}
+
+/// The entries found in a tar archive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TarEntries {
+    /// Number of entries in the archive, like regular files, directories and links.
+    pub seen: u64,
+    /// Number of entries which were not split into lines: entries which are no regular file, empty files,
+    /// and entries left out by ``ZstdLinesConfig::entry_filter()``.
+    pub skipped: u64,
+}
+
+/// Counted while a file is processed for its report, by every thread decoding it.
+#[derive(Debug, Default)]
+pub(crate) struct FileTally {
+    decompressed_bytes: AtomicU64,
+    tar: AtomicBool,
+    entries_seen: AtomicU64,
+    entries_skipped: AtomicU64,
+}
+
+impl FileTally {
+    /// Note that the file is a tar archive.
+    pub(crate) fn tar_archive(&self) {
+        self.tar.store(true, Ordering::Relaxed);
+    }
+
+    /// Count an entry of the tar archive.
+    pub(crate) fn tar_entry(&self, skipped: bool) {
+        self.entries_seen.fetch_add(1, Ordering::Relaxed);
+        if skipped {
+            self.entries_skipped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+thread_local! {
+    /// The tally of the file processed on this thread, if a report is collected for it.
+    static TALLY: RefCell<Option<Arc<FileTally>>> = const { RefCell::new(None) };
+}
+
+/// The tally of the file processed on this thread, to be entered on other threads decoding parts of it.
+pub(crate) fn current() -> Option<Arc<FileTally>> {
+    TALLY.with_borrow(Option::clone)
+}
+
+/// Count into `tally` on this thread until the guard is dropped, which restores the tally entered before.
+pub(crate) fn enter(tally: Option<Arc<FileTally>>) -> Entered {
+    Entered(TALLY.replace(tally))
+}
+
+/// Entered tally of a file, see ``enter()``.
+pub(crate) struct Entered(Option<Arc<FileTally>>);
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        TALLY.set(self.0.take());
+    }
+}
+
+/// Counts the bytes split into lines of a single stream, which are added to the tally of its file when dropped,
+/// so streams which fail are counted as well.
+pub(crate) struct StreamBytes {
+    tally: Option<Arc<FileTally>>,
+    bytes: u64,
+}
+
+impl StreamBytes {
+    /// Start counting a stream, only counted if a report is collected for its file.
+    pub(crate) fn start() -> Self {
+        StreamBytes {
+            tally: current(),
+            bytes: 0,
+        }
+    }
+
+    /// Count the bytes of a line including its line break.
+    pub(crate) fn add(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+    }
+}
+
+impl Drop for StreamBytes {
+    fn drop(&mut self) {
+        if let Some(tally) = &self.tally {
+            tally
+                .decompressed_bytes
+                .fetch_add(self.bytes, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Process a single file, collecting its report.
+fn report_file<F>(path: &Path, config: &Run<'_>, line_handler: &F) -> FileReport
+where
+    F: Fn(String, &Path) + Sync + Send,
+{
+    let started = Instant::now();
+    let tally = Arc::new(FileTally::default());
+    let delivered = AtomicU64::new(0);
+    let invalid = AtomicU64::new(0);
+    let line_handler = |buffer: &mut Vec<u8>, context: &LineContext<'_>| {
+        let Some(line) = decode_line(buffer, context, config) else {
+            invalid.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        delivered.fetch_add(1, Ordering::Relaxed);
+        line_handler(line, context.path);
+    };
+    let result = {
+        let _tally = enter(Some(Arc::clone(&tally)));
+        try_process_file(path, config, &line_handler)
+    };
+    let stopped = result.as_ref().is_err_and(is_stopped);
+    FileReport {
+        path: path.to_path_buf(),
+        result: match result {
+            Err(e) if !stopped => Err(ZstdLinesError::from_io(path, e)),
+            _ => Ok(()),
+        },
+        stopped,
+        lines_delivered: delivered.into_inner(),
+        invalid_lines: invalid.into_inner(),
+        decompressed_bytes: tally.decompressed_bytes.load(Ordering::Relaxed),
+        elapsed: started.elapsed(),
+        tar_entries: tally.tar.load(Ordering::Relaxed).then(|| TarEntries {
+            seen: tally.entries_seen.load(Ordering::Relaxed),
+            skipped: tally.entries_skipped.load(Ordering::Relaxed),
+        }),
+    }
+}
+
+/// Process every file, collecting one report per path in the order of the paths.
+pub(crate) fn report_files<I, F>(paths: I, config: &Run<'_>, line_handler: F) -> Vec<FileReport>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    let mut reports = schedule::process_files(config, paths.into_par_iter(), |path| {
+        report_file(path, config, &line_handler)
+    });
+    // In strict mode the file with the first problem was stopped like all others
+    if let Some(problem) = config.take_first_problem() {
+        if let Some(report) = reports
+            .iter_mut()
+            .find(|report| report.stopped && report.path == problem.path())
+        {
+            report.stopped = false;
+            report.result = Err(problem);
+        }
+    }
+    reports
+}
+
+/// Process each line in zstd compressed files in parallel like ``par_zstd_lines()``, and return a report for every file
+/// telling whether it succeeded, the error if not, how many lines were delivered or could not be decoded,
+/// how many bytes were decompressed and how long it took, and for tar archives how many entries were seen and skipped.
+/// Failures are not reported on stderr, they are part of the reports. See ``ZstdLinesConfig::run_with_reports()``
+/// for the same with other settings.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use zstd_lines::{TarEntries, ZstdLinesError};
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_report");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("a.zst"), zstd::encode_all(&b"1\n\xFF\n3\n"[..], 0).unwrap()).unwrap();
+/// let mut tar = tar::Builder::new(Vec::new());
+/// let mut header = tar::Header::new_gnu();
+/// header.set_size(4);
+/// tar.append_data(&mut header, "b.txt", &b"4\n5\n"[..]).unwrap();
+/// let mut header = tar::Header::new_gnu();
+/// header.set_size(0);
+/// tar.append_data(&mut header, "empty.txt", &b""[..]).unwrap();
+/// std::fs::write(dir.join("b.tar.zst"), zstd::encode_all(&tar.into_inner().unwrap()[..], 0).unwrap()).unwrap();
+///
+/// let reports = zstd_lines::par_zstd_lines_report([dir.join("a.zst"), dir.join("b.tar.zst"), dir.join("missing.zst")], |_line, _path| {});
+/// assert_eq!(reports.len(), 3);
+/// assert!(reports[0].result.is_ok());
+/// assert_eq!((reports[0].lines_delivered, reports[0].invalid_lines, reports[0].decompressed_bytes), (2, 1, 6));
+/// assert_eq!(reports[0].tar_entries, None);
+/// assert_eq!(reports[1].lines_delivered, 2);
+/// assert_eq!(reports[1].tar_entries, Some(TarEntries { seen: 2, skipped: 1 }));
+/// assert!(matches!(reports[2].result, Err(ZstdLinesError::Open { .. })));
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_report<I, F>(paths: I, line_handler: F) -> Vec<FileReport>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    ZstdLinesConfig::new()
+        .report_failures(false)
+        .run_with_reports(paths, line_handler)
+}
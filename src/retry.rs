@@ -0,0 +1,124 @@
+//! Retrying files which failed with a transient I/O error, see ``ZstdLinesConfig::retry()``.
+
+use crate::config::{is_stopped, Run};
+use crate::context::LineContext;
+use crate::diagnostics;
+use crate::error;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::thread;
+use std::time::Duration;
+
+/// The ``EIO`` error number, the same on Linux, macOS and the BSDs.
+const EIO: i32 = 5;
+
+/// When and how often a file which failed is processed again from the beginning, see ``ZstdLinesConfig::retry()``.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Number of attempts per file including the first one.
+    pub max_attempts: u32,
+    /// Wait before the second attempt, doubled for every further attempt.
+    pub backoff: Duration,
+    /// Also retry files which delivered lines before they failed, whose lines are delivered again by the next attempt.
+    /// ``LineContext::attempt`` tells the attempts apart. Off by default, so no line is ever delivered twice.
+    pub after_lines: bool,
+    /// Decide whether an error is worth another attempt, ``transient_error()`` by default.
+    /// It receives the I/O error the file failed with, looking through the decoder or tar entry it happened in.
+    pub retriable: fn(&io::Error) -> bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_secs(1),
+            after_lines: false,
+            retriable: transient_error,
+        }
+    }
+}
+
+/// Check whether an error is likely to go away when the file is read again, like timeouts and reset connections
+/// of network file systems and ``EIO``. Errors of the content, like corrupted frames, never are.
+///
+/// # Example
+/// ```
+/// use std::io;
+///
+/// assert!(zstd_lines::transient_error(&io::Error::from(io::ErrorKind::TimedOut)));
+/// assert!(!zstd_lines::transient_error(&io::Error::from(io::ErrorKind::InvalidData)));
+/// ```
+pub fn transient_error(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::TimedOut
+            | io::ErrorKind::Interrupted
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+    ) || (cfg!(unix) && error.raw_os_error() == Some(EIO))
+}
+
+/// The attempt a file is at, shared by all threads decoding it.
+#[derive(Debug)]
+pub(crate) struct Attempt {
+    number: AtomicU32,
+    delivered: AtomicBool,
+}
+
+impl Attempt {
+    pub(crate) fn new() -> Self {
+        Attempt {
+            number: AtomicU32::new(1),
+            delivered: AtomicBool::new(false),
+        }
+    }
+
+    /// Note that the current attempt delivers a line, returning its context with the number of the attempt.
+    pub(crate) fn deliver<'c>(&self, context: &LineContext<'c>) -> LineContext<'c> {
+        if !self.delivered.load(Ordering::Relaxed) {
+            self.delivered.store(true, Ordering::Relaxed);
+        }
+        LineContext {
+            attempt: self.number.load(Ordering::Relaxed),
+            ..*context
+        }
+    }
+}
+
+/// Process a file until it succeeds, fails with an error which is not retried, or runs out of attempts.
+/// The file is processed from the beginning by every attempt, waiting for the backoff of the policy in between.
+pub(crate) fn with_retries<F>(
+    path: &Path,
+    config: &Run<'_>,
+    attempt: &Attempt,
+    mut process: F,
+) -> io::Result<()>
+where
+    F: FnMut() -> io::Result<()>,
+{
+    let Some(policy) = &config.retry else {
+        return process();
+    };
+    let mut backoff = policy.backoff;
+    loop {
+        let result = process();
+        let number = attempt.number.load(Ordering::Relaxed);
+        match result {
+            Err(e)
+                if number < policy.max_attempts
+                    && !is_stopped(&e)
+                    && (policy.after_lines || !attempt.delivered.load(Ordering::Relaxed))
+                    && (policy.retriable)(error::root_cause(&e)) =>
+            {
+                diagnostics::file_retried(path, number, &e);
+                thread::sleep(backoff);
+                backoff = backoff.saturating_mul(2);
+                config.check_stopped()?;
+                attempt.number.store(number + 1, Ordering::Relaxed);
+                attempt.delivered.store(false, Ordering::Relaxed);
+            }
+            result => return result,
+        }
+    }
+}
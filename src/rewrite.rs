@@ -0,0 +1,283 @@
+//! Writing transformed lines back out, one zstd compressed output per input file.
+
+use crate::config::DEFAULT_CONFIG;
+use crate::context::LineContext;
+use crate::diagnostics;
+use crate::process_file_sequential;
+use crate::text::decode_line;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use zstd::stream::write::Encoder;
+
+/// Options for ``par_zstd_rewrite()``.
+#[derive(Debug, Clone)]
+pub struct RewriteOptions {
+    /// The zstd compression level of the outputs, 3 by default like the ``zstd`` command line tool.
+    pub level: i32,
+}
+
+impl Default for RewriteOptions {
+    fn default() -> Self {
+        RewriteOptions { level: 3 }
+    }
+}
+
+/// Writes the lines of a single output file, passed to the line handler of ``par_zstd_rewrite()``.
+pub struct LineWriter {
+    encoder: Encoder<'static, BufWriter<File>>,
+    path: PathBuf,
+}
+
+impl LineWriter {
+    /// Create the output file at `path`.
+    fn create(path: PathBuf, level: i32) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(&path)?);
+        Ok(LineWriter {
+            encoder: Encoder::new(file, level)?,
+            path,
+        })
+    }
+
+    /// Write a line followed by a ``\n``.
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        self.encoder.write_all(line.as_bytes())?;
+        self.encoder.write_all(b"\n")
+    }
+
+    /// The path of the output file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write the end of the zstd frame and flush the file.
+    fn finish(self) -> io::Result<()> {
+        let file = self.encoder.finish()?;
+        file.into_inner().map_err(|e| e.into_error())?;
+        Ok(())
+    }
+}
+
+/// Bytes are written as they are, without adding a line break.
+impl Write for LineWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.encoder.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.encoder.flush()
+    }
+}
+
+impl std::fmt::Debug for LineWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LineWriter")
+            .field("path", &self.path)
+            .finish()
+    }
+}
+
+/// Process each line of zstd compressed files in parallel and write whatever the line handler writes into one zstd compressed
+/// output per input file in `out_dir`, which is created if needed. Outputs are named after their input, with ``.tar`` and
+/// compression extensions replaced by ``.zst``, so the lines of all entries of an archive end up in a single output.
+///
+/// Every output is finished once its input was processed completely. If the input fails, or the line handler returns an error,
+/// the file is abandoned and its partial output is removed. Inputs whose output would overwrite the input itself or the output
+/// of another input fail without being read. Returns the output path, or the error, of every input in the order of the paths.
+/// Failures are reported on stderr as well. Files in the zstd seekable format are decoded on a single thread, so lines are written in order.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `out_dir` - The directory the outputs are written to.
+/// * `options` - The compression level of the outputs.
+/// * `line_handler` - A function or closure that will handle each decompressed line and write to the output of its file.
+///
+/// # Example
+/// ```
+/// use zstd_lines::RewriteOptions;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_rewrite");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("scores.jsonl.zst"), zstd::encode_all(&b"1\n20\n3\n40\n"[..], 0).unwrap()).unwrap();
+///
+/// let results = zstd_lines::par_zstd_rewrite(
+///     [dir.join("scores.jsonl.zst")],
+///     dir.join("out"),
+///     &RewriteOptions { level: 19 },
+///     |line, _path, out| {
+///         let score: u32 = line.parse().map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+///         if score >= 10 {
+///             out.write_line(&(score * 2).to_string())?;
+///         }
+///         Ok(())
+///     },
+/// );
+/// let output = results[0].1.as_ref().unwrap();
+/// assert_eq!(zstd::decode_all(std::fs::File::open(output).unwrap()).unwrap(), b"40\n80\n");
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_rewrite<I, O, F>(
+    paths: I,
+    out_dir: O,
+    options: &RewriteOptions,
+    line_handler: F,
+) -> Vec<(PathBuf, io::Result<PathBuf>)>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    O: AsRef<Path>,
+    F: Fn(String, &Path, &mut LineWriter) -> io::Result<()> + Sync + Send,
+{
+    let out_dir = out_dir.as_ref();
+    let paths: Vec<PathBuf> = paths
+        .into_par_iter()
+        .map(|path| path.as_ref().to_path_buf())
+        .collect();
+    let outputs = output_paths(&paths, out_dir);
+    paths
+        .into_par_iter()
+        .zip(outputs)
+        .map(|(path, output)| {
+            let result =
+                output.and_then(|output| rewrite_file(&path, output, options, &line_handler));
+            if let Err(e) = &result {
+                let message = format!("Failed to rewrite {}: {}", path.display(), e);
+                diagnostics::report_message(&path, &message);
+            }
+            (path, result)
+        })
+        .collect()
+}
+
+/// Decide where the output of every input goes, failing inputs whose output would clash with an input or another output.
+fn output_paths(paths: &[PathBuf], out_dir: &Path) -> Vec<io::Result<PathBuf>> {
+    if let Err(e) = fs::create_dir_all(out_dir) {
+        let kind = e.kind();
+        let message = format!("cannot create {}: {}", out_dir.display(), e);
+        return paths
+            .iter()
+            .map(|_| Err(io::Error::new(kind, message.clone())))
+            .collect();
+    }
+    // Paths are compared resolved, so an input is recognized whichever way it is spelled
+    let resolved_dir = fs::canonicalize(out_dir).unwrap_or_else(|_| out_dir.to_path_buf());
+    let inputs: Vec<PathBuf> = paths
+        .iter()
+        .filter_map(|path| fs::canonicalize(path).ok())
+        .collect();
+    let mut claimed: HashMap<PathBuf, &Path> = HashMap::new();
+    let mut outputs = Vec::with_capacity(paths.len());
+    for path in paths {
+        let name = output_name(path);
+        let output = out_dir.join(&name);
+        let resolved = resolved_dir.join(&name);
+        if inputs.contains(&resolved) {
+            outputs.push(Err(clash(&output, "it is an input")));
+        } else if let Some(other) = claimed.get(&resolved) {
+            let reason = format!("it is written for {}", other.display());
+            outputs.push(Err(clash(&output, &reason)));
+        } else {
+            claimed.insert(resolved, path);
+            outputs.push(Ok(output));
+        }
+    }
+    outputs
+}
+
+/// The error for an input whose output path cannot be written.
+fn clash(output: &Path, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        format!("cannot write {} since {}", output.display(), reason),
+    )
+}
+
+/// Name the output after the input, a ``dump.jsonl.tar.gz`` is written to ``dump.jsonl.zst``.
+fn output_name(path: &Path) -> OsString {
+    let name = path.file_name().unwrap_or(path.as_os_str());
+    let mut name = name.to_string_lossy().into_owned();
+    for extension in [
+        ".zst", ".zstd", ".gz", ".tgz", ".xz", ".lz4", ".bz2", ".zip",
+    ] {
+        if let Some(stem) = name.strip_suffix(extension) {
+            name = stem.to_string();
+            break;
+        }
+    }
+    if let Some(stem) = name.strip_suffix(".tar") {
+        name = stem.to_string();
+    }
+    name.push_str(".zst");
+    name.into()
+}
+
+/// Rewrite a single file, removing the output again if anything fails.
+fn rewrite_file<F>(
+    path: &Path,
+    output: PathBuf,
+    options: &RewriteOptions,
+    line_handler: &F,
+) -> io::Result<PathBuf>
+where
+    F: Fn(String, &Path, &mut LineWriter) -> io::Result<()>,
+{
+    let mut writer = LineWriter::create(output.clone(), options.level)?;
+    // Every file is a run of its own, so a failing handler only stops its own file
+    let config = DEFAULT_CONFIG.start_run();
+    let mut handler_error = None;
+    let result = process_file_sequential(path, &config, |buffer, context: &LineContext<'_>| {
+        if let Some(line) = decode_line(buffer, context, &config) {
+            if let Err(e) = line_handler(line, context.path, &mut writer) {
+                handler_error = Some(e);
+                config.stop();
+            }
+        }
+    });
+    let result = match handler_error {
+        Some(e) => Err(e),
+        None => result.and_then(|()| writer.finish()),
+    };
+    match result {
+        Ok(()) => Ok(output),
+        Err(e) => {
+            // The output is incomplete, nobody should mistake it for the rewritten file
+            let _ = fs::remove_file(&output);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::*;
+
+    #[test]
+    fn partial_outputs_of_failed_files_are_removed() {
+        let dir = test_dir("rewrite");
+        write_zst(&dir.join("scores.zst"), b"1\n2\n");
+        write_zst(&dir.join("broken.zst"), b"5\nfive\n");
+
+        let results = par_zstd_rewrite(
+            [dir.join("scores.zst"), dir.join("broken.zst")],
+            dir.join("out"),
+            &RewriteOptions { level: 3 },
+            |line, _path, out| {
+                let score: u32 = line
+                    .parse()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                out.write_line(&score.to_string())
+            },
+        );
+        assert_eq!(
+            results[0].1.as_ref().unwrap(),
+            &dir.join("out").join("scores.zst")
+        );
+        assert!(results[1].1.is_err());
+        assert!(!dir.join("out").join("broken.zst").exists());
+    }
+}
@@ -0,0 +1,56 @@
+//! Deciding which lines are delivered when only a sample of the lines is wanted.
+
+use crate::context::LineContext;
+
+/// Which lines are passed to the line handler, see ``ZstdLinesConfig::every_nth()`` and ``ZstdLinesConfig::fraction()``.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Sampling {
+    /// Every line is delivered.
+    All,
+    /// Lines whose number is a multiple of `n` are delivered.
+    EveryNth(u64),
+    /// Lines whose hash is below `threshold` are delivered, the hash depends on the seed and where the line is.
+    Fraction { threshold: u64, seed: u64 },
+}
+
+impl Sampling {
+    /// Sample a fraction between 0 and 1 of the lines.
+    pub(crate) fn fraction(fraction: f64, seed: u64) -> Self {
+        if fraction >= 1.0 {
+            return Sampling::All;
+        }
+        // Negative fractions and NaN saturate to 0, so no line is delivered
+        let threshold = (fraction * 2f64.powi(64)) as u64;
+        Sampling::Fraction { threshold, seed }
+    }
+
+    /// Check whether a line is part of the sample, without looking at its content.
+    pub(crate) fn includes(self, context: &LineContext<'_>) -> bool {
+        match self {
+            Sampling::All => true,
+            Sampling::EveryNth(n) => context.line_number.is_multiple_of(n),
+            Sampling::Fraction { threshold, seed } => {
+                let mut hash = seed ^ fnv1a(context.path.as_os_str().as_encoded_bytes());
+                if let Some(entry) = context.tar_entry {
+                    hash ^= fnv1a(entry.as_bytes()).rotate_left(32);
+                }
+                mix(hash ^ mix(context.byte_offset)) < threshold
+            }
+        }
+    }
+}
+
+/// Hash the name of a file or entry, so different files sample different lines.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01B3)
+    })
+}
+
+/// Finalizer of SplitMix64, spreading close offsets over the whole range.
+fn mix(mut value: u64) -> u64 {
+    value = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    value ^ (value >> 31)
+}
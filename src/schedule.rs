@@ -0,0 +1,63 @@
+//! Deciding in which order files are started, see ``ZstdLinesConfig::largest_first()``.
+
+use crate::config::ZstdLinesConfig;
+use rayon::iter::ParallelBridge;
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::fs;
+use std::path::Path;
+
+/// The size of a file, the key of ``ZstdLinesConfig::largest_first()``. ``None`` if it cannot be read,
+/// or if it is no regular file, like a FIFO.
+pub(crate) fn file_size(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+}
+
+/// Process every file with `process` and return the results in the order of the paths.
+/// Files are started in the order of the key set with ``ZstdLinesConfig::schedule_by()`` if there is one,
+/// largest keys first. Files without a key stay where they are among the others.
+pub(crate) fn process_files<I, R, P>(config: &ZstdLinesConfig<'_>, paths: I, process: P) -> Vec<R>
+where
+    I: ParallelIterator,
+    I::Item: AsRef<Path>,
+    R: Send,
+    P: Fn(&Path) -> R + Sync + Send,
+{
+    config.progress_files(paths.opt_len());
+    let Some(key) = &config.schedule else {
+        return paths.map(|path| process(path.as_ref())).collect();
+    };
+    let keyed: Vec<_> = paths.map(|path| (key(path.as_ref()), path)).collect();
+    let order = schedule(keyed.iter().map(|(key, _)| *key).collect());
+    let mut paths: Vec<_> = keyed.into_iter().map(|(_, path)| Some(path)).collect();
+    let scheduled: Vec<_> = order
+        .into_iter()
+        .map(|index| (index, paths[index].take().unwrap()))
+        .collect();
+
+    // Workers take the files from the front one by one, where splitting a vector would hand its back half to other threads
+    let mut results: Vec<(usize, R)> = scheduled
+        .into_iter()
+        .par_bridge()
+        .map(|(index, path)| (index, process(path.as_ref())))
+        .collect();
+    results.sort_unstable_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Order the files by their keys, largest first, returning their indices. Files with the same key keep their order,
+/// and files without a key keep their place.
+fn schedule(keys: Vec<Option<u64>>) -> Vec<usize> {
+    let places: Vec<usize> = (0..keys.len()).filter(|&i| keys[i].is_some()).collect();
+    let mut by_key = places.clone();
+    by_key.sort_by_key(|&i| Reverse(keys[i]));
+
+    let mut order: Vec<usize> = (0..keys.len()).collect();
+    for (place, index) in places.into_iter().zip(by_key) {
+        order[place] = index;
+    }
+    order
+}
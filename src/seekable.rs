@@ -0,0 +1,281 @@
+//! Parallel decoding of single files in the zstd seekable format.
+
+use crate::checksum::ChecksumReader;
+use crate::config::{Run, ZstdLinesConfig};
+use crate::context::LineContext;
+use crate::format::zstd_decoder;
+use crate::line_errors::LineProblems;
+use crate::report;
+use crate::text::LineDecoding;
+use crate::{
+    emit_line, is_tar_header, long_line, read_line, read_up_to, strip_bom, TAR_BLOCK_SIZE,
+};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+const SEEKABLE_MAGIC: u32 = 0x8F92EAB1;
+const SEEK_TABLE_MAGIC: u32 = 0x184D2A5E;
+const SKIPPABLE_HEADER_SIZE: u64 = 8;
+const FOOTER_SIZE: u64 = 9;
+const CHECKSUM_FLAG: u8 = 0b1000_0000;
+const RESERVED_BITS: u8 = 0b0111_1100;
+/// How many ranges to aim for per thread, so threads finishing early can pick up more work.
+const RANGES_PER_THREAD: usize = 4;
+
+/// A single independently decodable frame listed in the seek table.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SeekFrame {
+    compressed_offset: u64,
+    compressed_size: u64,
+    decompressed_offset: u64,
+    decompressed_size: u64,
+}
+
+/// A run of consecutive frames decoded by a single task.
+#[derive(Debug, Clone, Copy)]
+struct Range {
+    first_frame: usize,
+    start: u64,
+    end: u64,
+}
+
+/// Read the seek table at the end of a zstd seekable file.
+/// Returns ``None`` for anything that is not a valid seekable file with at least two frames,
+/// those files are processed by the regular single-threaded path which also reports any errors.
+pub(crate) fn seek_table(path: &Path, config: &ZstdLinesConfig<'_>) -> Option<Vec<SeekFrame>> {
+    // The seek table is a skippable frame itself, so it has to go through the frame callback,
+    // and lines can only be numbered when the file is decoded from the start
+    if config.on_skippable_frame.is_some() || config.line_numbers {
+        return None;
+    }
+
+    let file = File::open(path).ok()?;
+    let file_len = file.metadata().ok()?.len();
+    if file_len < SKIPPABLE_HEADER_SIZE + FOOTER_SIZE {
+        return None;
+    }
+    // A file which stalls in its seek table is processed sequentially, where it is reported
+    let mut file = config.watched(file).ok()?;
+
+    let mut footer = [0; FOOTER_SIZE as usize];
+    file.seek(SeekFrom::End(-(FOOTER_SIZE as i64))).ok()?;
+    file.read_exact(&mut footer).ok()?;
+    let frame_count = u32::from_le_bytes(footer[0..4].try_into().unwrap());
+    let descriptor = footer[4];
+    if u32::from_le_bytes(footer[5..9].try_into().unwrap()) != SEEKABLE_MAGIC
+        || descriptor & RESERVED_BITS != 0
+        || frame_count < 2
+    {
+        return None;
+    }
+
+    let entry_size: u64 = if descriptor & CHECKSUM_FLAG != 0 {
+        12
+    } else {
+        8
+    };
+    let table_len = SKIPPABLE_HEADER_SIZE + u64::from(frame_count) * entry_size + FOOTER_SIZE;
+    let data_len = file_len.checked_sub(table_len)?;
+
+    let mut table = vec![0; usize::try_from(table_len).ok()?];
+    file.seek(SeekFrom::Start(data_len)).ok()?;
+    file.read_exact(&mut table).ok()?;
+    if u32::from_le_bytes(table[0..4].try_into().unwrap()) != SEEK_TABLE_MAGIC
+        || u64::from(u32::from_le_bytes(table[4..8].try_into().unwrap()))
+            != table_len - SKIPPABLE_HEADER_SIZE
+    {
+        return None;
+    }
+
+    let mut frames = Vec::with_capacity(frame_count as usize);
+    let mut compressed_offset = 0;
+    let mut decompressed_offset = 0;
+    let entries = &table[SKIPPABLE_HEADER_SIZE as usize..(table_len - FOOTER_SIZE) as usize];
+    for entry in entries.chunks_exact(entry_size as usize) {
+        let compressed_size = u64::from(u32::from_le_bytes(entry[0..4].try_into().unwrap()));
+        let decompressed_size = u64::from(u32::from_le_bytes(entry[4..8].try_into().unwrap()));
+        frames.push(SeekFrame {
+            compressed_offset,
+            compressed_size,
+            decompressed_offset,
+            decompressed_size,
+        });
+        compressed_offset += compressed_size;
+        decompressed_offset += decompressed_size;
+    }
+
+    // The frames have to cover everything in front of the seek table, otherwise it is not describing this file
+    (compressed_offset == data_len).then_some(frames)
+}
+
+/// Check whether the decompressed content of a seekable file starts with something other than a tar header.
+/// Archives named like a plain file are only walked entry by entry from the start, as are files which cannot be
+/// read here, the sequential path reports their problem.
+pub(crate) fn starts_plain(path: &Path, config: &ZstdLinesConfig<'_>) -> bool {
+    let sniff = || -> io::Result<bool> {
+        let file = config.watched(File::open(path)?)?;
+        let mut decoder = zstd_decoder(file, config)?;
+        let mut block = [0; TAR_BLOCK_SIZE];
+        let block_len = read_up_to(&mut decoder, &mut block)?;
+        Ok(!is_tar_header(&block[..block_len]))
+    };
+    sniff().unwrap_or(false)
+}
+
+/// Process a file in the zstd seekable format, decoding ranges of frames on separate rayon tasks.
+/// Each line is delivered exactly once, lines spanning frames are reassembled by the range they start in.
+/// Lines within a range are delivered in order, but ranges are processed concurrently.
+///
+/// A line belongs to the range ``(start, end]`` its first byte lies in, with the first range also owning the line at 0.
+/// Every range but the first skips the line it starts in, and continues decoding past its end to finish its last line.
+/// Since a line starting exactly at ``start`` belongs to the previous range, no range needs to look at the byte before its start.
+pub(crate) fn process_seekable_file<F>(
+    path: &Path,
+    frames: &[SeekFrame],
+    config: &Run<'_>,
+    line_handler: &F,
+) -> io::Result<()>
+where
+    F: Fn(&mut Vec<u8>, &LineContext<'_>) + Sync + Send,
+{
+    let target_ranges = rayon::current_num_threads() * RANGES_PER_THREAD;
+    let tally = report::current();
+    split_ranges(frames, target_ranges)
+        .into_par_iter()
+        .try_for_each(|range| {
+            let _tally = report::enter(tally.clone());
+            process_range(path, frames, range, config, line_handler)
+        })
+}
+
+/// Like ``process_seekable_file()``, but threading an accumulator through the line handler for every line.
+/// Every range folds into its own accumulator created by `init`, which are merged with `combine` in the order of the ranges.
+/// Returns the accumulator along with the first error, ranges which failed contribute the lines in front of the failure.
+pub(crate) fn fold_seekable_file<A, I, F, C>(
+    path: &Path,
+    frames: &[SeekFrame],
+    config: &Run<'_>,
+    init: &I,
+    line_handler: &F,
+    combine: &C,
+) -> (A, io::Result<()>)
+where
+    A: Send,
+    I: Fn() -> A + Sync + Send,
+    F: Fn(A, &mut Vec<u8>, &LineContext<'_>) -> A + Sync + Send,
+    C: Fn(A, A) -> A + Sync + Send,
+{
+    let target_ranges = rayon::current_num_threads() * RANGES_PER_THREAD;
+    let tally = report::current();
+    let ranges: Vec<_> = split_ranges(frames, target_ranges)
+        .into_par_iter()
+        .map(|range| {
+            let _tally = report::enter(tally.clone());
+            let mut accumulator = Some(init());
+            let result = process_range(path, frames, range, config, |line, context| {
+                accumulator = accumulator.take().map(|a| line_handler(a, line, context));
+            });
+            (accumulator.unwrap_or_else(init), result)
+        })
+        .collect();
+
+    let mut accumulator = init();
+    let mut result = Ok(());
+    for (range_accumulator, range_result) in ranges {
+        accumulator = combine(accumulator, range_accumulator);
+        if result.is_ok() {
+            result = range_result;
+        }
+    }
+    (accumulator, result)
+}
+
+/// Group consecutive frames into roughly `target_ranges` ranges of similar decompressed size.
+fn split_ranges(frames: &[SeekFrame], target_ranges: usize) -> Vec<Range> {
+    let last = frames[frames.len() - 1];
+    let total = last.decompressed_offset + last.decompressed_size;
+    let target_size = (total / target_ranges.max(1) as u64).max(1);
+
+    let mut ranges = Vec::new();
+    let mut first_frame = 0;
+    for (index, frame) in frames.iter().enumerate() {
+        let start = frames[first_frame].decompressed_offset;
+        let end = frame.decompressed_offset + frame.decompressed_size;
+        if end - start >= target_size || index == frames.len() - 1 {
+            ranges.push(Range {
+                first_frame,
+                start,
+                end,
+            });
+            first_frame = index + 1;
+        }
+    }
+    ranges
+}
+
+/// Decode the lines owned by a single range.
+fn process_range<F>(
+    path: &Path,
+    frames: &[SeekFrame],
+    range: Range,
+    config: &Run<'_>,
+    mut line_handler: F,
+) -> io::Result<()>
+where
+    F: FnMut(&mut Vec<u8>, &LineContext<'_>),
+{
+    let last = frames[frames.len() - 1];
+    let offset = frames[range.first_frame].compressed_offset;
+    let mut file = config.open_input(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    // Reading stops in front of the seek table, the decoder continues into later frames as needed
+    let data = file.take(last.compressed_offset + last.compressed_size - offset);
+    let decoder = zstd_decoder(data, config)?;
+    let first = frames[range.first_frame].decompressed_offset;
+    let mut reader = config.buffered(ChecksumReader::new(decoder, path, first));
+
+    let mut buffer = Vec::new();
+    let mut position = range.start;
+    if range.start > 0 {
+        // The line this range starts in is finished by the previous range
+        position += read_line(&mut reader, &mut buffer, config)?.0 as u64;
+        buffer.clear();
+    }
+    // Line numbers are unknown when ranges are decoded in parallel, files which need them are never split
+    let shared_path: Arc<Path> = Arc::from(path);
+    let mut context = LineContext {
+        path,
+        shared_path: &shared_path,
+        line_number: 0,
+        byte_offset: 0,
+        tar_entry: None,
+        partial: false,
+        attempt: 1,
+        decoding: LineDecoding::new(path, config),
+    };
+    let mut stream_bytes = report::StreamBytes::start();
+    let problems = LineProblems::start(config);
+    while position <= range.end {
+        let (bytes_read, too_long) = read_line(&mut reader, &mut buffer, config)?;
+        if bytes_read == 0 {
+            break;
+        }
+        if position == 0 && config.strip_bom {
+            strip_bom(&mut buffer);
+        }
+        context.byte_offset = position;
+        position += bytes_read as u64;
+        stream_bytes.add(bytes_read);
+        if too_long {
+            long_line(&context, config)?;
+        } else {
+            emit_line(&mut buffer, &context, config, &mut line_handler);
+        }
+        problems.check(config)?;
+        config.check_stopped()?;
+    }
+    Ok(())
+}
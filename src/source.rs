@@ -0,0 +1,328 @@
+//! Processing arbitrary ``Read`` sources instead of files on disk.
+
+use crate::config::{Run, DEFAULT_CONFIG};
+use crate::diagnostics;
+use crate::format::decompress;
+use crate::text::{decoded, decoded_sync};
+use crate::{process_file, process_sniffed, process_tar_entries, split_lines};
+use rayon::prelude::*;
+use std::fmt;
+use std::io::{self, BufRead, Cursor, Read};
+use std::path::{Path, PathBuf};
+
+/// How the decompressed content of a source should be split into lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContentKind {
+    /// Plain newline delimited content.
+    Plain,
+    /// A tar archive, treated as one continuous file omitting all tar headers.
+    Tar,
+    /// Sniff the first decompressed block for a tar header.
+    #[default]
+    Detect,
+}
+
+/// A labelled zstd compressed ``Read`` source.
+/// The label is passed to the line handler in place of a file path.
+#[derive(Debug)]
+pub struct ReaderSource<R> {
+    /// Passed to the line handler in place of a path.
+    pub label: String,
+    /// The zstd compressed data.
+    pub reader: R,
+    /// How the decompressed content should be split into lines.
+    pub kind: ContentKind,
+}
+
+impl<R> ReaderSource<R> {
+    /// Create a new source which detects tar content automatically.
+    pub fn new(label: impl Into<String>, reader: R) -> Self {
+        ReaderSource {
+            label: label.into(),
+            reader,
+            kind: ContentKind::default(),
+        }
+    }
+
+    /// Set how the decompressed content should be split into lines.
+    pub fn with_kind(mut self, kind: ContentKind) -> Self {
+        self.kind = kind;
+        self
+    }
+}
+
+impl<R> From<(String, R)> for ReaderSource<R> {
+    fn from((label, reader): (String, R)) -> Self {
+        ReaderSource::new(label, reader)
+    }
+}
+
+impl<R> From<(&str, R)> for ReaderSource<R> {
+    fn from((label, reader): (&str, R)) -> Self {
+        ReaderSource::new(label, reader)
+    }
+}
+
+/// Process zstd compressed ``Read`` sources line-by-line and in parallel using stream decompression.
+/// Each source is labelled, and the label is passed to the line handler in place of a path.
+///
+/// # Arguments
+///
+/// * `sources` - ``ReaderSource``s or ``(label, reader)`` tuples.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use zstd_lines::{ContentKind, ReaderSource};
+/// use std::io::Cursor;
+/// use std::sync::Mutex;
+///
+/// let first = Cursor::new(zstd::encode_all(&b"a\nb\n"[..], 0).unwrap());
+/// let second = Cursor::new(zstd::encode_all(&b"c\n"[..], 0).unwrap());
+/// let sources = vec![
+///     ReaderSource::new("first", first),
+///     ReaderSource::new("second", second).with_kind(ContentKind::Plain),
+/// ];
+///
+/// let lines = Mutex::new(Vec::new());
+/// zstd_lines::par_zstd_lines_readers(sources, |line, label| {
+///     lines.lock().unwrap().push(format!("{}:{}", label.display(), line));
+/// });
+/// let mut lines = lines.into_inner().unwrap();
+/// lines.sort();
+/// assert_eq!(lines, vec!["first:a", "first:b", "second:c"]);
+/// ```
+pub fn par_zstd_lines_readers<S, R, F>(sources: Vec<S>, line_handler: F)
+where
+    S: Into<ReaderSource<R>> + Send,
+    R: Read + Send,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    sources.into_par_iter().for_each(|source| {
+        let source = source.into();
+        let label = Path::new(&source.label);
+        if let Err(e) = process_reader(source.reader, label, source.kind, &line_handler) {
+            let message = format!("Failed to process zst stream {}: {}", source.label, e);
+            diagnostics::report_message(label, &message);
+        }
+    });
+}
+
+/// Decompress a ``Read`` source and split its content into lines according to `kind`.
+/// The compression format is detected by the magic bytes, uncompressed content is split as it is.
+pub(crate) fn process_reader<R, F>(
+    reader: R,
+    label: &Path,
+    kind: ContentKind,
+    line_handler: F,
+) -> io::Result<()>
+where
+    R: Read,
+    F: FnMut(String, &Path),
+{
+    let config = DEFAULT_CONFIG.start_run();
+    let mut line_handler = line_handler;
+    let line_handler = decoded(&config, |line, context| line_handler(line, context.path));
+    let stream_handler = split_lines(label, &config, line_handler);
+    process_reader_with(reader, label, kind, &config, stream_handler)
+}
+
+/// Like ``process_reader()``, but using the settings of `config` and passing the decompressed content to the stream handler,
+/// once per tar entry or once for the whole source.
+pub(crate) fn process_reader_with<R, S>(
+    reader: R,
+    label: &Path,
+    kind: ContentKind,
+    config: &Run<'_>,
+    mut stream_handler: S,
+) -> io::Result<()>
+where
+    R: Read,
+    S: FnMut(&mut dyn BufRead, Option<&str>) -> io::Result<()>,
+{
+    let decoder = decompress(reader, label, config)?;
+    match kind {
+        ContentKind::Plain => stream_handler(&mut config.buffered(decoder), None),
+        ContentKind::Tar => process_tar_entries(decoder, label, config, stream_handler),
+        ContentKind::Detect => process_sniffed(decoder, label, config, false, stream_handler),
+    }
+}
+
+/// Process an in-memory zstd compressed buffer line-by-line.
+/// Tar content is detected by sniffing the first decompressed block for a ustar header.
+///
+/// # Arguments
+///
+/// * `label` - Passed to the line handler in place of a path.
+/// * `data` - The zstd compressed bytes.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// let data = zstd::encode_all(&b"first\nsecond\n"[..], 0).unwrap();
+///
+/// let mut lines = Vec::new();
+/// zstd_lines::zstd_lines_bytes("payload", &data, |line, label| {
+///     assert_eq!(label.to_str(), Some("payload"));
+///     lines.push(line);
+/// })?;
+/// assert_eq!(lines, vec!["first", "second"]);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn zstd_lines_bytes<F>(label: &str, data: &[u8], line_handler: F) -> io::Result<()>
+where
+    F: FnMut(String, &Path),
+{
+    process_reader(
+        Cursor::new(data),
+        Path::new(label),
+        ContentKind::Detect,
+        line_handler,
+    )
+}
+
+/// Process in-memory zstd compressed buffers line-by-line and in parallel.
+/// Tar content is detected by sniffing the first decompressed block of each buffer for a ustar header.
+///
+/// # Arguments
+///
+/// * `buffers` - ``(label, data)`` pairs, where the label is passed to the line handler in place of a path.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// let buffers = vec![
+///     ("a".to_string(), zstd::encode_all(&b"1\n2\n"[..], 0).unwrap()),
+///     ("b".to_string(), zstd::encode_all(&b"3\n"[..], 0).unwrap()),
+/// ];
+/// let count = AtomicUsize::new(0);
+/// zstd_lines::par_zstd_lines_bytes(buffers, |_line, _label| {
+///     count.fetch_add(1, Ordering::Relaxed);
+/// });
+/// assert_eq!(count.into_inner(), 3);
+/// ```
+pub fn par_zstd_lines_bytes<F>(buffers: Vec<(String, Vec<u8>)>, line_handler: F)
+where
+    F: Fn(String, &Path) + Sync + Send,
+{
+    let sources = buffers
+        .into_iter()
+        .map(|(label, data)| ReaderSource::new(label, Cursor::new(data)))
+        .collect();
+    par_zstd_lines_readers(sources, line_handler);
+}
+
+/// A single input for ``par_zstd_lines_sources()``, allowing files, buffers and readers to be mixed in one batch.
+pub enum ZstdSource {
+    /// A file on disk, handled exactly like in ``par_zstd_lines()``.
+    Path(PathBuf),
+    /// An in-memory zstd compressed buffer.
+    Bytes { label: String, data: Vec<u8> },
+    /// Any zstd compressed reader, e.g. a network stream.
+    Reader {
+        label: String,
+        reader: Box<dyn Read + Send>,
+    },
+}
+
+impl ZstdSource {
+    /// The path or label which is passed to the line handler for this source.
+    pub fn label(&self) -> &Path {
+        match self {
+            ZstdSource::Path(path) => path,
+            ZstdSource::Bytes { label, .. } | ZstdSource::Reader { label, .. } => Path::new(label),
+        }
+    }
+}
+
+impl fmt::Debug for ZstdSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZstdSource::Path(path) => f.debug_tuple("Path").field(path).finish(),
+            ZstdSource::Bytes { label, data } => f
+                .debug_struct("Bytes")
+                .field("label", label)
+                .field("len", &data.len())
+                .finish(),
+            ZstdSource::Reader { label, .. } => {
+                f.debug_struct("Reader").field("label", label).finish()
+            }
+        }
+    }
+}
+
+impl From<PathBuf> for ZstdSource {
+    fn from(path: PathBuf) -> Self {
+        ZstdSource::Path(path)
+    }
+}
+
+impl From<&Path> for ZstdSource {
+    fn from(path: &Path) -> Self {
+        ZstdSource::Path(path.to_path_buf())
+    }
+}
+
+/// Process a mixed batch of files, buffers and readers line-by-line and in parallel.
+/// Every source goes through the same decompression and line splitting, buffers and readers are sniffed for tar content.
+///
+/// # Arguments
+///
+/// * `sources` - The sources to process.
+/// * `line_handler` - A function or closure that will handle each decompressed line, receiving the path or label of its source.
+///
+/// # Example
+/// ```
+/// use zstd_lines::ZstdSource;
+/// use std::io::Cursor;
+/// use std::path::PathBuf;
+/// use std::sync::Mutex;
+///
+/// let sources = vec![
+///     ZstdSource::Path(PathBuf::from("file.jsonl.zst")),
+///     ZstdSource::Bytes {
+///         label: "cache".to_string(),
+///         data: zstd::encode_all(&b"cached\n"[..], 0).unwrap(),
+///     },
+///     ZstdSource::Reader {
+///         label: "stream".to_string(),
+///         reader: Box::new(Cursor::new(zstd::encode_all(&b"streamed\n"[..], 0).unwrap())),
+///     },
+/// ];
+///
+/// let lines = Mutex::new(Vec::new());
+/// zstd_lines::par_zstd_lines_sources(sources, |line, label| {
+///     lines.lock().unwrap().push(format!("{}:{}", label.display(), line));
+/// });
+/// let mut lines = lines.into_inner().unwrap();
+/// lines.sort();
+/// assert_eq!(lines, vec!["cache:cached", "stream:streamed"]);
+/// ```
+pub fn par_zstd_lines_sources<F>(sources: Vec<ZstdSource>, line_handler: F)
+where
+    F: Fn(String, &Path) + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    sources.into_par_iter().for_each(|source| match source {
+        ZstdSource::Path(path) => {
+            let line_handler =
+                decoded_sync(&config, |line, context| line_handler(line, context.path));
+            process_file(&path, &config, &line_handler);
+        }
+        ZstdSource::Bytes { label, data } => {
+            if let Err(e) = zstd_lines_bytes(&label, &data, &line_handler) {
+                let message = format!("Failed to process zst buffer {}: {}", label, e);
+                diagnostics::report_message(Path::new(&label), &message);
+            }
+        }
+        ZstdSource::Reader { label, reader } => {
+            let path = Path::new(&label);
+            if let Err(e) = process_reader(reader, path, ContentKind::Detect, &line_handler) {
+                let message = format!("Failed to process zst stream {}: {}", label, e);
+                diagnostics::report_message(path, &message);
+            }
+        }
+    });
+}
@@ -0,0 +1,220 @@
+//! Multi-part archives split into numbered files, e.g. with ``split -b``.
+
+use crate::diagnostics;
+use crate::{process_reader, ContentKind};
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::vec;
+
+/// Group paths following the ``name.zst.000``, ``name.zst.001`` naming convention into ordered part lists.
+/// Paths without a numeric extension end up in a group of their own.
+///
+/// # Example
+/// ```
+/// use std::path::PathBuf;
+///
+/// let groups = zstd_lines::group_split_parts(vec![
+///     "dump.jsonl.zst.001",
+///     "other.jsonl.zst",
+///     "dump.jsonl.zst.000",
+/// ]);
+/// assert_eq!(groups, vec![
+///     vec![PathBuf::from("dump.jsonl.zst.000"), PathBuf::from("dump.jsonl.zst.001")],
+///     vec![PathBuf::from("other.jsonl.zst")],
+/// ]);
+/// ```
+pub fn group_split_parts<I>(paths: I) -> Vec<Vec<PathBuf>>
+where
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+{
+    let mut groups: BTreeMap<PathBuf, Vec<(u64, PathBuf)>> = BTreeMap::new();
+    for path in paths {
+        let path = path.as_ref();
+        let (base, number) = match part_number(path) {
+            Some(number) => (path.with_extension(""), number),
+            None => (path.to_path_buf(), 0),
+        };
+        groups
+            .entry(base)
+            .or_default()
+            .push((number, path.to_path_buf()));
+    }
+
+    groups
+        .into_values()
+        .map(|mut parts| {
+            parts.sort();
+            parts.into_iter().map(|(_, path)| path).collect()
+        })
+        .collect()
+}
+
+/// Process groups of split archive parts line-by-line and in parallel.
+/// The parts of each group are concatenated in order before decompression, so lines spanning part boundaries are reassembled.
+/// The line handler receives the path of the first part, and missing parts are reported before anything is decoded.
+/// Tar archives are recognized by their first decompressed block.
+///
+/// # Arguments
+///
+/// * `groups` - Ordered lists of parts, e.g. from ``group_split_parts()``.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use std::sync::Mutex;
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_split");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// let data = zstd::encode_all(&b"first line\nsecond line\n"[..], 0).unwrap();
+/// let (head, tail) = data.split_at(data.len() / 2);
+/// std::fs::write(dir.join("dump.jsonl.zst.000"), head).unwrap();
+/// std::fs::write(dir.join("dump.jsonl.zst.001"), tail).unwrap();
+///
+/// let groups = zstd_lines::group_split_parts(vec![
+///     dir.join("dump.jsonl.zst.000"),
+///     dir.join("dump.jsonl.zst.001"),
+/// ]);
+/// let lines = Mutex::new(Vec::new());
+/// zstd_lines::par_zstd_lines_parts(groups, |line, _path| lines.lock().unwrap().push(line));
+/// assert_eq!(lines.into_inner().unwrap(), vec!["first line", "second line"]);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_parts<P, F>(groups: Vec<Vec<P>>, line_handler: F)
+where
+    P: AsRef<Path> + Send,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    groups.into_par_iter().for_each(|parts| {
+        let parts: Vec<PathBuf> = parts
+            .iter()
+            .map(|part| part.as_ref().to_path_buf())
+            .collect();
+        let Some(first) = parts.first().cloned() else {
+            return;
+        };
+        if let Err(e) = process_parts(parts, &first, &line_handler) {
+            let message = format!(
+                "Failed to process split zst file {}: {}",
+                first.display(),
+                e
+            );
+            diagnostics::report_message(&first, &message);
+        }
+    });
+}
+
+/// Check that all parts are present and decode them as one logical file.
+fn process_parts<F>(parts: Vec<PathBuf>, first: &Path, line_handler: F) -> io::Result<()>
+where
+    F: FnMut(String, &Path),
+{
+    check_parts(&parts)?;
+    // Tar archives are recognized by their content, the name of a part says nothing about it
+    process_reader(
+        PartsReader::new(parts),
+        first,
+        ContentKind::Detect,
+        line_handler,
+    )
+}
+
+/// Make sure every part exists and the part numbers start at 0 or 1 and have no gaps.
+fn check_parts(parts: &[PathBuf]) -> io::Result<()> {
+    let mut expected = None;
+    for part in parts {
+        if let Some(number) = part_number(part) {
+            // The first part is numbered 0 with ``split -d`` and 1 with most other tools
+            let first = (number > 1).then_some(1);
+            if let Some(expected) = expected.or(first).filter(|&expected| expected != number) {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("missing part {:03} before {}", expected, part.display()),
+                ));
+            }
+            expected = Some(number + 1);
+        }
+        fs::metadata(part)
+            .map_err(|e| io::Error::new(e.kind(), format!("part {}: {}", part.display(), e)))?;
+    }
+    Ok(())
+}
+
+/// Parse the numeric extension of a split part, e.g. 1 for ``dump.jsonl.zst.001``.
+fn part_number(path: &Path) -> Option<u64> {
+    let extension = path.extension()?.to_str()?;
+    if extension.is_empty() || !extension.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    extension.parse().ok()
+}
+
+/// Reads a list of files one after another as if they were a single file.
+struct PartsReader {
+    parts: vec::IntoIter<PathBuf>,
+    current: Option<File>,
+}
+
+impl PartsReader {
+    fn new(parts: Vec<PathBuf>) -> Self {
+        PartsReader {
+            parts: parts.into_iter(),
+            current: None,
+        }
+    }
+}
+
+impl Read for PartsReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(file) = &mut self.current {
+                let bytes_read = file.read(buf)?;
+                if bytes_read > 0 || buf.is_empty() {
+                    return Ok(bytes_read);
+                }
+            }
+            match self.parts.next() {
+                Some(part) => self.current = Some(File::open(part)?),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_dir;
+
+    #[test]
+    fn parts_are_numbered_from_zero_or_one() {
+        let dir = test_dir("split_numbering");
+        let parts = |numbers: &[&str]| -> Vec<PathBuf> {
+            numbers
+                .iter()
+                .map(|number| {
+                    let part = dir.join(format!("dump.jsonl.zst.{}", number));
+                    fs::write(&part, b"part").unwrap();
+                    part
+                })
+                .collect()
+        };
+
+        check_parts(&parts(&["000", "001"])).unwrap();
+        check_parts(&parts(&["001", "002"])).unwrap();
+        let error = check_parts(&parts(&["002", "003"])).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "missing part 001 before {}",
+                dir.join("dump.jsonl.zst.002").display()
+            )
+        );
+        let error = check_parts(&parts(&["001", "003"])).unwrap_err();
+        assert!(error.to_string().starts_with("missing part 002 before"));
+    }
+}
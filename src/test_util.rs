@@ -0,0 +1,117 @@
+//! Helpers shared by the unit tests of all modules.
+
+use crate::{par_zstd_lines_with_entry, ZstdLines};
+use std::fs;
+use std::io;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A directory of its own for the files of a test, removed with everything in it when the test is done.
+pub(crate) struct TestDir(PathBuf);
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Create an empty directory for the files of the test `name`, leftovers of an earlier run are removed.
+pub(crate) fn test_dir(name: &str) -> TestDir {
+    let dir = std::env::temp_dir().join(format!("zstd_lines_test_{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    TestDir(dir)
+}
+
+/// Write `content` zstd compressed to `path`.
+pub(crate) fn write_zst(path: &Path, content: &[u8]) {
+    fs::write(path, zstd::encode_all(content, 0).unwrap()).unwrap();
+}
+
+/// A tar archive with an entry for every pair of name and content.
+pub(crate) fn tar_archive(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut tar = tar::Builder::new(Vec::new());
+    for (name, content) in entries {
+        let mut header = tar::Header::new_ustar();
+        header.set_size(content.len() as u64);
+        tar.append_data(&mut header, name, *content).unwrap();
+    }
+    tar.into_inner().unwrap()
+}
+
+/// Write a zstd compressed tar archive with an entry for every pair of name and content to `path`.
+pub(crate) fn tar_zst(path: &Path, entries: &[(&str, &[u8])]) {
+    write_zst(path, &tar_archive(entries));
+}
+
+/// A tar archive with a ustar entry of every type, name, link name and content.
+pub(crate) fn typed_tar_archive(
+    entries: &[(tar::EntryType, &str, Option<&str>, &[u8])],
+) -> Vec<u8> {
+    let mut tar = tar::Builder::new(Vec::new());
+    for (entry_type, name, link, content) in entries {
+        let mut header = tar::Header::new_ustar();
+        header.set_entry_type(*entry_type);
+        header.set_path(name).unwrap();
+        if let Some(link) = link {
+            header.set_link_name(link).unwrap();
+        }
+        header.set_size(content.len() as u64);
+        header.set_cksum();
+        tar.append(&header, *content).unwrap();
+    }
+    tar.into_inner().unwrap()
+}
+
+/// Write `chunks` to `path` as frames of a file in the zstd seekable format, one frame per chunk.
+pub(crate) fn write_seekable(path: &Path, chunks: &[&[u8]]) {
+    let mut data = Vec::new();
+    let mut seek_table = Vec::new();
+    for chunk in chunks {
+        let frame = zstd::encode_all(*chunk, 0).unwrap();
+        seek_table.extend((frame.len() as u32).to_le_bytes());
+        seek_table.extend((chunk.len() as u32).to_le_bytes());
+        data.extend(frame);
+    }
+    seek_table.extend((chunks.len() as u32).to_le_bytes());
+    seek_table.push(0);
+    seek_table.extend(0x8F92EAB1u32.to_le_bytes());
+    data.extend(0x184D2A5Eu32.to_le_bytes());
+    data.extend((seek_table.len() as u32).to_le_bytes());
+    data.extend(seek_table);
+    fs::write(path, data).unwrap();
+}
+
+/// The lines of a file and how processing it ended.
+pub(crate) fn lines_of(path: &Path) -> (Vec<String>, io::Result<()>) {
+    let mut lines = Vec::new();
+    let result = path.zstd_lines(|line, _path| lines.push(line));
+    (lines, result)
+}
+
+/// The lines of every file prefixed with the name of their entry.
+pub(crate) fn entry_lines(path: &Path) -> Vec<String> {
+    let lines = Mutex::new(Vec::new());
+    par_zstd_lines_with_entry([path], |line, _path, entry| {
+        lines
+            .lock()
+            .unwrap()
+            .push(format!("{} {}", entry.unwrap(), line));
+    });
+    lines.into_inner().unwrap()
+}
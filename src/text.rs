@@ -0,0 +1,249 @@
+//! Turning the bytes of a line into a string.
+
+use crate::config::{Run, ZstdLinesConfig};
+use crate::context::LineContext;
+use crate::error::ZstdLinesError;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+/// What is removed from the end of every line besides the ``\n``.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    /// Only remove the ``\n``, a ``\r`` in front of it is part of the line.
+    KeepCr,
+    /// Remove a single ``\r`` at the end of the line, like most line-oriented tools.
+    #[default]
+    StripCr,
+    /// Remove all ASCII whitespace at the end of the line.
+    TrimWhitespace,
+}
+
+impl LineEnding {
+    /// Remove the line break from a line read with ``read_until()``, along with whatever the policy removes.
+    /// The last line of a stream is treated the same way, even without a ``\n``.
+    pub(crate) fn trim(self, buffer: &mut Vec<u8>) {
+        if buffer.last() == Some(&b'\n') {
+            buffer.pop();
+        }
+        match self {
+            LineEnding::KeepCr => {}
+            LineEnding::StripCr => {
+                if buffer.last() == Some(&b'\r') {
+                    buffer.pop();
+                }
+            }
+            LineEnding::TrimWhitespace => {
+                let len = buffer
+                    .iter()
+                    .rposition(|b| !b.is_ascii_whitespace())
+                    .map_or(0, |i| i + 1);
+                buffer.truncate(len);
+            }
+        }
+    }
+}
+
+/// Which lines are skipped before they reach the line handler, checked after the line ending is removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyLines {
+    /// Deliver every line.
+    #[default]
+    Keep,
+    /// Skip lines without a single byte.
+    SkipEmpty,
+    /// Skip lines consisting of nothing but ASCII whitespace, including lines without a single byte.
+    SkipBlank,
+}
+
+impl EmptyLines {
+    /// Check whether a line with its line ending removed is skipped.
+    pub(crate) fn skips(self, line: &[u8]) -> bool {
+        match self {
+            EmptyLines::Keep => false,
+            EmptyLines::SkipEmpty => line.is_empty(),
+            EmptyLines::SkipBlank => line.iter().all(u8::is_ascii_whitespace),
+        }
+    }
+}
+
+/// What happens to a line which is longer than ``ZstdLinesConfig::max_line_len()``.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LongLines {
+    /// Report the line on stderr and go on with the next line.
+    #[default]
+    Skip,
+    /// Fail the file or archive entry with an ``InvalidData`` error, the lines in front of it are still delivered.
+    Abort,
+}
+
+/// What happens to a line which is not valid UTF-8, see ``ZstdLinesConfig::utf8()``.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Utf8Policy {
+    /// Report the line on stderr, or pass it to ``ZstdLinesConfig::on_invalid_utf8()``, and skip it.
+    #[default]
+    Skip,
+    /// Deliver the line with malformed sequences replaced by U+FFFD, like ``ZstdLinesConfig::utf8_lossy()``.
+    Lossy,
+}
+
+/// How the lines of a single file or archive entry are decoded, resolved from the configuration before its first line.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct LineDecoding {
+    #[cfg(feature = "encoding")]
+    encoding: Option<&'static encoding_rs::Encoding>,
+    replace_malformed: bool,
+}
+
+impl LineDecoding {
+    /// Resolve the encoding of the file at `path`.
+    #[cfg_attr(not(feature = "encoding"), allow(unused_variables))]
+    pub(crate) fn new(path: &Path, config: &ZstdLinesConfig<'_>) -> Self {
+        LineDecoding {
+            #[cfg(feature = "encoding")]
+            encoding: config.encoding.as_ref().map(|select| select(path)),
+            replace_malformed: config.replace_malformed,
+        }
+    }
+
+    /// Name of the encoding, used when reporting lines which cannot be decoded.
+    fn name(self) -> &'static str {
+        #[cfg(feature = "encoding")]
+        if let Some(encoding) = self.encoding {
+            return encoding.name();
+        }
+        "UTF-8"
+    }
+
+    /// Decode a line, giving back the bytes if they are malformed and malformed sequences are not replaced.
+    /// Decoded lines come with whether malformed sequences had to be replaced.
+    fn decode(self, bytes: Vec<u8>) -> Result<(String, bool), Vec<u8>> {
+        #[cfg(feature = "encoding")]
+        if let Some(encoding) = self
+            .encoding
+            .filter(|&encoding| encoding != encoding_rs::UTF_8)
+        {
+            if self.replace_malformed {
+                let (line, had_errors) = encoding.decode_without_bom_handling(&bytes);
+                return Ok((line.into_owned(), had_errors));
+            }
+            return match encoding.decode_without_bom_handling_and_without_replacement(&bytes) {
+                Some(line) => Ok((line.into_owned(), false)),
+                None => Err(bytes),
+            };
+        }
+        match String::from_utf8(bytes) {
+            Ok(line) => Ok((line, false)),
+            Err(e) if self.replace_malformed => {
+                Ok((String::from_utf8_lossy(e.as_bytes()).into_owned(), true))
+            }
+            Err(e) => Err(e.into_bytes()),
+        }
+    }
+}
+
+/// Turn a handler of decoded lines into a handler of the raw lines produced by line splitting.
+/// Lines which cannot be decoded with the settings of `config` are reported on stderr and skipped.
+pub(crate) fn decoded<'a, F>(
+    config: &'a Run<'a>,
+    mut line_handler: F,
+) -> impl FnMut(&mut Vec<u8>, &LineContext<'_>) + 'a
+where
+    F: FnMut(String, &LineContext<'_>) + 'a,
+{
+    move |buffer, context| {
+        if let Some(line) = decode_line(buffer, context, config) {
+            line_handler(line, context);
+        }
+    }
+}
+
+/// Like ``decoded()``, for handlers which are shared between threads.
+pub(crate) fn decoded_sync<'a, F>(
+    config: &'a Run<'a>,
+    line_handler: F,
+) -> impl Fn(&mut Vec<u8>, &LineContext<'_>) + Sync + Send + 'a
+where
+    F: Fn(String, &LineContext<'_>) + Sync + Send + 'a,
+{
+    move |buffer, context| {
+        if let Some(line) = decode_line(buffer, context, config) {
+            line_handler(line, context);
+        }
+    }
+}
+
+/// Decode the line in `buffer`, taking over its allocation.
+/// Malformed lines are reported and left in the buffer.
+pub(crate) fn decode_line(
+    buffer: &mut Vec<u8>,
+    context: &LineContext<'_>,
+    config: &Run<'_>,
+) -> Option<String> {
+    let decoding = context.decoding;
+    match decoding.decode(std::mem::take(buffer)) {
+        Ok((line, replaced)) => {
+            if replaced {
+                config.replaced_lines.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(line)
+        }
+        // Lines that cannot be decoded are skipped, the rest of the file is still readable
+        Err(bytes) => {
+            config.invalid_lines.fetch_add(1, Ordering::Relaxed);
+            match &config.on_invalid_utf8 {
+                Some(handler) => handler(&bytes, context.path, context.line_number),
+                None => report_malformed(context, config, decoding.name()),
+            }
+            *buffer = bytes;
+            None
+        }
+    }
+}
+
+/// Report a line which is not valid in `encoding` and is skipped.
+pub(crate) fn report_malformed(
+    context: &LineContext<'_>,
+    config: &Run<'_>,
+    encoding: &'static str,
+) {
+    config.report(&ZstdLinesError::InvalidLine {
+        path: context.path.to_path_buf(),
+        entry: context.tar_entry.map(str::to_string),
+        line_number: context.line_number,
+        byte_offset: context.byte_offset,
+        encoding,
+    });
+}
+
+#[cfg(all(test, feature = "encoding"))]
+mod tests {
+    use crate::test_util::{tar_zst, test_dir, write_zst};
+    use crate::ZstdLinesConfig;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn encodings_are_selected_once_per_file_and_entry() {
+        let dir = test_dir("encoding_per_file");
+        tar_zst(
+            &dir.join("logs.tar.zst"),
+            &[("a.log", b"1\n2\n"), ("b.log", b"1\n2\n")],
+        );
+        write_zst(&dir.join("plain.log.zst"), b"1\n2\n3\n");
+
+        let selected = AtomicUsize::new(0);
+        let lines = AtomicUsize::new(0);
+        ZstdLinesConfig::new()
+            .encoding_for(|_path| {
+                selected.fetch_add(1, Ordering::Relaxed);
+                encoding_rs::WINDOWS_1252
+            })
+            .run(
+                [dir.join("logs.tar.zst"), dir.join("plain.log.zst")],
+                |_line, _path| {
+                    lines.fetch_add(1, Ordering::Relaxed);
+                },
+            );
+        assert_eq!(lines.into_inner(), 7);
+        assert_eq!(selected.into_inner(), 3);
+    }
+}
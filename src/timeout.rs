@@ -0,0 +1,150 @@
+//! Abandoning files whose reads stall, e.g. on a dying disk, see ``ZstdLinesConfig::file_timeout()``.
+
+use crate::error::stalled_error;
+use crate::pipe::PipeReader;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::mem;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// A compressed file opened for processing, read directly, as a pipe which may be reopened, or through a reader thread.
+pub(crate) enum InputFile {
+    Direct(File),
+    /// A FIFO read across end-of-file, which cannot be seeked.
+    Pipe(PipeReader),
+    Watched(WatchedFile),
+}
+
+impl InputFile {
+    /// Read the file through a reader thread which is given up on once a read takes longer than `timeout`, if there is one.
+    pub(crate) fn watched(self, timeout: Option<Duration>) -> io::Result<Self> {
+        match timeout {
+            Some(timeout) => WatchedFile::new(self, timeout).map(InputFile::Watched),
+            None => Ok(self),
+        }
+    }
+}
+
+impl Read for InputFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            InputFile::Direct(file) => file.read(buf),
+            InputFile::Pipe(pipe) => pipe.read(buf),
+            InputFile::Watched(file) => file.read(buf),
+        }
+    }
+}
+
+impl Seek for InputFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            InputFile::Direct(file) => file.seek(pos),
+            InputFile::Pipe(_) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "pipes cannot be seeked",
+            )),
+            InputFile::Watched(file) => file.seek(pos),
+        }
+    }
+}
+
+enum Request {
+    Read(Vec<u8>),
+    Seek(SeekFrom),
+}
+
+enum Response {
+    Read(Vec<u8>, io::Result<usize>),
+    Seek(io::Result<u64>),
+}
+
+/// A file whose reads and seeks are made by a thread of its own, while the thread processing the file waits for them
+/// at most for the timeout. A read which takes longer cannot be interrupted, so the reader thread is left behind:
+/// it ends and closes the file whenever the read returns.
+pub(crate) struct WatchedFile {
+    requests: Sender<Request>,
+    responses: Receiver<Response>,
+    timeout: Duration,
+    /// Number of bytes read from the file so far.
+    bytes_read: u64,
+    /// Passed to the reader thread for every read, so it is only allocated once.
+    buffer: Vec<u8>,
+    /// A read or seek took longer than the timeout, the reader thread may still be waiting for it.
+    stalled: bool,
+}
+
+impl WatchedFile {
+    fn new(mut file: InputFile, timeout: Duration) -> io::Result<Self> {
+        let (requests, received) = mpsc::channel();
+        let (respond, responses) = mpsc::channel();
+        thread::Builder::new()
+            .name("zstd_lines_reader".to_string())
+            .spawn(move || {
+                for request in received {
+                    let response = match request {
+                        Request::Read(mut buffer) => {
+                            let result = file.read(&mut buffer);
+                            Response::Read(buffer, result)
+                        }
+                        Request::Seek(pos) => Response::Seek(file.seek(pos)),
+                    };
+                    // The file was given up on while the request was made
+                    if respond.send(response).is_err() {
+                        break;
+                    }
+                }
+            })?;
+        Ok(WatchedFile {
+            requests,
+            responses,
+            timeout,
+            bytes_read: 0,
+            buffer: Vec::new(),
+            stalled: false,
+        })
+    }
+
+    /// Pass a request to the reader thread and wait for its response, failing once it takes longer than the timeout.
+    fn request(&mut self, request: Request) -> io::Result<Response> {
+        if self.stalled {
+            return Err(stalled_error(self.bytes_read, self.timeout));
+        }
+        // Sending fails if the reader thread panicked, so does receiving
+        let _ = self.requests.send(request);
+        match self.responses.recv_timeout(self.timeout) {
+            Ok(response) => Ok(response),
+            Err(RecvTimeoutError::Timeout) => {
+                self.stalled = true;
+                Err(stalled_error(self.bytes_read, self.timeout))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(io::Error::other("reader thread panicked")),
+        }
+    }
+}
+
+impl Read for WatchedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut buffer = mem::take(&mut self.buffer);
+        buffer.resize(buf.len(), 0);
+        let Response::Read(buffer, result) = self.request(Request::Read(buffer))? else {
+            unreachable!()
+        };
+        let result = result.inspect(|&read| buf[..read].copy_from_slice(&buffer[..read]));
+        // The buffer is kept for the next read, also when this one failed
+        self.buffer = buffer;
+        let read = result?;
+        self.bytes_read += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for WatchedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let Response::Seek(result) = self.request(Request::Seek(pos))? else {
+            unreachable!()
+        };
+        result
+    }
+}
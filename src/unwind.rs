@@ -0,0 +1,99 @@
+//! Keeping a panicking line handler from taking down the whole batch.
+
+use crate::config::DEFAULT_CONFIG;
+use crate::diagnostics;
+use crate::process_file;
+use crate::text::decoded_sync;
+use rayon::prelude::*;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+/// A file the line handler panicked on, returned by ``par_zstd_lines_catch_unwind()``.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePanic {
+    /// The file the line handler panicked on.
+    pub path: PathBuf,
+    /// The message the handler panicked with, if it panicked with a string.
+    pub message: String,
+}
+
+/// Like ``par_zstd_lines()``, but a panic in the line handler only abandons the file it happened in.
+/// Every file is processed inside ``catch_unwind()``, the remaining files are still processed, and the files which panicked
+/// are reported on stderr at the end, after failures of the other files, and returned in the order of the paths.
+///
+/// The line handler does not have to be ``UnwindSafe``, it is wrapped in ``AssertUnwindSafe`` instead. This is only as safe as
+/// the handler makes it: whatever it shares between lines may be left as it was when the panic happened, e.g. half of a record
+/// pushed into a collection. A ``Mutex`` which is locked while panicking is poisoned, so lock it with
+/// ``lock().unwrap_or_else(PoisonError::into_inner)`` if other files should still be able to use it.
+/// The panic hook still runs for every panic, by default it prints the message on stderr as it happens.
+///
+/// # Arguments
+///
+/// * `paths` - Anything that can be turned into a parallel iterator over ``AsRef<Path>`` items.
+/// * `line_handler` - A function or closure that will handle each decompressed line.
+///
+/// # Example
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// # let dir = std::env::temp_dir().join("zstd_lines_doc_catch_unwind");
+/// # std::fs::create_dir_all(&dir).unwrap();
+/// std::fs::write(dir.join("good.zst"), zstd::encode_all(&b"1\n2\n3\n"[..], 0).unwrap()).unwrap();
+/// std::fs::write(dir.join("bad.zst"), zstd::encode_all(&b"4\nfive\n6\n"[..], 0).unwrap()).unwrap();
+///
+/// let sum = AtomicUsize::new(0);
+/// let panics = zstd_lines::par_zstd_lines_catch_unwind([dir.join("good.zst"), dir.join("bad.zst")], |line, _path| {
+///     let number: usize = line.parse().expect("not a number");
+///     sum.fetch_add(number, Ordering::Relaxed);
+/// });
+/// assert_eq!(panics.len(), 1);
+/// assert_eq!(panics[0].path, dir.join("bad.zst"));
+/// assert!(panics[0].message.starts_with("not a number"));
+/// // The lines in front of the panic were handled
+/// assert_eq!(sum.into_inner(), 1 + 2 + 3 + 4);
+/// # std::fs::remove_dir_all(&dir).unwrap();
+/// ```
+pub fn par_zstd_lines_catch_unwind<I, F>(paths: I, line_handler: F) -> Vec<FilePanic>
+where
+    I: IntoParallelIterator,
+    I::Item: AsRef<Path>,
+    F: Fn(String, &Path) + Sync + Send,
+{
+    let config = DEFAULT_CONFIG.start_run();
+    let line_handler = decoded_sync(&config, |line, context| line_handler(line, context.path));
+    let panics: Vec<FilePanic> = paths
+        .into_par_iter()
+        .filter_map(|path| {
+            let path = path.as_ref();
+            panic::catch_unwind(AssertUnwindSafe(|| {
+                process_file(path, &config, &line_handler);
+            }))
+            .err()
+            .map(|payload| FilePanic {
+                path: path.to_path_buf(),
+                message: panic_message(&*payload),
+            })
+        })
+        .collect();
+    for panic in &panics {
+        let message = format!(
+            "Line handler panicked while processing {}: {}",
+            panic.path.display(),
+            panic.message
+        );
+        diagnostics::report_message(&panic.path, &message);
+    }
+    panics
+}
+
+/// Get the message out of a panic payload, which is a string for panics raised with a message.
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
@@ -0,0 +1,125 @@
+//! Splitting the members of zip archives into lines, available with the `zip` feature.
+
+use crate::config::Run;
+use crate::error::ZstdLinesError;
+use crate::format::decompress_entry;
+use crate::{entry_error, is_regular_file, read_up_to};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use zip::ZipArchive;
+
+/// Signature of a local file header, which starts every zip archive with at least one member.
+const LOCAL_HEADER_MAGIC: [u8; 4] = *b"PK\x03\x04";
+/// Signature of the end of central directory record, which starts an empty zip archive.
+const EMPTY_ARCHIVE_MAGIC: [u8; 4] = *b"PK\x05\x06";
+
+/// Check whether a file is a zip archive by looking at its first bytes.
+pub(crate) fn is_zip_file(path: &Path) -> bool {
+    // Reading the head of a FIFO would take it from the stream
+    if !is_regular_file(path) {
+        return false;
+    }
+    let mut head = [0; 4];
+    File::open(path)
+        .and_then(|mut file| read_up_to(&mut file, &mut head))
+        .is_ok_and(|len| {
+            len == head.len() && (head == LOCAL_HEADER_MAGIC || head == EMPTY_ARCHIVE_MAGIC)
+        })
+}
+
+/// Pass the content of every file in a zip archive to the stream handler along with the member name.
+/// Members are read from the file one after another using the central directory, the archive is never held in memory.
+/// Stored and deflated members are supported, members which are zstd compressed themselves are decompressed as well.
+/// Members which cannot be read, like encrypted ones, are reported and skipped.
+pub(crate) fn process_zip_file<S>(
+    path: &Path,
+    config: &Run<'_>,
+    mut stream_handler: S,
+) -> io::Result<()>
+where
+    S: FnMut(&mut dyn BufRead, Option<&str>) -> io::Result<()>,
+{
+    let mut archive = ZipArchive::new(BufReader::new(config.open_input(path)?))?;
+    for index in 0..archive.len() {
+        // The raw member only gives access to the metadata, nothing is decompressed yet
+        let member = archive.by_index_raw(index)?;
+        let name = member.name().to_owned();
+        if !member.is_file() || member.size() == 0 {
+            continue;
+        }
+        if member.encrypted() {
+            config.report(&ZstdLinesError::SkippedMember {
+                path: path.to_path_buf(),
+                member: name,
+                reason: "encrypted members are not supported".to_string(),
+            });
+            continue;
+        }
+        if let Some(filter) = &config.entry_filter {
+            if !filter(&name, member.size()) {
+                continue;
+            }
+        }
+        drop(member);
+
+        let member = match archive.by_index(index) {
+            Ok(member) => member,
+            Err(e) => {
+                config.report(&ZstdLinesError::SkippedMember {
+                    path: path.to_path_buf(),
+                    member: name,
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let mut reader = config.buffered(decompress_entry(member, path, config)?);
+        stream_handler(&mut reader, Some(&name))
+            .map_err(|e| entry_error("zip member", &name, e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{entry_lines, test_dir};
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    fn stored_and_deflated_members_are_read_and_encrypted_ones_skipped() {
+        let dir = test_dir("zip");
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let stored =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let deflated =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        zip.start_file("stored.jsonl", stored).unwrap();
+        zip.write_all(b"{\"a\":1}\n{\"a\":2}\n").unwrap();
+        zip.add_directory("nested/", stored).unwrap();
+        zip.start_file("nested/deflated.jsonl", deflated).unwrap();
+        zip.write_all(b"{\"b\":1}\n").unwrap();
+        zip.start_file("secret.jsonl", stored).unwrap();
+        zip.write_all(b"{\"c\":1}\n").unwrap();
+        let mut data = zip.finish().unwrap().into_inner();
+        // Set the encryption flag of the last member in both of its headers
+        for (signature, flags_offset) in [(b"PK\x03\x04", 6), (b"PK\x01\x02", 8)] {
+            let start = data
+                .windows(4)
+                .rposition(|window| window == signature)
+                .unwrap();
+            data[start + flags_offset] |= 1;
+        }
+        std::fs::write(dir.join("export.zip"), data).unwrap();
+
+        assert_eq!(
+            entry_lines(&dir.join("export.zip")),
+            vec![
+                r#"stored.jsonl {"a":1}"#,
+                r#"stored.jsonl {"a":2}"#,
+                r#"nested/deflated.jsonl {"b":1}"#,
+            ]
+        );
+    }
+}